@@ -0,0 +1,39 @@
+pub mod gltf;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+pub mod vox;
+
+use std::fmt;
+
+// Errors from the model loaders. Parse errors carry the 1-based line number
+// (or 0 when the problem isn't tied to a line, e.g. a truncated binary file).
+#[derive(Debug)]
+pub enum FormatError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl FormatError {
+    pub fn parse(line: usize, message: impl Into<String>) -> Self {
+        FormatError::Parse { line, message: message.into() }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Io(error) => write!(f, "i/o error: {}", error),
+            FormatError::Parse { line: 0, message } => write!(f, "{}", message),
+            FormatError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+impl From<std::io::Error> for FormatError {
+    fn from(error: std::io::Error) -> Self {
+        FormatError::Io(error)
+    }
+}
@@ -0,0 +1,668 @@
+use crate::color::Color;
+use crate::formats::FormatError;
+use crate::matrix::Mat4;
+use crate::raytracing::camera::Camera;
+use crate::raytracing::hittable::HittableList;
+use crate::raytracing::implicits::triangle_mesh::TriangleMesh;
+use crate::raytracing::material::*;
+use crate::raytracing::texture::{ImageTexture, Texture};
+use crate::vector::{Quat, Vec3};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// glTF 2.0 loader for .gltf (external or data: URI buffers) and .glb files.
+// Every node that references a mesh becomes one TriangleMesh in world space,
+// and every perspective camera node becomes a GltfCamera.
+// Not supported: sparse accessors, skins, morph targets, animations, orthographic cameras.
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+// Deeper hierarchies are refused rather than risking the stack
+const MAX_NODE_DEPTH: usize = 1024;
+
+#[derive(Clone, Debug)]
+pub struct GltfCamera {
+    pub name: String,
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub up: Vec3,
+    // glTF stores the vertical angle, Camera::field_of_view is horizontal
+    pub vertical_field_of_view: f64,
+    pub aspect_ratio: Option<f64>,
+}
+
+impl GltfCamera {
+    // Keeps the camera's own aspect ratio, since that comes from the image size
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.look_from = self.look_from;
+        camera.look_at = self.look_at;
+        camera.up = self.up;
+        let half_height = (self.vertical_field_of_view.to_radians() / 2.0).tan();
+        camera.field_of_view = (2.0 * (half_height * camera.aspect_ratio).atan()).to_degrees();
+        camera.focus_dist = (self.look_at - self.look_from).length();
+    }
+}
+
+pub struct GltfScene {
+    pub objects: HittableList,
+    pub cameras: Vec<GltfCamera>,
+    pub triangle_count: usize,
+    // Things that were skipped or replaced while loading, for the caller to report
+    pub warnings: Vec<String>,
+}
+
+pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<GltfScene, FormatError> {
+    let path = path.as_ref();
+    let data = std::fs::read(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    if data.starts_with(GLB_MAGIC) {
+        parse_glb(&data, base_dir)
+    } else {
+        parse_gltf(&data, None, base_dir)
+    }
+}
+
+pub fn parse_glb(data: &[u8], base_dir: &Path) -> Result<GltfScene, FormatError> {
+    let read_u32 = |offset: usize| -> Result<u32, FormatError> {
+        data.get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| FormatError::parse(0, format!("GLB file ended early at byte {}", offset)))
+    };
+    if !data.starts_with(GLB_MAGIC) {
+        return Err(FormatError::parse(0, "missing glTF magic number, not a GLB file"));
+    }
+    let version = read_u32(4)?;
+    if version != 2 {
+        return Err(FormatError::parse(0, format!("GLB version {} is not supported, only 2", version)));
+    }
+    let length = read_u32(8)? as usize;
+    if length > data.len() {
+        return Err(FormatError::parse(0, format!("GLB header says {} bytes but the file is {} bytes", length, data.len())));
+    }
+
+    let mut json = None;
+    let mut binary = None;
+    let mut offset = 12;
+    while offset < length {
+        let chunk_length = read_u32(offset)? as usize;
+        let chunk_type = read_u32(offset + 4)?;
+        let start = offset + 8;
+        let chunk = data.get(start..start + chunk_length)
+            .ok_or_else(|| FormatError::parse(0, "GLB chunk runs past the end of the file"))?;
+        match chunk_type {
+            GLB_CHUNK_JSON if json.is_none() => json = Some(chunk),
+            GLB_CHUNK_BIN if binary.is_none() => binary = Some(chunk),
+            _ => {}
+        }
+        // chunks are 4 byte aligned
+        offset = start + chunk_length.div_ceil(4) * 4;
+    }
+    let json = json.ok_or_else(|| FormatError::parse(0, "GLB file has no JSON chunk"))?;
+    parse_gltf(json, binary, base_dir)
+}
+
+// glb_binary is the BIN chunk of a .glb, used by the buffer without a uri
+pub fn parse_gltf(json: &[u8], glb_binary: Option<&[u8]>, base_dir: &Path) -> Result<GltfScene, FormatError> {
+    let json: Value = serde_json::from_slice(json)
+        .map_err(|e| FormatError::parse(e.line(), format!("invalid glTF JSON: {}", e)))?;
+    let version = json["asset"]["version"].as_str().unwrap_or("");
+    if !version.starts_with('2') {
+        return Err(FormatError::parse(0, format!("glTF version '{}' is not supported, only 2.x", version)));
+    }
+
+    let mut document = Document {
+        buffers: vec![],
+        textures: vec![],
+        materials: vec![],
+        warnings: vec![],
+        base_dir: base_dir.to_path_buf(),
+        json,
+    };
+    document.load_buffers(glb_binary)?;
+    document.load_textures();
+    document.load_materials();
+
+    let mut scene = GltfScene {
+        objects: HittableList::new(),
+        cameras: vec![],
+        triangle_count: 0,
+        warnings: std::mem::take(&mut document.warnings),
+    };
+    // Nodes form trees, so every node is visited once. Anything else would repeat work for every
+    // path into a shared node.
+    let mut visited = vec![false; array(&document.json, "nodes").len()];
+    for root in document.root_nodes()? {
+        document.add_node(root, Mat4::IDENTITY, 0, &mut visited, &mut scene)?;
+    }
+    Ok(scene)
+}
+
+struct Document {
+    json: Value,
+    buffers: Vec<Vec<u8>>,
+    // Per glTF texture, None if the image couldn't be loaded
+    textures: Vec<Option<Arc<dyn Texture>>>,
+    // One per glTF material, plus the default material at the end
+    materials: Vec<Arc<dyn Material>>,
+    warnings: Vec<String>,
+    base_dir: PathBuf,
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value[key].as_array().map(|a| a.as_slice()).unwrap_or(&[])
+}
+
+fn index(value: &Value, key: &str) -> Option<usize> {
+    value[key].as_u64().map(|i| i as usize)
+}
+
+fn number(value: &Value, key: &str, default: f64) -> f64 {
+    value[key].as_f64().unwrap_or(default)
+}
+
+fn numbers<const N: usize>(value: &Value, key: &str, default: [f64; N]) -> [f64; N] {
+    let mut result = default;
+    if let Some(values) = value[key].as_array() {
+        for (out, value) in result.iter_mut().zip(values) {
+            *out = value.as_f64().unwrap_or(*out);
+        }
+    }
+    result
+}
+
+impl Document {
+    fn load_buffers(&mut self, glb_binary: Option<&[u8]>) -> Result<(), FormatError> {
+        for (i, buffer) in array(&self.json, "buffers").iter().enumerate() {
+            let bytes = match buffer["uri"].as_str() {
+                Some(uri) => self.read_uri(uri)?,
+                None => glb_binary
+                    .ok_or_else(|| FormatError::parse(0, format!("buffer {} has no uri and there is no GLB binary chunk", i)))?
+                    .to_vec(),
+            };
+            let byte_length = index(buffer, "byteLength").unwrap_or(0);
+            if bytes.len() < byte_length {
+                return Err(FormatError::parse(0, format!("buffer {} should be {} bytes but only {} were read", i, byte_length, bytes.len())));
+            }
+            self.buffers.push(bytes);
+        }
+        Ok(())
+    }
+
+    fn read_uri(&self, uri: &str) -> Result<Vec<u8>, FormatError> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let (header, payload) = data.split_once(',')
+                .ok_or_else(|| FormatError::parse(0, "malformed data URI"))?;
+            if !header.ends_with(";base64") {
+                return Err(FormatError::parse(0, "only base64 data URIs are supported"));
+            }
+            return decode_base64(payload).ok_or_else(|| FormatError::parse(0, "invalid base64 in data URI"));
+        }
+        Ok(std::fs::read(self.base_dir.join(percent_decode(uri)))?)
+    }
+
+    fn buffer_view(&self, view_index: usize) -> Result<(&[u8], Option<usize>), FormatError> {
+        let view = self.json["bufferViews"].get(view_index)
+            .ok_or_else(|| FormatError::parse(0, format!("missing bufferView {}", view_index)))?;
+        let buffer_index = index(view, "buffer").unwrap_or(0);
+        let buffer = self.buffers.get(buffer_index)
+            .ok_or_else(|| FormatError::parse(0, format!("bufferView {} refers to missing buffer {}", view_index, buffer_index)))?;
+        let offset = index(view, "byteOffset").unwrap_or(0);
+        let length = index(view, "byteLength").unwrap_or(0);
+        let bytes = offset.checked_add(length)
+            .and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| FormatError::parse(0, format!("bufferView {} runs past the end of buffer {}", view_index, buffer_index)))?;
+        Ok((bytes, index(view, "byteStride")))
+    }
+
+    // Returns the accessor's elements flattened, and the number of components per element.
+    // Normalized integers come back in 0..1 (or -1..1). max_count is the element count other data
+    // already fixed (the vertex count for vertex attributes), the bound for accessors with no bufferView.
+    fn read_accessor(&self, accessor_index: usize, max_count: Option<usize>) -> Result<(Vec<f64>, usize), FormatError> {
+        let accessor = self.json["accessors"].get(accessor_index)
+            .ok_or_else(|| FormatError::parse(0, format!("missing accessor {}", accessor_index)))?;
+        let error = |message: &str| FormatError::parse(0, format!("accessor {}: {}", accessor_index, message));
+        if !accessor["sparse"].is_null() {
+            return Err(error("sparse accessors are not supported"));
+        }
+        let count = index(accessor, "count").ok_or_else(|| error("missing count"))?;
+        let components = match accessor["type"].as_str() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return Err(error("unknown type")),
+        };
+        let component_type = accessor["componentType"].as_u64().unwrap_or(0);
+        let component_size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(error(&format!("unknown componentType {}", component_type))),
+        };
+        let normalized = accessor["normalized"].as_bool().unwrap_or(false);
+
+        let view_index = match index(accessor, "bufferView") {
+            Some(view_index) => view_index,
+            // No buffer view means all zeros, but nothing in the file backs the count then
+            None => {
+                let length = max_count
+                    .filter(|&max_count| count <= max_count)
+                    .and_then(|_| count.checked_mul(components))
+                    .ok_or_else(|| error(&format!("count {} has no bufferView to back it", count)))?;
+                return Ok((vec![0.0; length], components));
+            }
+        };
+        let (bytes, stride) = self.buffer_view(view_index)?;
+        let element_size = components * component_size;
+        let stride = stride.unwrap_or(element_size);
+        if stride < element_size {
+            return Err(error(&format!("byteStride {} is smaller than an element", stride)));
+        }
+        let offset = index(accessor, "byteOffset").unwrap_or(0);
+        let end = match count {
+            0 => Some(0),
+            _ => stride.checked_mul(count - 1)
+                .and_then(|length| length.checked_add(offset))
+                .and_then(|length| length.checked_add(element_size)),
+        };
+        if end.is_none_or(|end| end > bytes.len()) {
+            return Err(error("data runs past the end of its bufferView"));
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let at = offset + element * stride + component * component_size;
+                let b = &bytes[at..at + component_size];
+                let value = match component_type {
+                    5120 => if normalized { (b[0] as i8 as f64 / 127.0).max(-1.0) } else { b[0] as i8 as f64 },
+                    5121 => if normalized { b[0] as f64 / 255.0 } else { b[0] as f64 },
+                    5122 => {
+                        let value = i16::from_le_bytes([b[0], b[1]]) as f64;
+                        if normalized { (value / 32767.0).max(-1.0) } else { value }
+                    }
+                    5123 => {
+                        let value = u16::from_le_bytes([b[0], b[1]]) as f64;
+                        if normalized { value / 65535.0 } else { value }
+                    }
+                    5125 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                };
+                values.push(value);
+            }
+        }
+        Ok((values, components))
+    }
+
+    fn load_textures(&mut self) {
+        let mut warnings = vec![];
+        let images: Vec<Option<Arc<dyn Texture>>> = array(&self.json, "images").iter().enumerate()
+            .map(|(i, image)| {
+                let bytes = match (image["uri"].as_str(), index(image, "bufferView")) {
+                    (Some(uri), _) => self.read_uri(uri).ok(),
+                    (None, Some(view)) => self.buffer_view(view).ok().map(|(bytes, _)| bytes.to_vec()),
+                    _ => None,
+                };
+                let texture = bytes.and_then(|bytes| ImageTexture::from_memory(&bytes));
+                if texture.is_none() {
+                    warnings.push(format!("Could not load glTF image {}, using the base color instead", i));
+                }
+                texture.map(|t| Arc::new(t) as Arc<dyn Texture>)
+            })
+            .collect();
+        self.warnings.extend(warnings);
+        self.textures = array(&self.json, "textures").iter()
+            .map(|texture| index(texture, "source").and_then(|source| images.get(source).cloned().flatten()))
+            .collect();
+    }
+
+    // Picks the closest material we have, in the same spirit as the OBJ loader:
+    // emissive -> DiffuseLight, transmissive -> Dielectric, metallic -> Metal, otherwise Lambertian.
+    fn load_materials(&mut self) {
+        let max_component = |c: Color| f64::max(c.x, f64::max(c.y, c.z));
+        let mut materials: Vec<Arc<dyn Material>> = vec![];
+        for material in array(&self.json, "materials") {
+            let pbr = &material["pbrMetallicRoughness"];
+            let [r, g, b, alpha] = numbers(pbr, "baseColorFactor", [1.0, 1.0, 1.0, 1.0]);
+            let base_color = Color::new(r, g, b);
+            let metallic = number(pbr, "metallicFactor", 1.0);
+            let roughness = number(pbr, "roughnessFactor", 1.0);
+            let extensions = &material["extensions"];
+            let [er, eg, eb] = numbers(material, "emissiveFactor", [0.0, 0.0, 0.0]);
+            let emissive_strength = number(&extensions["KHR_materials_emissive_strength"], "emissiveStrength", 1.0);
+            let emission = emissive_strength * Color::new(er, eg, eb);
+            let transmission = number(&extensions["KHR_materials_transmission"], "transmissionFactor", 0.0);
+            let refraction_index = number(&extensions["KHR_materials_ior"], "ior", 1.5);
+            let blended = material["alphaMode"].as_str() == Some("BLEND") && alpha < 1.0;
+            // The base color factor is dropped when there is a texture, there's no texture multiply yet
+            let base_texture = index(&pbr["baseColorTexture"], "index")
+                .and_then(|texture| self.textures.get(texture).cloned().flatten());
+
+            let converted: Arc<dyn Material> = if max_component(emission) > 0.0 {
+                Arc::new(DiffuseLight::new(emission))
+            } else if transmission > 0.0 || blended {
+                Arc::new(Dielectric::new(refraction_index))
+            } else if metallic >= 0.5 {
+                Arc::new(Metal::new(base_color, roughness))
+            } else if let Some(texture) = base_texture {
+                Arc::new(Lambertian::from_texture(texture))
+            } else {
+                Arc::new(Lambertian::new(base_color))
+            };
+            materials.push(converted);
+        }
+        materials.push(Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))));
+        self.materials = materials;
+    }
+
+    fn root_nodes(&self) -> Result<Vec<usize>, FormatError> {
+        let scenes = array(&self.json, "scenes");
+        if !scenes.is_empty() {
+            let scene_index = index(&self.json, "scene").unwrap_or(0);
+            let scene = scenes.get(scene_index)
+                .ok_or_else(|| FormatError::parse(0, format!("default scene {} does not exist", scene_index)))?;
+            return Ok(array(scene, "nodes").iter().filter_map(|n| n.as_u64().map(|n| n as usize)).collect());
+        }
+        // No scenes: every node that isn't somebody's child
+        let nodes = array(&self.json, "nodes");
+        let mut is_child = vec![false; nodes.len()];
+        for node in nodes {
+            for child in array(node, "children").iter().filter_map(|c| c.as_u64()) {
+                if let Some(flag) = is_child.get_mut(child as usize) {
+                    *flag = true;
+                }
+            }
+        }
+        Ok((0..nodes.len()).filter(|&i| !is_child[i]).collect())
+    }
+
+    fn add_node(&self, node_index: usize, parent: Mat4, depth: usize, visited: &mut [bool], scene: &mut GltfScene) -> Result<(), FormatError> {
+        let nodes = array(&self.json, "nodes");
+        let node = nodes.get(node_index)
+            .ok_or_else(|| FormatError::parse(0, format!("missing node {}", node_index)))?;
+        if std::mem::replace(&mut visited[node_index], true) {
+            return Err(FormatError::parse(0, format!("node {} is reached twice, the node hierarchy is not a tree", node_index)));
+        }
+        if depth >= MAX_NODE_DEPTH {
+            return Err(FormatError::parse(0, format!("node hierarchy is more than {} levels deep", MAX_NODE_DEPTH)));
+        }
+        let world = parent * local_matrix(node);
+
+        if let Some(mesh) = index(node, "mesh") {
+            let triangle_mesh = self.build_mesh(mesh, &world, &mut scene.warnings)?;
+            if triangle_mesh.triangle_count() > 0 {
+                scene.triangle_count += triangle_mesh.triangle_count();
+                scene.objects.add(Arc::new(triangle_mesh));
+            }
+        }
+        if let Some(camera) = index(node, "camera") {
+            let camera_json = self.json["cameras"].get(camera)
+                .ok_or_else(|| FormatError::parse(0, format!("node {} refers to missing camera {}", node_index, camera)))?;
+            if camera_json["type"].as_str() == Some("perspective") {
+                let perspective = &camera_json["perspective"];
+                let look_from = world.transform_point(Vec3::new(0.0, 0.0, 0.0));
+                // glTF cameras look down -z with +y up
+                let forward = world.transform_vector(Vec3::new(0.0, 0.0, -1.0)).normalized();
+                scene.cameras.push(GltfCamera {
+                    name: camera_json["name"].as_str().or(node["name"].as_str()).unwrap_or("").to_string(),
+                    look_from,
+                    look_at: look_from + forward,
+                    up: world.transform_vector(Vec3::new(0.0, 1.0, 0.0)).normalized(),
+                    vertical_field_of_view: number(perspective, "yfov", 0.8).to_degrees(),
+                    aspect_ratio: perspective["aspectRatio"].as_f64(),
+                });
+            }
+        }
+        for child in array(node, "children").iter().filter_map(|c| c.as_u64()) {
+            self.add_node(child as usize, world, depth + 1, visited, scene)?;
+        }
+        Ok(())
+    }
+
+    // All primitives of a mesh instance go into one TriangleMesh
+    fn build_mesh(&self, mesh_index: usize, world: &Mat4, warnings: &mut Vec<String>) -> Result<TriangleMesh, FormatError> {
+        let mesh = self.json["meshes"].get(mesh_index)
+            .ok_or_else(|| FormatError::parse(0, format!("missing mesh {}", mesh_index)))?;
+        // A singular transform squashes the mesh flat, any normals will do there
+        let normal_matrix = world.normal_matrix().unwrap_or(Mat4::IDENTITY);
+        let default_material = self.materials.len() - 1;
+
+        let mut positions = vec![];
+        let mut normals = vec![];
+        let mut uvs = vec![];
+        let mut triangles: Vec<[u32; 3]> = vec![];
+        let mut face_materials = vec![];
+        let mut all_have_normals = true;
+        let mut all_have_uvs = true;
+        let primitives = array(mesh, "primitives");
+        for primitive in primitives {
+            let attributes = &primitive["attributes"];
+            if index(attributes, "NORMAL").is_none() {
+                all_have_normals = false;
+            }
+            if index(attributes, "TEXCOORD_0").is_none() {
+                all_have_uvs = false;
+            }
+        }
+
+        for (primitive_index, primitive) in primitives.iter().enumerate() {
+            let mode = index(primitive, "mode").unwrap_or(4);
+            if !matches!(mode, 4..=6) {
+                warnings.push(format!("Skipping glTF mesh {} primitive {}: mode {} is not triangles", mesh_index, primitive_index, mode));
+                continue;
+            }
+            let attributes = &primitive["attributes"];
+            let position_accessor = index(attributes, "POSITION")
+                .ok_or_else(|| FormatError::parse(0, format!("mesh {} primitive {} has no POSITION", mesh_index, primitive_index)))?;
+            let (primitive_positions, _) = self.read_accessor(position_accessor, None)?;
+            let vertex_count = primitive_positions.len() / 3;
+            let first_vertex = positions.len();
+            for p in primitive_positions.chunks_exact(3) {
+                positions.push(world.transform_point(Vec3::new(p[0], p[1], p[2])));
+            }
+            if all_have_normals && let Some(accessor) = index(attributes, "NORMAL") {
+                let (primitive_normals, _) = self.read_accessor(accessor, Some(vertex_count))?;
+                for n in primitive_normals.chunks_exact(3).take(vertex_count) {
+                    normals.push(normal_matrix.transform_vector(Vec3::new(n[0], n[1], n[2])).normalized());
+                }
+            }
+            if all_have_uvs && let Some(accessor) = index(attributes, "TEXCOORD_0") {
+                let (primitive_uvs, _) = self.read_accessor(accessor, Some(vertex_count))?;
+                // glTF puts v = 0 at the top of the image
+                uvs.extend(primitive_uvs.chunks_exact(2).take(vertex_count).map(|uv| (uv[0], 1.0 - uv[1])));
+            }
+            if normals.len() != positions.len() {
+                all_have_normals = false;
+                normals.clear();
+            }
+            if uvs.len() != positions.len() {
+                all_have_uvs = false;
+                uvs.clear();
+            }
+
+            let indices: Vec<usize> = match index(primitive, "indices") {
+                Some(accessor) => self.read_accessor(accessor, None)?.0.iter().map(|&i| i as usize).collect(),
+                None => (0..vertex_count).collect(),
+            };
+            if let Some(&bad) = indices.iter().find(|&&i| i >= vertex_count) {
+                return Err(FormatError::parse(0, format!("mesh {} primitive {} refers to vertex {} but there are only {}",
+                                                         mesh_index, primitive_index, bad, vertex_count)));
+            }
+            let material = index(primitive, "material").filter(|&m| m < default_material).unwrap_or(default_material);
+            let corner = |i: usize| (first_vertex + indices[i]) as u32;
+            let mut add_triangle = |a: u32, b: u32, c: u32| {
+                triangles.push([a, b, c]);
+                face_materials.push(material as u32);
+            };
+            match mode {
+                4 => (0..indices.len() / 3).for_each(|t| add_triangle(corner(3 * t), corner(3 * t + 1), corner(3 * t + 2))),
+                // strips alternate winding so every triangle faces the same way
+                5 => (0..indices.len().saturating_sub(2)).for_each(|t| if t % 2 == 0 {
+                    add_triangle(corner(t), corner(t + 1), corner(t + 2))
+                } else {
+                    add_triangle(corner(t + 1), corner(t), corner(t + 2))
+                }),
+                _ => (1..indices.len().saturating_sub(1)).for_each(|t| add_triangle(corner(0), corner(t), corner(t + 1))),
+            }
+        }
+        Ok(TriangleMesh::from_triangles(positions, normals, uvs, triangles, self.materials.clone(), face_materials))
+    }
+}
+
+fn local_matrix(node: &Value) -> Mat4 {
+    if node["matrix"].is_array() {
+        return Mat4::from_column_major(numbers(node, "matrix", [
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        ]));
+    }
+    let [tx, ty, tz] = numbers(node, "translation", [0.0, 0.0, 0.0]);
+    let [x, y, z, w] = numbers(node, "rotation", [0.0, 0.0, 0.0, 1.0]);
+    let [sx, sy, sz] = numbers(node, "scale", [1.0, 1.0, 1.0]);
+    let rotation = Quat::new(w, x, y, z);
+    // A zero quaternion can't be normalized, treat it as no rotation
+    let rotation = if rotation.length() > 0.0 { rotation.normalized() } else { Quat::IDENTITY };
+    Mat4::from_trs(Vec3::new(tx, ty, tz), rotation, Vec3::new(sx, sy, sz))
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut accumulator = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            b' ' | b'\n' | b'\r' | b'\t' => continue,
+            _ => return None,
+        };
+        accumulator = (accumulator << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((accumulator >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+// uris are URI-encoded, e.g. spaces become %20
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(value)) => {
+                decoded.push(value);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One triangle in the GLB binary chunk, extra JSON spliced into the top level object
+    fn triangle_gltf(extra: &str) -> (String, Vec<u8>) {
+        let binary: Vec<u8> = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let json = format!(r#"{{
+            "asset": {{"version": "2.0"}},
+            "buffers": [{{"byteLength": 36}}],
+            "bufferViews": [{{"buffer": 0, "byteLength": 36}}],
+            "accessors": [{{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}}],
+            "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}}}]}}]
+            {}
+        }}"#, extra);
+        (json, binary)
+    }
+
+    fn parse(extra: &str) -> Result<GltfScene, FormatError> {
+        let (json, binary) = triangle_gltf(extra);
+        parse_gltf(json.as_bytes(), Some(&binary), Path::new("."))
+    }
+
+    fn error_message(result: Result<GltfScene, FormatError>) -> String {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn loads_a_triangle() {
+        let scene = parse(r#", "nodes": [{"mesh": 0, "translation": [1, 2, 3]}]"#).unwrap();
+        assert_eq!(scene.triangle_count, 1);
+        assert!(scene.warnings.is_empty());
+    }
+
+    #[test]
+    fn rejects_a_buffer_view_offset_that_overflows() {
+        let (json, binary) = triangle_gltf(r#", "nodes": [{"mesh": 0}]"#);
+        let json = json.replace(r#""buffer": 0, "byteLength": 36"#, r#""buffer": 0, "byteOffset": 18446744073709551615, "byteLength": 36"#);
+        let result = parse_gltf(json.as_bytes(), Some(&binary), Path::new("."));
+        assert!(error_message(result).contains("runs past the end"));
+    }
+
+    #[test]
+    fn rejects_an_accessor_count_without_a_buffer_view() {
+        let (json, binary) = triangle_gltf(r#", "nodes": [{"mesh": 0}]"#);
+        let json = json.replace(r#""bufferView": 0, "componentType": 5126, "count": 3"#, r#""componentType": 5126, "count": 4611686018427387904"#);
+        let result = parse_gltf(json.as_bytes(), Some(&binary), Path::new("."));
+        assert!(error_message(result).contains("no bufferView"));
+    }
+
+    #[test]
+    fn rejects_shared_and_cyclic_nodes() {
+        // Every node lists the next two as children, which is exponential if followed naively
+        let nodes: Vec<String> = (0..64)
+            .map(|i| if i < 62 { format!(r#"{{"children": [{}, {}]}}"#, i + 1, i + 2) } else { r#"{"mesh": 0}"#.to_string() })
+            .collect();
+        let shared = parse(&format!(r#", "nodes": [{}], "scenes": [{{"nodes": [0]}}]"#, nodes.join(", ")));
+        assert!(error_message(shared).contains("reached twice"));
+
+        let cycle = parse(r#", "nodes": [{"children": [1]}, {"children": [0]}], "scenes": [{"nodes": [0]}]"#);
+        assert!(error_message(cycle).contains("reached twice"));
+    }
+
+    #[test]
+    fn skipped_primitives_become_warnings() {
+        let (json, binary) = triangle_gltf(r#", "nodes": [{"mesh": 0}]"#);
+        let json = json.replace(r#""attributes": {"POSITION": 0}"#, r#""attributes": {"POSITION": 0}, "mode": 1"#);
+        let scene = parse_gltf(json.as_bytes(), Some(&binary), Path::new(".")).unwrap();
+        assert_eq!(scene.triangle_count, 0);
+        assert_eq!(scene.warnings.len(), 1);
+    }
+    #[test]
+    fn node_rotation_matches_mat4_rotation() {
+        let half = 45.0_f64.to_radians();
+        // Not unit length, the loader normalizes it
+        let node: Value = serde_json::from_str(&format!(
+            r#"{{"translation": [1, 2, 3], "rotation": [0, {}, 0, {}], "scale": [2, 2, 2]}}"#, 2.0 * half.sin(), 2.0 * half.cos())).unwrap();
+        let expected = Mat4::translation(Vec3::new(1.0, 2.0, 3.0))
+            * Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), 90.0_f64.to_radians())
+            * Mat4::scale(Vec3::new(2.0, 2.0, 2.0));
+        let point = Vec3::new(0.3, -0.7, 1.1);
+        assert!((local_matrix(&node).transform_point(point) - expected.transform_point(point)).length() < 1e-12);
+    }
+}
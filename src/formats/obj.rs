@@ -0,0 +1,445 @@
+use crate::color::Color;
+use crate::formats::FormatError;
+use crate::mesh::Mesh;
+use crate::raytracing::implicits::triangle_mesh::TriangleMesh;
+use crate::raytracing::material::*;
+use crate::raytracing::texture::ImageTexture;
+use crate::vector::Vec3;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Wavefront OBJ + MTL loader.
+// Polygons are fan-triangulated. Faces without normals get smooth normals within
+// their smoothing group, or flat normals with `s off`.
+
+#[derive(Clone, Debug)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub diffuse: Color,     // Kd
+    pub specular: Color,    // Ks
+    pub shininess: f64,     // Ns
+    pub refraction_index: f64, // Ni
+    pub dissolve: f64,      // d (or 1 - Tr)
+    pub emission: Color,    // Ke
+    pub illumination: i32,  // illum
+    pub diffuse_map: Option<PathBuf>, // map_Kd, relative to the .mtl
+}
+
+impl ObjMaterial {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            refraction_index: 1.5,
+            dissolve: 1.0,
+            emission: Color::new(0.0, 0.0, 0.0),
+            illumination: 2,
+            diffuse_map: None,
+        }
+    }
+
+    // Picks the closest material we have:
+    // emissive -> DiffuseLight, transparent -> Dielectric, mirror-like -> Metal, otherwise Lambertian.
+    pub fn to_material(&self) -> Arc<dyn Material> {
+        let max_component = |c: Color| f64::max(c.x, f64::max(c.y, c.z));
+        if max_component(self.emission) > 0.0 {
+            return Arc::new(DiffuseLight::new(self.emission));
+        }
+        if self.dissolve < 1.0 || matches!(self.illumination, 4 | 6 | 7 | 9) {
+            return Arc::new(Dielectric::new(self.refraction_index));
+        }
+        let reflective_illum = matches!(self.illumination, 3 | 5 | 8);
+        if reflective_illum || (max_component(self.diffuse) < 0.01 && max_component(self.specular) > 0.0) {
+            // Phong exponent to roughness
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt();
+            return Arc::new(Metal::new(self.specular, fuzz));
+        }
+        if let Some(map) = &self.diffuse_map {
+            match ImageTexture::try_new(map) {
+                Some(texture) => return Arc::new(Lambertian::from_texture(Arc::new(texture))),
+                None => println!("Could not load texture {}, using Kd instead", map.display()),
+            }
+        }
+        Arc::new(Lambertian::new(self.diffuse))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ObjGroup {
+    pub name: String,
+    pub object: String,
+    // Range of triangles in ObjModel::mesh
+    pub first_triangle: usize,
+    pub triangle_count: usize,
+}
+
+pub struct ObjModel {
+    pub mesh: Mesh,
+    pub materials: Vec<ObjMaterial>,
+    // Index into materials for every triangle, None for faces without (or with an unknown) usemtl
+    pub face_materials: Vec<Option<usize>>,
+    pub groups: Vec<ObjGroup>,
+}
+
+impl ObjModel {
+    pub fn triangle_count(&self) -> usize {
+        self.mesh.indices.len() / 3
+    }
+
+    // Faces without a material use default_material
+    pub fn to_triangle_mesh(&self, default_material: Arc<dyn Material>) -> TriangleMesh {
+        let mut materials: Vec<Arc<dyn Material>> = self.materials.iter().map(|m| m.to_material()).collect();
+        let default_index = materials.len();
+        materials.push(default_material);
+        let face_materials = self.face_materials.iter().map(|m| m.unwrap_or(default_index)).collect();
+        TriangleMesh::with_face_materials(&self.mesh, materials, face_materials)
+    }
+}
+
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<ObjModel, FormatError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    parse_obj(&source, base_dir)
+}
+
+pub fn load_mtl<P: AsRef<Path>>(path: P) -> Result<Vec<ObjMaterial>, FormatError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    parse_mtl(&source, base_dir)
+}
+
+// Identifies one output vertex: position, uv and normal indices, plus the smoothing
+// key used when the normal has to be generated.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct VertexKey {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+    smoothing: i64,
+}
+
+// mtllib paths are resolved against base_dir
+pub fn parse_obj(source: &str, base_dir: &Path) -> Result<ObjModel, FormatError> {
+    let mut positions: Vec<Vec3> = vec![];
+    let mut tex_coords: Vec<(f64, f64)> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+
+    let mut keys: Vec<VertexKey> = vec![];
+    let mut key_lookup: HashMap<VertexKey, usize> = HashMap::new();
+    let mut indices: Vec<usize> = vec![];
+    let mut face_materials: Vec<Option<usize>> = vec![];
+    let mut groups: Vec<ObjGroup> = vec![];
+
+    let mut materials: Vec<ObjMaterial> = vec![];
+    let mut current_material: Option<usize> = None;
+    let mut smoothing_group: i64 = 0;
+    let mut object_name = String::new();
+    let mut group_name = String::from("default");
+    let mut face_count: i64 = 0;
+
+    for (line_index, raw_line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = raw_line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+        match keyword {
+            "v" => positions.push(parse_vec3(&arguments, line_number)?),
+            "vn" => normals.push(parse_vec3(&arguments, line_number)?),
+            "vt" => {
+                let u = parse_float(arguments.first(), line_number)?;
+                let v = if arguments.len() > 1 { parse_float(arguments.get(1), line_number)? } else { 0.0 };
+                tex_coords.push((u, v));
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(FormatError::parse(line_number, "face needs at least 3 vertices"));
+                }
+                face_count += 1;
+                let mut face_vertices = vec![];
+                for argument in &arguments {
+                    let mut parts = argument.split('/');
+                    let position = resolve_index(parts.next(), positions.len(), line_number, "vertex")?
+                        .ok_or_else(|| FormatError::parse(line_number, "face vertex without a position index"))?;
+                    let uv = resolve_index(parts.next(), tex_coords.len(), line_number, "texture coordinate")?;
+                    let normal = resolve_index(parts.next(), normals.len(), line_number, "normal")?;
+                    let smoothing = if normal.is_some() {
+                        0
+                    } else if smoothing_group > 0 {
+                        smoothing_group
+                    } else {
+                        -face_count
+                    };
+                    let key = VertexKey { position, uv, normal, smoothing };
+                    let index = *key_lookup.entry(key).or_insert_with(|| {
+                        keys.push(key);
+                        keys.len() - 1
+                    });
+                    face_vertices.push(index);
+                }
+                if groups.last().is_none_or(|g| g.name != group_name || g.object != object_name) {
+                    groups.push(ObjGroup {
+                        name: group_name.clone(),
+                        object: object_name.clone(),
+                        first_triangle: indices.len() / 3,
+                        triangle_count: 0,
+                    });
+                }
+                for i in 1..face_vertices.len() - 1 {
+                    indices.extend_from_slice(&[face_vertices[0], face_vertices[i], face_vertices[i + 1]]);
+                    face_materials.push(current_material);
+                    groups.last_mut().unwrap().triangle_count += 1;
+                }
+            }
+            "o" => object_name = arguments.join(" "),
+            "g" => group_name = if arguments.is_empty() { String::from("default") } else { arguments.join(" ") },
+            "s" => {
+                smoothing_group = match arguments.first() {
+                    Some(&"off") | None => 0,
+                    Some(group) => group.parse::<i64>()
+                        .map_err(|_| FormatError::parse(line_number, format!("bad smoothing group '{}'", group)))?,
+                };
+            }
+            "mtllib" => {
+                for library in &arguments {
+                    let library_path = base_dir.join(library);
+                    match load_mtl(&library_path) {
+                        Ok(loaded) => materials.extend(loaded),
+                        Err(FormatError::Io(error)) => {
+                            println!("Could not read material library {}: {}", library_path.display(), error);
+                        }
+                        Err(error) => return Err(error),
+                    }
+                }
+            }
+            "usemtl" => {
+                let name = arguments.join(" ");
+                current_material = materials.iter().position(|m| m.name == name);
+            }
+            // Curves, surfaces, lines and points aren't supported
+            _ => {}
+        }
+    }
+
+    let mut mesh_vertices = Vec::with_capacity(keys.len());
+    let mut mesh_normals = vec![Vec3::new(0.0, 0.0, 0.0); keys.len()];
+    let mut mesh_uvs = vec![];
+    let has_uvs = keys.iter().any(|k| k.uv.is_some());
+    for (i, key) in keys.iter().enumerate() {
+        mesh_vertices.push(positions[key.position]);
+        if let Some(normal) = key.normal {
+            mesh_normals[i] = normals[normal];
+        }
+        if has_uvs {
+            mesh_uvs.push(key.uv.map(|uv| tex_coords[uv]).unwrap_or((0.0, 0.0)));
+        }
+    }
+
+    // Area weighted face normals for vertices that didn't come with one
+    for triangle in indices.chunks_exact(3) {
+        let (a, b, c) = (mesh_vertices[triangle[0]], mesh_vertices[triangle[1]], mesh_vertices[triangle[2]]);
+        let face_normal = (b - a).cross(c - a);
+        for &vertex in triangle {
+            if keys[vertex].normal.is_none() {
+                mesh_normals[vertex] += face_normal;
+            }
+        }
+    }
+    for normal in mesh_normals.iter_mut() {
+        if normal.length_squared() > 0.0 {
+            *normal = normal.normalized();
+        }
+    }
+
+    let mut mesh = Mesh::new(mesh_vertices, mesh_normals, indices);
+    mesh.uvs = mesh_uvs;
+    Ok(ObjModel { mesh, materials, face_materials, groups })
+}
+
+// map_Kd paths are resolved against base_dir
+pub fn parse_mtl(source: &str, base_dir: &Path) -> Result<Vec<ObjMaterial>, FormatError> {
+    let mut materials: Vec<ObjMaterial> = vec![];
+    for (line_index, raw_line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = raw_line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+        if keyword == "newmtl" {
+            materials.push(ObjMaterial::new(&arguments.join(" ")));
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => return Err(FormatError::parse(line_number, format!("'{}' before any newmtl", keyword))),
+        };
+        match keyword {
+            "Kd" => material.diffuse = parse_vec3(&arguments, line_number)?,
+            "Ks" => material.specular = parse_vec3(&arguments, line_number)?,
+            "Ke" => material.emission = parse_vec3(&arguments, line_number)?,
+            "Ns" => material.shininess = parse_float(arguments.first(), line_number)?,
+            "Ni" => material.refraction_index = parse_float(arguments.first(), line_number)?,
+            "d" => material.dissolve = parse_float(arguments.last(), line_number)?,
+            "Tr" => material.dissolve = 1.0 - parse_float(arguments.first(), line_number)?,
+            "illum" => {
+                material.illumination = arguments.first()
+                    .and_then(|a| a.parse::<i32>().ok())
+                    .ok_or_else(|| FormatError::parse(line_number, "bad illum value"))?;
+            }
+            // options like -s or -bm come before the file name
+            "map_Kd" => match arguments.last() {
+                Some(file) => material.diffuse_map = Some(base_dir.join(file)),
+                None => return Err(FormatError::parse(line_number, "map_Kd without a file name")),
+            },
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+fn parse_float(token: Option<&&str>, line_number: usize) -> Result<f64, FormatError> {
+    let token = token.ok_or_else(|| FormatError::parse(line_number, "missing number"))?;
+    token.parse::<f64>().map_err(|_| FormatError::parse(line_number, format!("bad number '{}'", token)))
+}
+
+fn parse_vec3(arguments: &[&str], line_number: usize) -> Result<Vec3, FormatError> {
+    if arguments.len() < 3 {
+        return Err(FormatError::parse(line_number, "expected 3 numbers"));
+    }
+    Ok(Vec3::new(
+        parse_float(arguments.first(), line_number)?,
+        parse_float(arguments.get(1), line_number)?,
+        parse_float(arguments.get(2), line_number)?,
+    ))
+}
+
+// OBJ indices start at 1, negative ones count back from the latest element.
+// An empty token (as in "1//3") means the element isn't there.
+fn resolve_index(token: Option<&str>, count: usize, line_number: usize, what: &str) -> Result<Option<usize>, FormatError> {
+    let token = match token {
+        Some(token) if !token.is_empty() => token,
+        _ => return Ok(None),
+    };
+    let index = token.parse::<i64>()
+        .map_err(|_| FormatError::parse(line_number, format!("bad {} index '{}'", what, token)))?;
+    let resolved = if index > 0 { index - 1 } else { count as i64 + index };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(FormatError::parse(line_number, format!("{} index {} out of range", what, index)));
+    }
+    Ok(Some(resolved as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<ObjModel, FormatError> {
+        parse_obj(source, Path::new("."))
+    }
+
+    fn error_line(result: Result<ObjModel, FormatError>) -> usize {
+        match result {
+            Err(FormatError::Parse { line, .. }) => line,
+            Err(error) => panic!("not a parse error: {}", error),
+            Ok(_) => panic!("parsed"),
+        }
+    }
+
+    const QUAD: &str = "
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 1
+vn 0 0 1
+o thing
+g front
+f 1/1/1 2/1/1 3/2/1 4/2/1
+g back
+f -1 -2 -3
+";
+
+    #[test]
+    fn polygons_are_fan_triangulated() {
+        let model = parse(QUAD).unwrap();
+        assert_eq!(model.triangle_count(), 3);
+        assert_eq!(&model.mesh.indices[..6], &[0, 1, 2, 0, 2, 3]);
+        assert_eq!(model.groups.len(), 2);
+        assert_eq!((model.groups[0].name.as_str(), model.groups[0].object.as_str()), ("front", "thing"));
+        assert_eq!((model.groups[1].first_triangle, model.groups[1].triangle_count), (2, 1));
+        // Negative indices count back from the last vertex
+        let last = &model.mesh.indices[6..9];
+        assert!((model.mesh.vertices[last[0]] - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        assert!((model.mesh.vertices[last[2]] - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12);
+        assert_eq!(model.mesh.uvs.len(), model.mesh.vertices.len());
+        assert_eq!(model.face_materials, vec![None; 3]);
+    }
+
+    #[test]
+    fn smoothing_groups_share_vertices() {
+        let folded = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\n{}\nf 1 2 3\nf 1 4 2\n";
+        let smooth = parse(&folded.replace("{}", "s 1")).unwrap();
+        assert_eq!(smooth.mesh.vertices.len(), 4);
+        // The shared edge's normal is between both faces
+        let normal = smooth.mesh.normals[0];
+        assert!(normal.z > 0.1 && normal.y > 0.1);
+        let flat = parse(&folded.replace("{}", "s off")).unwrap();
+        assert_eq!(flat.mesh.vertices.len(), 6);
+        assert!((flat.mesh.normals[0] - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nf 1 2\n")), 3);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n")), 4);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n")), 4);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 -4\n")), 4);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1/1 2 3\n")), 4);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf /1 2 3\n")), 4);
+        assert_eq!(error_line(parse("v 0 zero 0\n")), 1);
+        assert_eq!(error_line(parse("\n# comment\nv 0 0\n")), 3);
+        assert_eq!(error_line(parse("s one\n")), 1);
+    }
+
+    #[test]
+    fn mtl_fields() {
+        let source = "newmtl glass\nKd 0.1 0.2 0.3\nNi 1.33\nd 0.5\nillum 7\nmap_Kd -s 2 2 2 textures/glass.png\n\nnewmtl light\nKe 4 4 4\nTr 0.25\n";
+        let materials = parse_mtl(source, Path::new("models")).unwrap();
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].name, "glass");
+        assert!((materials[0].diffuse - Color::new(0.1, 0.2, 0.3)).length() < 1e-12);
+        assert_eq!((materials[0].refraction_index, materials[0].dissolve, materials[0].illumination), (1.33, 0.5, 7));
+        assert_eq!(materials[0].diffuse_map, Some(Path::new("models").join("textures/glass.png")));
+        assert_eq!(materials[1].dissolve, 0.75);
+
+        assert!(parse_mtl("Kd 1 1 1\n", Path::new(".")).is_err());
+        assert!(parse_mtl("newmtl a\nKd 1 1\n", Path::new(".")).is_err());
+        assert!(parse_mtl("newmtl a\nillum two\n", Path::new(".")).is_err());
+        assert!(parse_mtl("newmtl a\nmap_Kd\n", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn usemtl_picks_from_mtllib() {
+        let dir = std::env::temp_dir().join(format!("rusterizer_obj_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("scene.mtl"), "newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\n").unwrap();
+        let source = "mtllib scene.mtl missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nusemtl blue\nf 1 2 3\nusemtl nothing\nf 1 2 3\n";
+        let model = parse_obj(source, &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let model = model.unwrap();
+        assert_eq!(model.materials.len(), 2);
+        assert_eq!(model.face_materials, vec![None, Some(1), None]);
+    }
+}
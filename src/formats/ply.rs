@@ -0,0 +1,458 @@
+use crate::color::Color;
+use crate::formats::FormatError;
+use crate::mesh::Mesh;
+use crate::vector::Vec3;
+use std::path::Path;
+
+// Stanford PLY, ASCII and binary of either endianness.
+// Reads vertex positions, normals, colors and uvs plus faces (fan-triangulated).
+// Any other elements are skipped.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(ScalarType::Int8),
+            "uchar" | "uint8" => Some(ScalarType::UInt8),
+            "short" | "int16" => Some(ScalarType::Int16),
+            "ushort" | "uint16" => Some(ScalarType::UInt16),
+            "int" | "int32" => Some(ScalarType::Int32),
+            "uint" | "uint32" => Some(ScalarType::UInt32),
+            "float" | "float32" => Some(ScalarType::Float32),
+            "double" | "float64" => Some(ScalarType::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    value_type: ScalarType,
+    // Some(count type) for list properties
+    list_count_type: Option<ScalarType>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header {
+    format: PlyFormat,
+    elements: Vec<Element>,
+    body_offset: usize,
+}
+
+pub fn load_ply<P: AsRef<Path>>(path: P) -> Result<Mesh, FormatError> {
+    let data = std::fs::read(path)?;
+    parse_ply(&data)
+}
+
+pub fn parse_ply(data: &[u8]) -> Result<Mesh, FormatError> {
+    let header = parse_header(data)?;
+    let mut body = BodyReader::new(&data[header.body_offset..], header.format)?;
+
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut colors = vec![];
+    let mut uvs = vec![];
+    let mut indices = vec![];
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(&mut body, element, &mut vertices, &mut normals, &mut colors, &mut uvs)?,
+            "face" => read_faces(&mut body, element, &mut indices)?,
+            _ => skip_element(&mut body, element)?,
+        }
+    }
+    if let Some(&bad) = indices.iter().find(|&&i| i >= vertices.len()) {
+        return Err(FormatError::parse(0, format!("face refers to vertex {} but there are only {}", bad, vertices.len())));
+    }
+
+    let mut mesh = Mesh::new(vertices, normals, indices);
+    mesh.colors = colors;
+    mesh.uvs = uvs;
+    Ok(mesh)
+}
+
+fn parse_header(data: &[u8]) -> Result<Header, FormatError> {
+    // end_header has to be a line of its own, a comment may mention it too
+    let mut end = None;
+    let mut line_start = 0;
+    while line_start < data.len() {
+        let line_end = data[line_start..].iter().position(|&b| b == b'\n').map_or(data.len(), |i| line_start + i);
+        if data[line_start..line_end].trim_ascii() == b"end_header" {
+            end = Some(line_start);
+            break;
+        }
+        line_start = line_end + 1;
+    }
+    let end = end.ok_or_else(|| FormatError::parse(0, "no end_header, not a PLY file or truncated header"))?;
+    // the body starts after the newline that ends the end_header line
+    let body_offset = data[end..].iter().position(|&b| b == b'\n').map_or(data.len(), |i| end + i + 1);
+
+    let text = std::str::from_utf8(&data[..end])
+        .map_err(|_| FormatError::parse(0, "PLY header is not valid text"))?;
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, line)) if line.trim() == "ply" => {}
+        _ => return Err(FormatError::parse(1, "missing 'ply' magic number")),
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    for (line_index, line) in lines {
+        let line_number = line_index + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"format") => {
+                format = Some(match tokens.get(1) {
+                    Some(&"ascii") => PlyFormat::Ascii,
+                    Some(&"binary_little_endian") => PlyFormat::BinaryLittleEndian,
+                    Some(&"binary_big_endian") => PlyFormat::BinaryBigEndian,
+                    _ => return Err(FormatError::parse(line_number, format!("unknown format '{}'", line))),
+                });
+            }
+            Some(&"element") => {
+                if tokens.len() != 3 {
+                    return Err(FormatError::parse(line_number, "expected 'element <name> <count>'"));
+                }
+                let count = tokens[2].parse::<usize>()
+                    .map_err(|_| FormatError::parse(line_number, format!("bad element count '{}'", tokens[2])))?;
+                elements.push(Element { name: tokens[1].to_string(), count, properties: vec![] });
+            }
+            Some(&"property") => {
+                let element = elements.last_mut()
+                    .ok_or_else(|| FormatError::parse(line_number, "property before any element"))?;
+                let scalar = |name: &str| ScalarType::from_name(name)
+                    .ok_or_else(|| FormatError::parse(line_number, format!("unknown property type '{}'", name)));
+                let property = if tokens.get(1) == Some(&"list") {
+                    if tokens.len() != 5 {
+                        return Err(FormatError::parse(line_number, "expected 'property list <count type> <type> <name>'"));
+                    }
+                    Property { name: tokens[4].to_string(), value_type: scalar(tokens[3])?, list_count_type: Some(scalar(tokens[2])?) }
+                } else {
+                    if tokens.len() != 3 {
+                        return Err(FormatError::parse(line_number, "expected 'property <type> <name>'"));
+                    }
+                    Property { name: tokens[2].to_string(), value_type: scalar(tokens[1])?, list_count_type: None }
+                };
+                element.properties.push(property);
+            }
+            Some(&"comment") | Some(&"obj_info") | None => {}
+            Some(other) => return Err(FormatError::parse(line_number, format!("unexpected header keyword '{}'", other))),
+        }
+    }
+    let format = format.ok_or_else(|| FormatError::parse(0, "PLY header has no format line"))?;
+    Ok(Header { format, elements, body_offset: body_offset.min(data.len()) })
+}
+
+struct BodyReader<'a> {
+    format: PlyFormat,
+    data: &'a [u8],
+    position: usize,
+    tokens: Vec<&'a str>,
+}
+
+impl<'a> BodyReader<'a> {
+    fn new(data: &'a [u8], format: PlyFormat) -> Result<Self, FormatError> {
+        let tokens = if format == PlyFormat::Ascii {
+            std::str::from_utf8(data)
+                .map_err(|_| FormatError::parse(0, "ASCII PLY body is not valid text"))?
+                .split_ascii_whitespace()
+                .collect()
+        } else {
+            vec![]
+        };
+        Ok(Self { format, data, position: 0, tokens })
+    }
+
+    fn read(&mut self, value_type: ScalarType) -> Result<f64, FormatError> {
+        if self.format == PlyFormat::Ascii {
+            let token = self.tokens.get(self.position)
+                .ok_or_else(|| FormatError::parse(0, "PLY file ended early"))?;
+            self.position += 1;
+            return token.parse::<f64>().map_err(|_| FormatError::parse(0, format!("bad number '{}' in PLY body", token)));
+        }
+
+        let size = value_type.size();
+        if self.position + size > self.data.len() {
+            return Err(FormatError::parse(0, "PLY file ended early"));
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..size].copy_from_slice(&self.data[self.position..self.position + size]);
+        self.position += size;
+        if self.format == PlyFormat::BinaryBigEndian {
+            bytes[..size].reverse();
+        }
+        Ok(match value_type {
+            ScalarType::Int8 => bytes[0] as i8 as f64,
+            ScalarType::UInt8 => bytes[0] as f64,
+            ScalarType::Int16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            ScalarType::UInt16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            ScalarType::Int32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            ScalarType::UInt32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            ScalarType::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            ScalarType::Float64 => f64::from_le_bytes(bytes),
+        })
+    }
+
+    // Reads one property; lists come back with all their items
+    fn read_property(&mut self, property: &Property, values: &mut Vec<f64>) -> Result<(), FormatError> {
+        values.clear();
+        match property.list_count_type {
+            Some(count_type) => {
+                let count = self.read(count_type)?;
+                if count < 0.0 {
+                    return Err(FormatError::parse(0, format!("negative list length in '{}'", property.name)));
+                }
+                for _ in 0..count as usize {
+                    values.push(self.read(property.value_type)?);
+                }
+            }
+            None => values.push(self.read(property.value_type)?),
+        }
+        Ok(())
+    }
+}
+
+fn read_vertices(body: &mut BodyReader, element: &Element, vertices: &mut Vec<Vec3>, normals: &mut Vec<Vec3>,
+                 colors: &mut Vec<Color>, uvs: &mut Vec<(f64, f64)>) -> Result<(), FormatError> {
+    let has = |names: &[&str]| element.properties.iter().any(|p| names.contains(&p.name.as_str()));
+    let has_normals = has(&["nx"]);
+    let has_colors = has(&["red", "r", "diffuse_red"]);
+    let has_uvs = has(&["u", "s", "texture_u", "texture_s"]);
+
+    let mut values = vec![];
+    for _ in 0..element.count {
+        let mut position = Vec3::new(0.0, 0.0, 0.0);
+        let mut normal = Vec3::new(0.0, 0.0, 0.0);
+        let mut color = Color::new(1.0, 1.0, 1.0);
+        let mut uv = (0.0, 0.0);
+        for property in &element.properties {
+            body.read_property(property, &mut values)?;
+            let value = values.first().copied().unwrap_or(0.0);
+            // 8 bit colors are 0..255, float colors are already 0..1
+            let color_value = if property.value_type == ScalarType::UInt8 { value / 255.0 } else { value };
+            match property.name.as_str() {
+                "x" => position.x = value,
+                "y" => position.y = value,
+                "z" => position.z = value,
+                "nx" => normal.x = value,
+                "ny" => normal.y = value,
+                "nz" => normal.z = value,
+                "red" | "r" | "diffuse_red" => color.x = color_value,
+                "green" | "g" | "diffuse_green" => color.y = color_value,
+                "blue" | "b" | "diffuse_blue" => color.z = color_value,
+                "u" | "s" | "texture_u" | "texture_s" => uv.0 = value,
+                "v" | "t" | "texture_v" | "texture_t" => uv.1 = value,
+                _ => {}
+            }
+        }
+        vertices.push(position);
+        if has_normals {
+            normals.push(normal);
+        }
+        if has_colors {
+            colors.push(color);
+        }
+        if has_uvs {
+            uvs.push(uv);
+        }
+    }
+    Ok(())
+}
+
+fn read_faces(body: &mut BodyReader, element: &Element, indices: &mut Vec<usize>) -> Result<(), FormatError> {
+    let mut values = vec![];
+    for face in 0..element.count {
+        for property in &element.properties {
+            body.read_property(property, &mut values)?;
+            if property.name != "vertex_indices" && property.name != "vertex_index" {
+                continue;
+            }
+            if values.len() < 3 {
+                return Err(FormatError::parse(0, format!("face {} has fewer than 3 vertices", face)));
+            }
+            if values.iter().any(|&v| v < 0.0) {
+                return Err(FormatError::parse(0, format!("face {} has a negative vertex index", face)));
+            }
+            for i in 1..values.len() - 1 {
+                indices.extend_from_slice(&[values[0] as usize, values[i] as usize, values[i + 1] as usize]);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn skip_element(body: &mut BodyReader, element: &Element) -> Result<(), FormatError> {
+    let mut values = vec![];
+    for _ in 0..element.count {
+        for property in &element.properties {
+            body.read_property(property, &mut values)?;
+        }
+    }
+    Ok(())
+}
+
+// Writes the mesh as stored (without its position/rotation/scale).
+// Normals, colors and uvs are written when the mesh has one per vertex.
+pub fn write_ply<P: AsRef<Path>>(mesh: &Mesh, path: P, format: PlyFormat) -> Result<(), FormatError> {
+    std::fs::write(path, ply_bytes(mesh, format))?;
+    Ok(())
+}
+
+pub fn ply_bytes(mesh: &Mesh, format: PlyFormat) -> Vec<u8> {
+    let vertex_count = mesh.vertices.len();
+    let has_normals = mesh.normals.len() == vertex_count;
+    let has_colors = mesh.colors.len() == vertex_count;
+    let has_uvs = mesh.uvs.len() == vertex_count;
+    let face_count = mesh.indices.len() / 3;
+
+    let format_name = match format {
+        PlyFormat::Ascii => "ascii",
+        PlyFormat::BinaryLittleEndian => "binary_little_endian",
+        PlyFormat::BinaryBigEndian => "binary_big_endian",
+    };
+    let mut header = format!("ply\nformat {} 1.0\ncomment written by Rusterizer\nelement vertex {}\n", format_name, vertex_count);
+    header.push_str("property float x\nproperty float y\nproperty float z\n");
+    if has_normals {
+        header.push_str("property float nx\nproperty float ny\nproperty float nz\n");
+    }
+    if has_colors {
+        header.push_str("property uchar red\nproperty uchar green\nproperty uchar blue\n");
+    }
+    if has_uvs {
+        header.push_str("property float u\nproperty float v\n");
+    }
+    header.push_str(&format!("element face {}\nproperty list uchar int vertex_indices\nend_header\n", face_count));
+
+    let color_byte = |c: f64| (255.99 * c.clamp(0.0, 1.0)) as u8;
+    let mut bytes = header.into_bytes();
+    if format == PlyFormat::Ascii {
+        let mut body = String::new();
+        for i in 0..vertex_count {
+            let v = mesh.vertices[i];
+            body.push_str(&format!("{} {} {}", v.x as f32, v.y as f32, v.z as f32));
+            if has_normals {
+                let n = mesh.normals[i];
+                body.push_str(&format!(" {} {} {}", n.x as f32, n.y as f32, n.z as f32));
+            }
+            if has_colors {
+                let c = mesh.colors[i];
+                body.push_str(&format!(" {} {} {}", color_byte(c.x), color_byte(c.y), color_byte(c.z)));
+            }
+            if has_uvs {
+                body.push_str(&format!(" {} {}", mesh.uvs[i].0 as f32, mesh.uvs[i].1 as f32));
+            }
+            body.push('\n');
+        }
+        for triangle in mesh.indices.chunks_exact(3) {
+            body.push_str(&format!("3 {} {} {}\n", triangle[0], triangle[1], triangle[2]));
+        }
+        bytes.extend_from_slice(body.as_bytes());
+        return bytes;
+    }
+
+    let big_endian = format == PlyFormat::BinaryBigEndian;
+    let mut push_f32 = |bytes: &mut Vec<u8>, value: f64| {
+        let value = value as f32;
+        bytes.extend_from_slice(&if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+    };
+    for i in 0..vertex_count {
+        let v = mesh.vertices[i];
+        for value in [v.x, v.y, v.z] {
+            push_f32(&mut bytes, value);
+        }
+        if has_normals {
+            let n = mesh.normals[i];
+            for value in [n.x, n.y, n.z] {
+                push_f32(&mut bytes, value);
+            }
+        }
+        if has_colors {
+            let c = mesh.colors[i];
+            bytes.extend_from_slice(&[color_byte(c.x), color_byte(c.y), color_byte(c.z)]);
+        }
+        if has_uvs {
+            push_f32(&mut bytes, mesh.uvs[i].0);
+            push_f32(&mut bytes, mesh.uvs[i].1);
+        }
+    }
+    for triangle in mesh.indices.chunks_exact(3) {
+        bytes.push(3);
+        for &index in triangle {
+            let index = index as i32;
+            bytes.extend_from_slice(&if big_endian { index.to_be_bytes() } else { index.to_le_bytes() });
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad() -> Mesh {
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0),
+        ];
+        Mesh::new(vertices, vec![], vec![0, 1, 2, 0, 2, 3])
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        for format in [PlyFormat::Ascii, PlyFormat::BinaryLittleEndian, PlyFormat::BinaryBigEndian] {
+            let mesh = parse_ply(&ply_bytes(&quad(), format)).unwrap();
+            assert_eq!(mesh.indices, quad().indices);
+            assert_eq!(mesh.vertices.len(), 4);
+            assert!((mesh.vertices[2] - Vec3::new(1.0, 1.0, 0.0)).length() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn end_header_in_a_comment() {
+        let data = b"ply\nformat ascii 1.0\ncomment written before end_header\nelement vertex 1\n\
+                     property float x\nproperty float y\nproperty float z\nend_header\n1 2 3\n";
+        let mesh = parse_ply(data).unwrap();
+        assert_eq!(mesh.vertices.len(), 1);
+        assert!((mesh.vertices[0] - Vec3::new(1.0, 2.0, 3.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_ply(b"ply\nformat ascii 1.0\nelement vertex 1\n").is_err());
+        assert!(parse_ply(b"obj\nend_header\n").is_err());
+        let truncated = ply_bytes(&quad(), PlyFormat::BinaryLittleEndian);
+        assert!(parse_ply(&truncated[..truncated.len() - 3]).is_err());
+        let data = b"ply\nformat ascii 1.0\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n3 0 1 2\n";
+        assert!(parse_ply(data).is_err(), "faces pointing at missing vertices");
+    }
+}
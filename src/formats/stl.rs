@@ -0,0 +1,221 @@
+use crate::formats::FormatError;
+use crate::mesh::Mesh;
+use crate::vector::Vec3;
+use std::path::Path;
+
+// STL, ASCII or binary. STL has no shared vertices, so every facet gets
+// its own three vertices with the facet normal (flat shading).
+
+const BINARY_HEADER_SIZE: usize = 84;
+const BINARY_FACET_SIZE: usize = 50;
+
+pub fn load_stl<P: AsRef<Path>>(path: P) -> Result<Mesh, FormatError> {
+    let data = std::fs::read(path)?;
+    parse_stl(&data)
+}
+
+pub fn parse_stl(data: &[u8]) -> Result<Mesh, FormatError> {
+    // Some binary exporters also start their header with "solid",
+    // so trust the size check before looking at the first word.
+    if data.len() >= BINARY_HEADER_SIZE {
+        let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+        if BINARY_HEADER_SIZE + count * BINARY_FACET_SIZE == data.len() {
+            return parse_binary(data, count);
+        }
+    }
+    if data.trim_ascii_start().starts_with(b"solid") {
+        let text = std::str::from_utf8(data)
+            .map_err(|_| FormatError::parse(0, "ASCII STL is not valid text"))?;
+        return parse_ascii(text);
+    }
+    if data.len() < BINARY_HEADER_SIZE {
+        return Err(FormatError::parse(0, format!("file is {} bytes, too short for a binary STL header", data.len())));
+    }
+    let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+    Err(FormatError::parse(0, format!(
+        "binary STL says {} facets ({} bytes) but the file is {} bytes",
+        count, BINARY_HEADER_SIZE + count * BINARY_FACET_SIZE, data.len())))
+}
+
+fn parse_binary(data: &[u8], count: usize) -> Result<Mesh, FormatError> {
+    let read_vec3 = |offset: usize| {
+        let f = |i: usize| {
+            let o = offset + 4 * i;
+            f32::from_le_bytes([data[o], data[o + 1], data[o + 2], data[o + 3]]) as f64
+        };
+        Vec3::new(f(0), f(1), f(2))
+    };
+    let mut facets = Vec::with_capacity(count);
+    for facet in 0..count {
+        let offset = BINARY_HEADER_SIZE + facet * BINARY_FACET_SIZE;
+        facets.push((read_vec3(offset), [read_vec3(offset + 12), read_vec3(offset + 24), read_vec3(offset + 36)]));
+    }
+    Ok(mesh_from_facets(facets))
+}
+
+fn parse_ascii(text: &str) -> Result<Mesh, FormatError> {
+    let mut facets = vec![];
+    let mut normal = Vec3::new(0.0, 0.0, 0.0);
+    let mut corners: Vec<Vec3> = vec![];
+    let mut in_facet = false;
+    let mut ended = false;
+
+    let parse_vec3 = |tokens: &[&str], line_number: usize| -> Result<Vec3, FormatError> {
+        if tokens.len() != 3 {
+            return Err(FormatError::parse(line_number, "expected three numbers"));
+        }
+        let mut values = [0.0; 3];
+        for (value, token) in values.iter_mut().zip(tokens) {
+            *value = token.parse::<f64>()
+                .map_err(|_| FormatError::parse(line_number, format!("bad number '{}'", token)))?;
+        }
+        Ok(Vec3::new(values[0], values[1], values[2]))
+    };
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line_number = line_index + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            None => {}
+            Some(&"solid") | Some(&"outer") | Some(&"endloop") => {}
+            Some(&"facet") => {
+                if in_facet {
+                    return Err(FormatError::parse(line_number, "facet inside another facet"));
+                }
+                if tokens.get(1) != Some(&"normal") {
+                    return Err(FormatError::parse(line_number, "expected 'facet normal nx ny nz'"));
+                }
+                normal = parse_vec3(&tokens[2..], line_number)?;
+                corners.clear();
+                in_facet = true;
+            }
+            Some(&"vertex") => {
+                if !in_facet {
+                    return Err(FormatError::parse(line_number, "vertex outside of a facet"));
+                }
+                corners.push(parse_vec3(&tokens[1..], line_number)?);
+            }
+            Some(&"endfacet") => {
+                if !in_facet {
+                    return Err(FormatError::parse(line_number, "endfacet outside of a facet"));
+                }
+                if corners.len() != 3 {
+                    return Err(FormatError::parse(line_number, format!("facet has {} vertices, expected 3", corners.len())));
+                }
+                facets.push((normal, [corners[0], corners[1], corners[2]]));
+                in_facet = false;
+            }
+            Some(&"endsolid") => {
+                if in_facet {
+                    return Err(FormatError::parse(line_number, "facet still open at 'endsolid'"));
+                }
+                ended = true;
+                break;
+            }
+            Some(other) => return Err(FormatError::parse(line_number, format!("unknown keyword '{}'", other))),
+        }
+    }
+    if !ended {
+        return Err(FormatError::parse(0, "ASCII STL ended without 'endsolid'"));
+    }
+    Ok(mesh_from_facets(facets))
+}
+
+fn mesh_from_facets(facets: Vec<(Vec3, [Vec3; 3])>) -> Mesh {
+    let mut vertices = Vec::with_capacity(3 * facets.len());
+    let mut normals = Vec::with_capacity(3 * facets.len());
+    for (normal, [a, b, c]) in facets {
+        // Plenty of exporters write zero normals, so fall back to the winding
+        let normal = if normal.length() > 0.0 { normal.normalized() } else { facet_normal(a, b, c) };
+        vertices.extend_from_slice(&[a, b, c]);
+        normals.extend_from_slice(&[normal, normal, normal]);
+    }
+    let indices = (0..vertices.len()).collect();
+    Mesh::new(vertices, normals, indices)
+}
+
+fn facet_normal(a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let normal = (b - a).cross(c - a);
+    if normal.length() > 0.0 { normal.normalized() } else { normal }
+}
+
+// Writes the mesh as stored (without its position/rotation/scale), with normals from the winding.
+pub fn write_stl<P: AsRef<Path>>(mesh: &Mesh, path: P, binary: bool) -> Result<(), FormatError> {
+    let bytes = if binary { stl_binary_bytes(mesh) } else { stl_ascii_string(mesh).into_bytes() };
+    std::fs::write(path, bytes)?;
+    Ok(())
+}
+
+fn mesh_triangles(mesh: &Mesh) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+    mesh.indices.chunks_exact(3)
+        .filter(|t| t.iter().all(|&i| i < mesh.vertices.len()))
+        .map(|t| [mesh.vertices[t[0]], mesh.vertices[t[1]], mesh.vertices[t[2]]])
+}
+
+pub fn stl_ascii_string(mesh: &Mesh) -> String {
+    let mut text = String::from("solid rusterizer\n");
+    for [a, b, c] in mesh_triangles(mesh) {
+        let n = facet_normal(a, b, c);
+        text.push_str(&format!("  facet normal {} {} {}\n    outer loop\n", n.x as f32, n.y as f32, n.z as f32));
+        for v in [a, b, c] {
+            text.push_str(&format!("      vertex {} {} {}\n", v.x as f32, v.y as f32, v.z as f32));
+        }
+        text.push_str("    endloop\n  endfacet\n");
+    }
+    text.push_str("endsolid rusterizer\n");
+    text
+}
+
+pub fn stl_binary_bytes(mesh: &Mesh) -> Vec<u8> {
+    let triangles: Vec<[Vec3; 3]> = mesh_triangles(mesh).collect();
+    let mut bytes = vec![0u8; 80];
+    bytes[..10].copy_from_slice(b"rusterizer");
+    bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    for [a, b, c] in triangles {
+        for v in [facet_normal(a, b, c), a, b, c] {
+            for value in [v.x, v.y, v.z] {
+                bytes.extend_from_slice(&(value as f32).to_le_bytes());
+            }
+        }
+        // attribute byte count, unused
+        bytes.extend_from_slice(&[0, 0]);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> Mesh {
+        let vertices = vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)];
+        Mesh::new(vertices, vec![], vec![0, 1, 2])
+    }
+
+    #[test]
+    fn round_trips_ascii_and_binary() {
+        for data in [stl_ascii_string(&triangle()).into_bytes(), stl_binary_bytes(&triangle())] {
+            let mesh = parse_stl(&data).unwrap();
+            assert_eq!(mesh.vertices.len(), 3);
+            assert!((mesh.vertices[1] - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-6);
+            // Normals come from the winding
+            assert!((mesh.normals[0] - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn open_facet_at_endsolid() {
+        let text = "solid t\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\nendloop\nendsolid t\n";
+        let error = parse_stl(text.as_bytes()).unwrap_err().to_string();
+        assert!(error.contains("still open"), "{error}");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_stl(b"solid t\nfacet normal 0 0 1\nendfacet\nendsolid\n").is_err());
+        assert!(parse_stl(b"solid t\n").is_err());
+        assert!(parse_stl(b"solid t\nendfacet\nendsolid\n").is_err());
+        let binary = stl_binary_bytes(&triangle());
+        assert!(parse_stl(&binary[..binary.len() - 1]).is_err());
+    }
+}
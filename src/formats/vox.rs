@@ -0,0 +1,187 @@
+use crate::color::Color;
+use crate::formats::FormatError;
+use std::path::Path;
+
+// Voxel volumes: MagicaVoxel .vox files and headerless raw byte grids.
+// Every voxel is one byte, 0 is empty and anything else is a palette / material index.
+// .vox is z-up, it is turned y-up here (vox x, y, z become x, z, -y) so
+// the grid's y axis is the scene's up.
+
+// MagicaVoxel models are at most this many voxels along each axis
+const MAX_VOX_SIZE: usize = 256;
+
+pub struct VoxelData {
+    // Voxels along x, y and z
+    pub size: [usize; 3],
+    // x fastest, then y, then z
+    pub voxels: Vec<u8>,
+    // Color of index i at palette[i - 1]. Empty for raw files.
+    pub palette: Vec<Color>,
+}
+
+impl VoxelData {
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.voxels[(z * self.size[1] + y) * self.size[0] + x]
+    }
+}
+
+pub fn load_vox<P: AsRef<Path>>(path: P) -> Result<VoxelData, FormatError> {
+    let data = std::fs::read(path)?;
+    parse_vox(&data)
+}
+
+// One byte per voxel in the VoxelData order, the size isn't stored in the file
+pub fn load_raw_voxels<P: AsRef<Path>>(path: P, size: [usize; 3]) -> Result<VoxelData, FormatError> {
+    let data = std::fs::read(path)?;
+    parse_raw_voxels(&data, size)
+}
+
+pub fn parse_raw_voxels(data: &[u8], size: [usize; 3]) -> Result<VoxelData, FormatError> {
+    let count = size[0].checked_mul(size[1]).and_then(|count| count.checked_mul(size[2]));
+    if count != Some(data.len()) {
+        return Err(FormatError::parse(0, format!(
+            "raw voxels of size {} x {} x {} need {} bytes but the file is {} bytes",
+            size[0], size[1], size[2], count.map_or("more".to_string(), |count| count.to_string()), data.len())));
+    }
+    Ok(VoxelData { size, voxels: data.to_vec(), palette: vec![] })
+}
+
+// Reads the first model's SIZE and XYZI chunks and the RGBA palette, other chunks are skipped.
+// Files without a palette get a gray ramp instead of MagicaVoxel's default palette.
+pub fn parse_vox(data: &[u8]) -> Result<VoxelData, FormatError> {
+    if data.len() < 8 || &data[0..4] != b"VOX " {
+        return Err(FormatError::parse(0, "not a .vox file"));
+    }
+    let read_u32 = |offset: usize| -> Result<u32, FormatError> {
+        data.get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| FormatError::parse(0, format!("file ends inside a chunk at byte {}", offset)))
+    };
+
+    let mut size = None;
+    let mut voxels = None;
+    let mut palette = vec![];
+    // Children follow their parent's content (MAIN's children are the rest of the file),
+    // so walking the chunks in order visits everything
+    let mut offset = 8;
+    while offset < data.len() {
+        let id = data.get(offset..offset + 4)
+            .ok_or_else(|| FormatError::parse(0, "file ends inside a chunk header"))?;
+        let content_size = read_u32(offset + 4)? as usize;
+        // The next 4 bytes are the children's size
+        let content = offset + 12;
+        let end = content + content_size;
+        if end > data.len() {
+            return Err(FormatError::parse(0, format!(
+                "chunk '{}' needs {} bytes but the file ends after {}",
+                String::from_utf8_lossy(id), content_size, data.len() - content)));
+        }
+        match id {
+            b"SIZE" if size.is_none() => {
+                size = Some([read_u32(content)? as usize, read_u32(content + 4)? as usize, read_u32(content + 8)? as usize]);
+            }
+            b"XYZI" if voxels.is_none() => {
+                let count = read_u32(content)? as usize;
+                if 4 + 4 * count > content_size {
+                    return Err(FormatError::parse(0, format!("XYZI chunk says {} voxels but only holds {}", count, (content_size - 4) / 4)));
+                }
+                voxels = Some(&data[content + 4..content + 4 + 4 * count]);
+            }
+            b"RGBA" => {
+                let entries = &data[content..end];
+                palette = entries.chunks_exact(4)
+                    .take(255)
+                    .map(|c| Color::new(c[0] as f64 / 255.0, c[1] as f64 / 255.0, c[2] as f64 / 255.0))
+                    .collect();
+            }
+            _ => {}
+        }
+        offset = end;
+    }
+
+    let Some([vox_x, vox_y, vox_z]) = size else {
+        return Err(FormatError::parse(0, ".vox file has no SIZE chunk"));
+    };
+    let Some(entries) = voxels else {
+        return Err(FormatError::parse(0, ".vox file has no XYZI chunk"));
+    };
+    if [vox_x, vox_y, vox_z].iter().any(|&axis| axis > MAX_VOX_SIZE) {
+        return Err(FormatError::parse(0, format!(
+            "SIZE {} x {} x {} is larger than the {} voxels per axis .vox allows", vox_x, vox_y, vox_z, MAX_VOX_SIZE)));
+    }
+    let cells = vox_x.checked_mul(vox_y).and_then(|cells| cells.checked_mul(vox_z))
+        .ok_or_else(|| FormatError::parse(0, format!("SIZE {} x {} x {} is too large", vox_x, vox_y, vox_z)))?;
+    let size = [vox_x, vox_z, vox_y];
+    let mut grid = vec![0; cells];
+    for entry in entries.chunks_exact(4) {
+        let (x, y, z, index) = (entry[0] as usize, entry[1] as usize, entry[2] as usize, entry[3]);
+        if x >= vox_x || y >= vox_y || z >= vox_z {
+            return Err(FormatError::parse(0, format!("voxel ({}, {}, {}) is outside the model's size", x, y, z)));
+        }
+        grid[((vox_y - 1 - y) * size[1] + z) * size[0] + x] = index;
+    }
+    // Indices without a palette entry (no RGBA chunk, or a short one) get a gray ramp
+    palette.extend((palette.len() + 1..=255).map(|i| {
+        let gray = i as f64 / 255.0;
+        Color::new(gray, gray, gray)
+    }));
+    Ok(VoxelData { size, voxels: grid, palette })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((content.len() as u32).to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(content);
+        bytes
+    }
+
+    fn vox_file(size: [u32; 3], voxels: &[[u8; 4]], rgba: Option<&[u8]>) -> Vec<u8> {
+        let mut children = chunk(b"SIZE", &size.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>());
+        let mut xyzi = (voxels.len() as u32).to_le_bytes().to_vec();
+        xyzi.extend(voxels.iter().flatten());
+        children.extend(chunk(b"XYZI", &xyzi));
+        if let Some(rgba) = rgba {
+            children.extend(chunk(b"RGBA", rgba));
+        }
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend(150u32.to_le_bytes());
+        bytes.extend(b"MAIN");
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend((children.len() as u32).to_le_bytes());
+        bytes.extend(children);
+        bytes
+    }
+
+    #[test]
+    fn z_up_becomes_y_up() {
+        // One voxel at the top of a 2 x 3 x 4 (z up) model
+        let data = parse_vox(&vox_file([2, 3, 4], &[[1, 0, 3, 7]], Some(&[255, 0, 0, 255]))).unwrap();
+        assert_eq!(data.size, [2, 4, 3]);
+        assert_eq!(data.get(1, 3, 2), 7);
+        assert_eq!(data.voxels.iter().filter(|&&v| v != 0).count(), 1);
+        assert_eq!(data.palette.len(), 255);
+        assert_eq!((data.palette[0].x, data.palette[0].y), (1.0, 0.0));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_vox(b"VOX").is_err());
+        assert!(parse_vox(&vox_file([300, 1, 1], &[], None)).is_err());
+        assert!(parse_vox(&vox_file([u32::MAX, u32::MAX, u32::MAX], &[], None)).is_err());
+        assert!(parse_vox(&vox_file([2, 2, 2], &[[2, 0, 0, 1]], None)).is_err());
+        let file = vox_file([2, 2, 2], &[[0, 0, 0, 1]], None);
+        assert!(parse_vox(&file[..file.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn raw_voxels_need_the_exact_size() {
+        assert_eq!(parse_raw_voxels(&[0, 1, 2, 3, 4, 5], [1, 2, 3]).unwrap().get(0, 1, 2), 5);
+        assert!(parse_raw_voxels(&[0; 5], [1, 2, 3]).is_err());
+        assert!(parse_raw_voxels(&[], [usize::MAX, 2, 1]).is_err());
+    }
+}
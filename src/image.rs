@@ -63,6 +63,18 @@ impl Image {
 
     }
 
+    // Copies source into this image with its top left corner at (x_offset, y_offset).
+    // Anything that falls outside is dropped.
+    pub fn blit(&mut self, source: &Image, x_offset: usize, y_offset: usize) {
+        for y in 0..source.height {
+            for x in 0..source.width {
+                if x + x_offset < self.width && y + y_offset < self.height {
+                    *self.index_2d_mut(x + x_offset, y + y_offset) = *source.index_2d(x, y);
+                }
+            }
+        }
+    }
+
     pub fn over(&mut self, x: usize, y: usize, color: Color, alpha: f64) {
         if x >= self.width || y >= self.height {
             return;
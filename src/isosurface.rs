@@ -0,0 +1,305 @@
+use crate::mesh::Mesh;
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::sdf::Sdf;
+use crate::solid::Solid;
+use crate::vector::Vec3;
+use rayon::prelude::*;
+
+// Turns implicit volumes into triangle meshes with dual contouring.
+// The volume is sampled on the corners of a grid of cells. Every cell the surface passes through
+// gets one vertex, placed where the tangent planes at its edge crossings meet (so sharp edges
+// and corners survive), and every grid edge the surface crosses becomes a quad between the
+// four cells around it. Triangles wind counter-clockwise seen from outside.
+pub struct Mesher {
+    bounds: AABB,
+    cells: [usize; 3],
+}
+
+// Cell corners are numbered x + 2y + 4z, the 12 edges join corners that differ in one bit
+const CELL_EDGES: [(usize, usize); 12] = [
+    (0, 1), (2, 3), (4, 5), (6, 7),
+    (0, 2), (1, 3), (4, 6), (5, 7),
+    (0, 4), (1, 5), (2, 6), (3, 7),
+];
+
+impl Mesher {
+    // Bisection steps when locating where an edge crosses the surface
+    const CROSSING_STEPS: usize = 12;
+    // Pull towards the average crossing, keeps flat and nearly flat cells stable
+    const QEF_REGULARIZATION: f64 = 0.01;
+
+    // resolution cells along the longest side of bounds, cells are (roughly) cubes.
+    // The surface should stay inside bounds, or the mesh has holes where it leaves.
+    pub fn new(bounds: AABB, resolution: usize) -> Self {
+        let sizes = [bounds.x.size(), bounds.y.size(), bounds.z.size()];
+        let longest = sizes.iter().cloned().fold(0.0, f64::max);
+        let cells = sizes.map(|size| ((size / longest * resolution as f64).round() as usize).max(1));
+        Self { bounds, cells }
+    }
+
+    pub fn mesh_sdf(&self, sdf: &dyn Sdf) -> Mesh {
+        self.extract(&|p| sdf.distance(p), true)
+    }
+
+    // Inside where density is above iso_level
+    pub fn mesh_density(&self, density: &(dyn Fn(Vec3) -> f64 + Sync), iso_level: f64) -> Mesh {
+        self.extract(&|p| iso_level - density(p), true)
+    }
+
+    // Only inside/outside is known, so crossings come from bisection and
+    // normals from the triangles around each vertex
+    pub fn mesh_solid(&self, solid: &(dyn Solid + Sync)) -> Mesh {
+        self.extract(&|p| if solid.is_point_inside(p) { -1.0 } else { 1.0 }, false)
+    }
+
+    fn cell_size(&self) -> Vec3 {
+        Vec3::new(
+            self.bounds.x.size() / self.cells[0] as f64,
+            self.bounds.y.size() / self.cells[1] as f64,
+            self.bounds.z.size() / self.cells[2] as f64,
+        )
+    }
+
+    fn corner_position(&self, i: usize, j: usize, k: usize) -> Vec3 {
+        let size = self.cell_size();
+        Vec3::new(
+            self.bounds.x.lower_bound + i as f64 * size.x,
+            self.bounds.y.lower_bound + j as f64 * size.y,
+            self.bounds.z.lower_bound + k as f64 * size.z,
+        )
+    }
+
+    // field is negative inside. With has_gradient the field is continuous and its gradient
+    // gives the tangent planes and the normals.
+    fn extract(&self, field: &(dyn Fn(Vec3) -> f64 + Sync), has_gradient: bool) -> Mesh {
+        let [nx, ny, nz] = self.cells;
+        let corner_index = |i: usize, j: usize, k: usize| (k * (ny + 1) + j) * (nx + 1) + i;
+        let cell_index = |i: usize, j: usize, k: usize| (k * ny + j) * nx + i;
+        let cell_size = self.cell_size();
+        let gradient_step = 0.1 * cell_size.x.min(cell_size.y).min(cell_size.z);
+        let gradient = |p: Vec3| {
+            let dx = Vec3::new(gradient_step, 0.0, 0.0);
+            let dy = Vec3::new(0.0, gradient_step, 0.0);
+            let dz = Vec3::new(0.0, 0.0, gradient_step);
+            let g = Vec3::new(field(p + dx) - field(p - dx), field(p + dy) - field(p - dy), field(p + dz) - field(p - dz));
+            if g.length_squared() > 0.0 { g.normalized() } else { Vec3::new(0.0, 0.0, 0.0) }
+        };
+
+        let inside: Vec<bool> = (0..(nx + 1) * (ny + 1) * (nz + 1)).into_par_iter()
+            .map(|index| {
+                let i = index % (nx + 1);
+                let j = index / (nx + 1) % (ny + 1);
+                let k = index / ((nx + 1) * (ny + 1));
+                field(self.corner_position(i, j, k)) < 0.0
+            })
+            .collect();
+
+        // One vertex per cell the surface goes through
+        let cell_vertices: Vec<Option<Vec3>> = (0..nx * ny * nz).into_par_iter()
+            .map(|index| {
+                let i = index % nx;
+                let j = index / nx % ny;
+                let k = index / (nx * ny);
+                let corner = |c: usize| (i + (c & 1), j + (c >> 1 & 1), k + (c >> 2 & 1));
+                let mut crossings = vec![];
+                for (a, b) in CELL_EDGES {
+                    let (ai, aj, ak) = corner(a);
+                    let (bi, bj, bk) = corner(b);
+                    let a_inside = inside[corner_index(ai, aj, ak)];
+                    if a_inside == inside[corner_index(bi, bj, bk)] {
+                        continue;
+                    }
+                    let point = Self::crossing(field, self.corner_position(ai, aj, ak), self.corner_position(bi, bj, bk), a_inside);
+                    let normal = if has_gradient { gradient(point) } else { Vec3::new(0.0, 0.0, 0.0) };
+                    crossings.push((point, normal));
+                }
+                if crossings.is_empty() {
+                    return None;
+                }
+                let cell_min = self.corner_position(i, j, k);
+                Some(Self::place_vertex(&crossings, cell_min, cell_min + cell_size))
+            })
+            .collect();
+
+        let mut vertices = vec![];
+        let mut vertex_of_cell = vec![usize::MAX; nx * ny * nz];
+        for (index, vertex) in cell_vertices.iter().enumerate() {
+            if let Some(vertex) = vertex {
+                vertex_of_cell[index] = vertices.len();
+                vertices.push(*vertex);
+            }
+        }
+
+        // A quad around every crossed edge that has cells on all four sides.
+        // The cells are listed counter-clockwise around the edge's direction,
+        // which faces the quad along the edge, the right way when the edge goes from inside to outside.
+        let mut indices = vec![];
+        let mut add_quad = |cells: [(usize, usize, usize); 4], flip: bool| {
+            let mut quad = cells.map(|(i, j, k)| vertex_of_cell[cell_index(i, j, k)]);
+            if flip {
+                quad.reverse();
+            }
+            indices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+        };
+        for k in 0..=nz {
+            for j in 0..=ny {
+                for i in 0..=nx {
+                    let here = inside[corner_index(i, j, k)];
+                    if i < nx && j > 0 && k > 0 && j < ny && k < nz && here != inside[corner_index(i + 1, j, k)] {
+                        add_quad([(i, j - 1, k - 1), (i, j, k - 1), (i, j, k), (i, j - 1, k)], !here);
+                    }
+                    if j < ny && i > 0 && k > 0 && i < nx && k < nz && here != inside[corner_index(i, j + 1, k)] {
+                        add_quad([(i - 1, j, k - 1), (i - 1, j, k), (i, j, k), (i, j, k - 1)], !here);
+                    }
+                    if k < nz && i > 0 && j > 0 && i < nx && j < ny && here != inside[corner_index(i, j, k + 1)] {
+                        add_quad([(i - 1, j - 1, k), (i, j - 1, k), (i, j, k), (i - 1, j, k)], !here);
+                    }
+                }
+            }
+        }
+
+        let normals = if has_gradient {
+            vertices.par_iter().map(|v| gradient(*v)).collect()
+        } else {
+            Self::face_normals(&vertices, &indices)
+        };
+        Mesh::new(vertices, normals, indices)
+    }
+
+    fn crossing(field: &(dyn Fn(Vec3) -> f64 + Sync), a: Vec3, b: Vec3, a_inside: bool) -> Vec3 {
+        let (mut a, mut b) = (a, b);
+        for _ in 0..Self::CROSSING_STEPS {
+            let middle = 0.5 * (a + b);
+            if (field(middle) < 0.0) == a_inside {
+                a = middle;
+            } else {
+                b = middle;
+            }
+        }
+        0.5 * (a + b)
+    }
+
+    // Minimizes the squared distances to the crossings' tangent planes (the QEF).
+    // Falls back to the average crossing without normals, or when the solution leaves the cell.
+    fn place_vertex(crossings: &[(Vec3, Vec3)], cell_min: Vec3, cell_max: Vec3) -> Vec3 {
+        let mass_point = crossings.iter().fold(Vec3::new(0.0, 0.0, 0.0), |sum, (p, _)| sum + *p) / crossings.len() as f64;
+        // Normal equations (A^T A + r I) y = A^T (b - A m), solved for the offset y from the mass point
+        let r = Self::QEF_REGULARIZATION;
+        let mut columns = [Vec3::new(r, 0.0, 0.0), Vec3::new(0.0, r, 0.0), Vec3::new(0.0, 0.0, r)];
+        let mut rhs = Vec3::new(0.0, 0.0, 0.0);
+        for (point, normal) in crossings {
+            for (axis, column) in columns.iter_mut().enumerate() {
+                *column += normal[axis] * *normal;
+            }
+            rhs += normal.dot(*point - mass_point) * *normal;
+        }
+        // Cramer's rule
+        let [c0, c1, c2] = columns;
+        let determinant = c0.dot(c1.cross(c2));
+        if determinant.abs() < 1e-12 {
+            return mass_point;
+        }
+        let offset = Vec3::new(rhs.dot(c1.cross(c2)), c0.dot(rhs.cross(c2)), c0.dot(c1.cross(rhs))) / determinant;
+        let vertex = mass_point + offset;
+        let in_cell = (0..3).all(|axis| vertex[axis] >= cell_min[axis] && vertex[axis] <= cell_max[axis]);
+        if in_cell { vertex } else { mass_point }
+    }
+
+    // Area weighted average of the triangles around each vertex
+    fn face_normals(vertices: &[Vec3], indices: &[usize]) -> Vec<Vec3> {
+        let mut normals = vec![Vec3::new(0.0, 0.0, 0.0); vertices.len()];
+        for triangle in indices.chunks_exact(3) {
+            let (a, b, c) = (vertices[triangle[0]], vertices[triangle[1]], vertices[triangle[2]]);
+            let area_normal = (b - a).cross(c - a);
+            for &index in triangle {
+                normals[index] += area_normal;
+            }
+        }
+        normals.iter().map(|n| if n.length_squared() > 0.0 { n.normalized() } else { *n }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::cuboid::Cuboid;
+    use crate::raytracing::material::Lambertian;
+    use crate::raytracing::sdf::primitives::{SdfBox, SdfSphere};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn bounds(half_size: f64) -> AABB {
+        AABB::from_corners(Vec3::new(-half_size, -half_size, -half_size), Vec3::new(half_size, half_size, half_size))
+    }
+
+    // Every edge is shared by exactly two triangles that use it in opposite directions,
+    // and the enclosed volume (divergence theorem) is returned
+    fn closed_volume(mesh: &Mesh) -> f64 {
+        let mut edges: HashMap<(usize, usize), i32> = HashMap::new();
+        let mut volume = 0.0;
+        for triangle in mesh.indices.chunks_exact(3) {
+            for e in 0..3 {
+                let (a, b) = (triangle[e], triangle[(e + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += if a < b { 1 } else { -1 };
+            }
+            let (a, b, c) = (mesh.vertices[triangle[0]], mesh.vertices[triangle[1]], mesh.vertices[triangle[2]]);
+            volume += a.dot(b.cross(c)) / 6.0;
+        }
+        assert!(edges.values().all(|&count| count == 0), "the mesh has open or doubled edges");
+        volume
+    }
+
+    #[test]
+    fn sphere_sdf() {
+        let mesh = Mesher::new(bounds(1.5), 24).mesh_sdf(&SdfSphere { radius: 1.0 });
+        assert!(!mesh.indices.is_empty());
+        for (vertex, normal) in mesh.vertices.iter().zip(&mesh.normals) {
+            assert!((vertex.length() - 1.0).abs() < 0.02);
+            assert!(normal.dot(vertex.normalized()) > 0.99);
+        }
+        // Counter-clockwise from outside gives a positive volume
+        let volume = closed_volume(&mesh);
+        assert!((volume - 4.0 / 3.0 * std::f64::consts::PI).abs() < 0.05, "{}", volume);
+    }
+
+    #[test]
+    fn box_keeps_its_corners() {
+        let mesh = Mesher::new(bounds(1.5), 11).mesh_sdf(&SdfBox { half_extents: Vec3::new(1.0, 1.0, 1.0) });
+        let corner = Vec3::new(1.0, 1.0, 1.0);
+        let closest = mesh.vertices.iter().map(|v| (*v - corner).length()).fold(f64::INFINITY, f64::min);
+        assert!(closest < 0.02, "{}", closest);
+        assert!((closed_volume(&mesh) - 8.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn density_and_solid() {
+        let mesher = Mesher::new(bounds(1.5), 16);
+        let mesh = mesher.mesh_density(&|p: Vec3| 2.0 - p.length_squared(), 1.0);
+        assert!(mesh.vertices.iter().all(|v| (v.length() - 1.0).abs() < 0.03));
+        assert!((closed_volume(&mesh) - 4.0 / 3.0 * std::f64::consts::PI).abs() < 0.15);
+
+        let cuboid = Cuboid::new(Vec3::new(-1.0, -0.5, -0.75), Vec3::new(1.0, 0.5, 0.75), Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))));
+        let mesh = mesher.mesh_solid(&cuboid);
+        assert!((closed_volume(&mesh) - 3.0).abs() < 0.3);
+        // Normals from the faces point out of the box
+        for (vertex, normal) in mesh.vertices.iter().zip(&mesh.normals) {
+            assert!(normal.dot(*vertex) > 0.0);
+        }
+    }
+
+    #[test]
+    fn vertex_sits_where_the_planes_meet() {
+        let (x, y, z) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let crossings = [
+            (Vec3::new(0.3, 0.0, 0.0), x), (Vec3::new(0.3, 1.0, 1.0), x),
+            (Vec3::new(0.0, 0.6, 0.0), y), (Vec3::new(1.0, 0.6, 1.0), y),
+            (Vec3::new(0.0, 0.0, 0.2), z), (Vec3::new(1.0, 1.0, 0.2), z),
+        ];
+        let vertex = Mesher::place_vertex(&crossings, Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+        assert!((vertex - Vec3::new(0.3, 0.6, 0.2)).length() < 0.02, "{:?}", vertex);
+        // Without normals it's the average crossing
+        let flat = crossings.map(|(p, _)| (p, Vec3::new(0.0, 0.0, 0.0)));
+        let vertex = Mesher::place_vertex(&flat, Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+        assert!((vertex - Vec3::new(2.6, 3.2, 2.4) / 6.0).length() < 1e-12);
+    }
+}
@@ -8,6 +8,8 @@ mod mesh;
 mod raytracing;
 mod solid;
 mod random;
+mod formats;
+mod isosurface;
 
 use std::sync::Arc;
 use std::rc::Rc;
@@ -19,12 +21,18 @@ use raytracing::hittable::*;
 use raytracing::implicits::sphere::*;
 use raytracing::material::*;
 use raytracing::bvh::*;
+use raytracing::flat_bvh::FlatBVH;
 use crate::raytracing::texture::*;
 use crate::raytracing::implicits::quad::Quad;
+use crate::raytracing::implicits::plane::Plane;
+use crate::raytracing::animation::*;
+use crate::raytracing::instancing::InstanceScene;
+use crate::matrix::Mat4;
 
 fn main() {
     hw3_scene3();
     //final_scene();
+    //final_scene_bvh_comparison();
     //cornell_smoke()
     //simple_light();
     //quads();
@@ -32,6 +40,44 @@ fn main() {
     //earth();
     //checkered_spheres();
     //homework_3_render_test();
+    //turntable();
+}
+
+fn turntable() {
+    let mut world = HittableList::new();
+    let checker = Arc::new(CheckerTexture::new(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+    world.add(Arc::new(Plane::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Arc::new(Lambertian::from_texture(checker)))));
+    world.add(Arc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, Arc::new(Dielectric::new(1.5)))));
+    world.add(Arc::new(Sphere::new(Vec3::new(-2.5, 1.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1))))));
+
+    let bounce = Track::new()
+        .with_key(Keyframe::new(0.0, Vec3::new(2.5, 1.0, 0.0), Interpolation::CatmullRom))
+        .with_key(Keyframe::new(1.0, Vec3::new(2.5, 3.0, 0.0), Interpolation::CatmullRom))
+        .with_key(Keyframe::new(2.0, Vec3::new(2.5, 1.0, 0.0), Interpolation::CatmullRom));
+    let metal_ball = Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0))));
+    world.add(Arc::new(AnimatedTransform::new(metal_ball, bounce, Track::new())));
+
+    let mut animation = CameraAnimation::new();
+    for i in 0..=4 {
+        let angle = (i as f64) * 0.5 * std::f64::consts::PI;
+        let look_from = Vec3::new(12.0 * angle.sin(), 3.0, 12.0 * angle.cos());
+        animation.look_from.add(Keyframe::new(i as f64 * 0.5, look_from, Interpolation::CatmullRom));
+    }
+    animation.look_at.add(Keyframe::new(0.0, Vec3::new(0.0, 1.0, 0.0), Interpolation::Linear));
+
+    let mut camera = Camera::from_aspect_ratio(400, 16.0 / 9.0);
+    camera.samples_per_pixel = 50;
+    camera.max_depth = 20;
+    camera.background = Color::new(0.70, 0.80, 1.00);
+    camera.field_of_view = 35.0;
+    camera.shutter_close = 0.5;
+
+    let bvh = BVHNode::new(&mut world);
+    let time = std::time::Instant::now();
+    camera.render_frames(&bvh, &animation, 0..48, 24.0, "turntable");
+    let time_elapsed = time.elapsed();
+    println!();
+    println!("Time taken to render: {} seconds", time_elapsed.as_secs_f64());
 }
 
 fn hw3_scene3() {
@@ -125,6 +171,36 @@ fn hw3_scene2() {
 
 
 fn final_scene() {
+    let mut world = final_scene_world();
+
+    let mut camera = Camera::from_aspect_ratio(800, 1.0);
+
+    camera.samples_per_pixel = 600;
+    camera.max_depth = 30;
+    camera.background = Color::new(0.0, 0.0, 0.0);
+
+    camera.field_of_view = 40.0;
+    camera.look_from = Vec3::new(478.0, 278.0, -600.0);
+    camera.look_at = Vec3::new(278.0, 278.0, 0.0);
+    camera.up = Vec3::new(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+
+    let tlas = FlatBVH::with_split(&mut world, BVHSplit::SurfaceArea(SAHOptions::default()));
+    let time = std::time::Instant::now();
+    camera.render_threaded(&tlas);
+    camera.viewport.write_to_file("rt.ppm");
+    let time_elapsed = time.elapsed();
+    println!();
+    println!("Time taken to render: {} seconds", time_elapsed.as_secs_f64());
+}
+
+// Builds the final scene's top level tree both ways and prints how they compare
+fn final_scene_bvh_comparison() {
+    println!("{}", BVHComparison::new(&final_scene_world(), SAHOptions::default()));
+}
+
+fn final_scene_world() -> HittableList {
     let mut world = HittableList::new();
 
     let mut boxes1 = HittableList::new();
@@ -174,29 +250,12 @@ fn final_scene() {
         boxes2.add(Arc::new(Sphere::new(Vec3::random_range(0.0..165.0), 10.0, white.clone())));
     }
 
-    let blas2 = Arc::new(BVHNode::new(&mut boxes2));
-    world.add(Arc::new(Translate::new(Arc::new(RotateY::new(blas2, 15.0)), Vec3::new(-100.0, 270.0, 395.0))));
-
-    let mut camera = Camera::from_aspect_ratio(800, 1.0);
-
-    camera.samples_per_pixel = 600;
-    camera.max_depth = 30;
-    camera.background = Color::new(0.0, 0.0, 0.0);
-
-    camera.field_of_view = 40.0;
-    camera.look_from = Vec3::new(478.0, 278.0, -600.0);
-    camera.look_at = Vec3::new(278.0, 278.0, 0.0);
-    camera.up = Vec3::new(0.0, 1.0, 0.0);
-
-    camera.defocus_angle = 0.0;
-
-    let tlas = BVHNode::new(&mut world);
-    let time = std::time::Instant::now();
-    camera.render_threaded(&tlas);
-    camera.viewport.write_to_file("rt.ppm");
-    let time_elapsed = time.elapsed();
-    println!();
-    println!("Time taken to render: {} seconds", time_elapsed.as_secs_f64());
+    let mut instances = InstanceScene::new();
+    let blas2 = instances.add_geometry("boxes2", &mut boxes2);
+    let placement = Mat4::translation(Vec3::new(-100.0, 270.0, 395.0)) * Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), 15.0_f64.to_radians());
+    instances.add_instance(&blas2, placement, None);
+    world.add(Arc::new(instances.build()));
+    world
 }
 
 fn cornell_smoke() {
@@ -499,13 +558,15 @@ fn homework_3_render_test() {
     camera.up = Vec3::new(0.0, 1.0, 0.0);
 
     camera.defocus_angle = 0.6;
-    camera.focus_dist = 10.0;
 
 
     let ground_material = Arc::new(Lambertian::from_texture(checker));
     world.add(Arc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_material)));
 
     let world2 = BVHNode::new(&mut world);
+    // focus on the glass sphere in the middle of the frame
+    let (center_x, center_y) = (camera.viewport.width / 2, camera.viewport.height / 2);
+    camera.autofocus(center_x, center_y, &world2);
     //let mut world3 = HittableList::new();
     //world3.add(world2);
 
@@ -1,7 +1,9 @@
+use crate::vector::{Quat, Vec3};
 
 // Naming convention: _RowCol
-#[derive(Copy, Clone, Debug)]
-struct Mat4 {
+// Points are column vectors, so a * b applies b first.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
     // row 1
     _11: f64,
     _12: f64,
@@ -17,7 +19,7 @@ struct Mat4 {
     _32: f64,
     _33: f64,
     _34: f64,
-    // row 3
+    // row 4
     _41: f64,
     _42: f64,
     _43: f64,
@@ -25,7 +27,13 @@ struct Mat4 {
 }
 
 impl Mat4 {
-    #[expect(unused)]
+    pub const IDENTITY: Mat4 = Mat4::from_rows([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
     pub fn new(diagonal: f64) -> Mat4{
         Mat4 {
             _11: diagonal,
@@ -46,8 +54,223 @@ impl Mat4 {
             _44: diagonal,
         }
     }
+
+    pub const fn from_rows(rows: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 {
+            _11: rows[0][0], _12: rows[0][1], _13: rows[0][2], _14: rows[0][3],
+            _21: rows[1][0], _22: rows[1][1], _23: rows[1][2], _24: rows[1][3],
+            _31: rows[2][0], _32: rows[2][1], _33: rows[2][2], _34: rows[2][3],
+            _41: rows[3][0], _42: rows[3][1], _43: rows[3][2], _44: rows[3][3],
+        }
+    }
+
+    // Column-major, the layout OpenGL and glTF use
+    pub fn from_column_major(values: [f64; 16]) -> Mat4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, value) in values.iter().enumerate() {
+            rows[i % 4][i / 4] = *value;
+        }
+        Mat4::from_rows(rows)
+    }
+
+    pub fn rows(&self) -> [[f64; 4]; 4] {
+        [
+            [self._11, self._12, self._13, self._14],
+            [self._21, self._22, self._23, self._24],
+            [self._31, self._32, self._33, self._34],
+            [self._41, self._42, self._43, self._44],
+        ]
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        Mat4::from_rows([
+            [1.0, 0.0, 0.0, offset.x],
+            [0.0, 1.0, 0.0, offset.y],
+            [0.0, 0.0, 1.0, offset.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Non-uniform scale along x, y and z
+    pub fn scale(factors: Vec3) -> Mat4 {
+        Mat4::from_rows([
+            [factors.x, 0.0, 0.0, 0.0],
+            [0.0, factors.y, 0.0, 0.0],
+            [0.0, 0.0, factors.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Counter-clockwise rotation (looking down the axis towards the origin), Rodrigues' formula
+    pub fn rotation(axis: Vec3, radians: f64) -> Mat4 {
+        let a = axis.normalized();
+        let (sin, cos) = radians.sin_cos();
+        let t = 1.0 - cos;
+        Mat4::from_rows([
+            [t * a.x * a.x + cos, t * a.x * a.y - sin * a.z, t * a.x * a.z + sin * a.y, 0.0],
+            [t * a.x * a.y + sin * a.z, t * a.y * a.y + cos, t * a.y * a.z - sin * a.x, 0.0],
+            [t * a.x * a.z - sin * a.y, t * a.y * a.z + sin * a.x, t * a.z * a.z + cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Scale first, then rotate, then translate
+    pub fn from_trs(translation: Vec3, rotation: Quat, scale: Vec3) -> Mat4 {
+        Mat4::translation(translation) * rotation.to_mat4() * Mat4::scale(scale)
+    }
+
+    // Splits an affine matrix into from_trs parts. Shear is lost, and mirroring ends up as a negative x scale.
+    // None for projective or singular matrices.
+    pub fn decompose(&self) -> Option<(Vec3, Quat, Vec3)> {
+        if self._41 != 0.0 || self._42 != 0.0 || self._43 != 0.0 || self._44 == 0.0 {
+            return None;
+        }
+        let m = *self * (1.0 / self._44);
+        let translation = Vec3::new(m._14, m._24, m._34);
+        let columns = [
+            Vec3::new(m._11, m._21, m._31),
+            Vec3::new(m._12, m._22, m._32),
+            Vec3::new(m._13, m._23, m._33),
+        ];
+        let mut scale = Vec3::new(columns[0].length(), columns[1].length(), columns[2].length());
+        if scale.x < 1e-12 || scale.y < 1e-12 || scale.z < 1e-12 {
+            return None;
+        }
+        if columns[0].dot(columns[1].cross(columns[2])) < 0.0 {
+            scale.x = -scale.x;
+        }
+        let (x, y, z) = (columns[0] / scale.x, columns[1] / scale.y, columns[2] / scale.z);
+        let rotation = Mat4::from_rows([
+            [x.x, y.x, z.x, 0.0],
+            [x.y, y.y, z.y, 0.0],
+            [x.z, y.z, z.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Some((translation, Quat::from_mat4(&rotation), scale))
+    }
+
+    // World to view matrix, right handed: the camera looks down -z with +y up
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Mat4 {
+        let w = (eye - target).normalized();
+        let u = up.cross(w).normalized();
+        let v = w.cross(u);
+        Mat4::from_rows([
+            [u.x, u.y, u.z, -u.dot(eye)],
+            [v.x, v.y, v.z, -v.dot(eye)],
+            [w.x, w.y, w.z, -w.dot(eye)],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // OpenGL style projection to clip space, z maps near..far to -1..1
+    pub fn perspective(vertical_fov_radians: f64, aspect_ratio: f64, near: f64, far: f64) -> Mat4 {
+        let f = 1.0 / (vertical_fov_radians / 2.0).tan();
+        Mat4::from_rows([
+            [f / aspect_ratio, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, (far + near) / (near - far), 2.0 * far * near / (near - far)],
+            [0.0, 0.0, -1.0, 0.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let rows = self.rows();
+        let mut transposed = [[0.0; 4]; 4];
+        for (i, row) in rows.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                transposed[j][i] = *value;
+            }
+        }
+        Mat4::from_rows(transposed)
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let m = self.rows();
+        let mut determinant = 0.0;
+        for (j, value) in m[0].iter().enumerate() {
+            determinant += value * Self::cofactor(&m, 0, j);
+        }
+        determinant
+    }
+
+    // None for singular matrices
+    pub fn inverse(&self) -> Option<Mat4> {
+        let m = self.rows();
+        let determinant = self.determinant();
+        // Relative to the largest determinant columns of these lengths could have (Hadamard's bound),
+        // so a uniform scale of 1e-4 counts as invertible as much as a scale of 1
+        let bound: f64 = (0..4).map(|j| m.iter().map(|row| row[j] * row[j]).sum::<f64>().sqrt()).product();
+        if !determinant.is_finite() || determinant.abs() <= 1e-12 * bound {
+            return None;
+        }
+        // inverse = adjugate / determinant, the adjugate is the transposed cofactor matrix
+        let mut inverse = [[0.0; 4]; 4];
+        for (i, row) in inverse.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = Self::cofactor(&m, j, i) / determinant;
+            }
+        }
+        Some(Mat4::from_rows(inverse))
+    }
+
+    fn cofactor(m: &[[f64; 4]; 4], row: usize, column: usize) -> f64 {
+        let mut minor = [[0.0; 3]; 3];
+        let mut r = 0;
+        for (i, source_row) in m.iter().enumerate() {
+            if i == row {
+                continue;
+            }
+            let mut c = 0;
+            for (j, value) in source_row.iter().enumerate() {
+                if j == column {
+                    continue;
+                }
+                minor[r][c] = *value;
+                c += 1;
+            }
+            r += 1;
+        }
+        let minor_determinant = minor[0][0] * (minor[1][1] * minor[2][2] - minor[1][2] * minor[2][1])
+            - minor[0][1] * (minor[1][0] * minor[2][2] - minor[1][2] * minor[2][0])
+            + minor[0][2] * (minor[1][0] * minor[2][1] - minor[1][1] * minor[2][0]);
+        if (row + column).is_multiple_of(2) { minor_determinant } else { -minor_determinant }
+    }
+
+    // w = 1, divides by the resulting w for projective matrices
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let x = self._11 * p.x + self._12 * p.y + self._13 * p.z + self._14;
+        let y = self._21 * p.x + self._22 * p.y + self._23 * p.z + self._24;
+        let z = self._31 * p.x + self._32 * p.y + self._33 * p.z + self._34;
+        let w = self._41 * p.x + self._42 * p.y + self._43 * p.z + self._44;
+        if w == 1.0 || w == 0.0 { Vec3::new(x, y, z) } else { Vec3::new(x / w, y / w, z / w) }
+    }
+
+    // w = 0, so translation doesn't apply
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self._11 * v.x + self._12 * v.y + self._13 * v.z,
+            self._21 * v.x + self._22 * v.y + self._23 * v.z,
+            self._31 * v.x + self._32 * v.y + self._33 * v.z,
+        )
+    }
+
+    // Matrix for normals, the inverse transpose of the upper 3x3
+    pub fn normal_matrix(&self) -> Option<Mat4> {
+        let mut linear = self.rows();
+        for row in linear.iter_mut() {
+            row[3] = 0.0;
+        }
+        linear[3] = [0.0, 0.0, 0.0, 1.0];
+        Some(Mat4::from_rows(linear).inverse()?.transpose())
+    }
 }
 
+impl std::ops::Mul<f64> for Mat4 {
+    type Output = Mat4;
+    fn mul(self, rhs: f64) -> Mat4 {
+        Mat4::from_rows(self.rows().map(|row| row.map(|value| value * rhs)))
+    }
+}
 
 impl std::ops::Mul<Mat4> for Mat4 {
     type Output = Mat4;
@@ -78,4 +301,104 @@ impl std::ops::Mul<Mat4> for Mat4 {
             _44: self._41 * rhs._14 + self._42 * rhs._24 + self._43 * rhs._34 + self._44 * rhs._44,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Mat4, b: Mat4, tolerance: f64) {
+        for (row_a, row_b) in a.rows().iter().zip(b.rows().iter()) {
+            for (x, y) in row_a.iter().zip(row_b) {
+                assert!((x - y).abs() < tolerance, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    fn affine() -> Mat4 {
+        Mat4::translation(Vec3::new(1.0, -2.0, 3.0))
+            * Mat4::rotation(Vec3::new(1.0, 2.0, 3.0), 0.7)
+            * Mat4::scale(Vec3::new(2.0, 0.5, 3.0))
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let matrix = affine();
+        let inverse = matrix.inverse().unwrap();
+        assert_close(matrix * inverse, Mat4::IDENTITY, 1e-12);
+        assert_close(inverse * matrix, Mat4::IDENTITY, 1e-12);
+        let point = Vec3::new(0.3, 4.0, -1.5);
+        assert!((inverse.transform_point(matrix.transform_point(point)) - point).length() < 1e-12);
+        assert!((matrix.determinant() - 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn inverse_is_relative_to_scale() {
+        for scale in [1e-4, 1e-8, 1e4] {
+            let matrix = Mat4::scale(Vec3::new(scale, scale, scale));
+            assert_close(matrix * matrix.inverse().unwrap(), Mat4::IDENTITY, 1e-9);
+        }
+        assert!(Mat4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+        let rows = [[1.0, 2.0, 3.0, 0.0], [2.0, 4.0, 6.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+        assert!(Mat4::from_rows(rows).inverse().is_none());
+        assert!(Mat4::new(f64::NAN).inverse().is_none());
+    }
+
+    #[test]
+    fn vectors_ignore_translation() {
+        let matrix = Mat4::translation(Vec3::new(5.0, 6.0, 7.0));
+        let v = matrix.transform_vector(Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!((v.x, v.y, v.z), (1.0, 2.0, 3.0));
+        let p = matrix.transform_point(Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!((p.x, p.y, p.z), (6.0, 8.0, 10.0));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let matrix = affine();
+        let normal_matrix = matrix.normal_matrix().unwrap();
+        // The plane through the origin with this normal
+        let normal = Vec3::new(1.0, 1.0, 0.0);
+        let tangent = Vec3::new(1.0, -1.0, 5.0);
+        let dot = normal_matrix.transform_vector(normal).dot(matrix.transform_vector(tangent));
+        assert!(dot.abs() < 1e-12);
+    }
+
+    #[test]
+    fn rotation_is_counter_clockwise() {
+        let v = Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), std::f64::consts::FRAC_PI_2).transform_vector(Vec3::new(1.0, 0.0, 0.0));
+        assert!((v - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn look_at_puts_the_target_down_negative_z() {
+        let view = Mat4::look_at(Vec3::new(1.0, 2.0, 3.0), Vec3::new(-4.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 0.0));
+        let target = view.transform_point(Vec3::new(-4.0, 0.0, 1.0));
+        assert!(target.x.abs() < 1e-12 && target.y.abs() < 1e-12 && target.z < 0.0);
+        let eye = view.transform_point(Vec3::new(1.0, 2.0, 3.0));
+        assert!(eye.length() < 1e-12);
+    }
+
+    #[test]
+    fn decompose_undoes_from_trs() {
+        let translation = Vec3::new(1.0, -2.0, 3.0);
+        let rotation = Quat::from_axis_angle(Vec3::new(1.0, 2.0, 3.0), 2.5);
+        for scale in [Vec3::new(2.0, 0.5, 3.0), Vec3::new(-2.0, 0.5, 3.0)] {
+            let matrix = Mat4::from_trs(translation, rotation, scale);
+            let (t, r, s) = matrix.decompose().unwrap();
+            assert!((t - translation).length() < 1e-12);
+            assert!((s - scale).length() < 1e-12);
+            assert_close(Mat4::from_trs(t, r, s), matrix, 1e-12);
+        }
+        assert!(Mat4::scale(Vec3::new(1.0, 0.0, 1.0)).decompose().is_none());
+        assert!(Mat4::perspective(1.0, 1.0, 0.1, 10.0).decompose().is_none());
+    }
+
+    #[test]
+    fn column_major_is_the_transpose_of_rows() {
+        let values: [f64; 16] = std::array::from_fn(|i| i as f64);
+        let matrix = Mat4::from_column_major(values);
+        assert_eq!(matrix.rows()[0], [0.0, 4.0, 8.0, 12.0]);
+        assert_eq!(matrix.transpose().rows()[0], [0.0, 1.0, 2.0, 3.0]);
+    }
+}
@@ -13,6 +13,9 @@ pub enum DrawMode {
 pub struct Mesh {
     pub vertices: Vec<Vec3>,
     pub normals: Vec<Vec3>,
+    // Optional, one per vertex when present
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Color>,
     pub indices: Vec<usize>,
 
     pub position: Vec3,
@@ -31,6 +34,8 @@ impl Mesh {
         Self {
             vertices: vertices,
             normals: normals,
+            uvs: Vec::new(),
+            colors: Vec::new(),
             indices: indices,
             position: Vec3::new(0.0, 0.0, 0.0),
             rotation: Vec3::new(0.0, 0.0, 0.0),
@@ -75,7 +80,7 @@ impl Mesh {
 
     pub fn rasterize(&self, img: &mut Image, draw_mode: DrawMode, draw_normals: bool) {
         if draw_normals {
-            for i in 0..self.indices.len() {
+            for i in 0..self.vertices.len() {
                 let point = self.vertices[i].scaled_non_uniform(self.scale).rotated(self.rotation).translated(self.position);
                 let normal = self.normals[i].scaled_non_uniform(self.scale).rotated(self.rotation).translated(self.position).normalized();
                 img.draw_line(point, point + normal, self.color, 1.0, LineType::Antialiased);
@@ -83,16 +88,16 @@ impl Mesh {
         }
         match draw_mode {
             DrawMode::Points => {
-                for i in 0..self.indices.len() {
+                for i in 0..self.vertices.len() {
                     let point = self.vertices[i].scaled_non_uniform(self.scale).rotated(self.rotation).translated(self.position);
                     img.draw_point(point, self.color, 1.0, 1.0, PointType::Square);
                 }
             }
             DrawMode::Lines => {
                 for i in (0..self.indices.len()).step_by(3) {
-                    let index1 = i;
-                    let index2 = i + 1;
-                    let index3 = i + 2;
+                    let index1 = self.indices[i];
+                    let index2 = self.indices[i + 1];
+                    let index3 = self.indices[i + 2];
                     let point1 = self.vertices[index1].scaled_non_uniform(self.scale).rotated(self.rotation).translated(self.position);
                     let point2 = self.vertices[index2].scaled_non_uniform(self.scale).rotated(self.rotation).translated(self.position);
                     let point3 = self.vertices[index3].scaled_non_uniform(self.scale).rotated(self.rotation).translated(self.position);
@@ -103,9 +108,9 @@ impl Mesh {
             },
             DrawMode::Triangles => {
                 for i in (0..self.indices.len()).step_by(3) {
-                    let index1 = i;
-                    let index2 = i + 1;
-                    let index3 = i + 2;
+                    let index1 = self.indices[i];
+                    let index2 = self.indices[i + 1];
+                    let index3 = self.indices[i + 2];
                     let point1 = self.vertices[index1].scaled_non_uniform(self.scale).rotated(self.rotation).translated(self.position);
                     let point2 = self.vertices[index2].scaled_non_uniform(self.scale).rotated(self.rotation).translated(self.position);
                     let point3 = self.vertices[index3].scaled_non_uniform(self.scale).rotated(self.rotation).translated(self.position);
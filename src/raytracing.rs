@@ -6,6 +6,12 @@ pub mod implicits;
 pub mod material;
 pub mod aabb;
 pub mod bvh;
+pub mod flat_bvh;
 pub mod texture;
+pub mod animation;
+pub mod instancing;
+pub mod csg;
+pub mod sdf;
+pub mod point_cloud;
 
 
@@ -48,16 +48,44 @@ impl AABB {
     fn pad_to_minimums(&mut self) {
         let delta = 0.0001;
         if self.x.size() < delta {
-            self.x.expand(delta);
+            self.x = self.x.expand(delta);
         }
         if self.y.size() < delta {
-            self.y.expand(delta);
+            self.y = self.y.expand(delta);
         }
         if self.z.size() < delta {
-            self.z.expand(delta);
+            self.z = self.z.expand(delta);
         }
     }
 
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (x, y, z) = (self.x, self.y, self.z);
+        [
+            Vec3::new(x.lower_bound, y.lower_bound, z.lower_bound),
+            Vec3::new(x.upper_bound, y.lower_bound, z.lower_bound),
+            Vec3::new(x.lower_bound, y.upper_bound, z.lower_bound),
+            Vec3::new(x.upper_bound, y.upper_bound, z.lower_bound),
+            Vec3::new(x.lower_bound, y.lower_bound, z.upper_bound),
+            Vec3::new(x.upper_bound, y.lower_bound, z.upper_bound),
+            Vec3::new(x.lower_bound, y.upper_bound, z.upper_bound),
+            Vec3::new(x.upper_bound, y.upper_bound, z.upper_bound),
+        ]
+    }
+
+    // other moved by every offset inside this box
+    pub fn swept(&self, other: AABB) -> Self {
+        Self {
+            x: Interval::new(self.x.lower_bound + other.x.lower_bound, self.x.upper_bound + other.x.upper_bound),
+            y: Interval::new(self.y.lower_bound + other.y.lower_bound, self.y.upper_bound + other.y.upper_bound),
+            z: Interval::new(self.z.lower_bound + other.z.lower_bound, self.z.upper_bound + other.z.upper_bound),
+        }
+    }
+
+    // Infinite along some axis, like a Plane. An EMPTY box doesn't count.
+    pub fn is_unbounded(&self) -> bool {
+        [self.x, self.y, self.z].iter().any(|i| i.lower_bound == f64::NEG_INFINITY || i.upper_bound == f64::INFINITY)
+    }
+
     pub fn axis_interval(&self, n: i32) -> &Interval {
         if n == 1 {
             &self.y
@@ -82,6 +110,18 @@ impl AABB {
         true
     }
 
+    // The part of ray_t inside the box, None if the ray misses it
+    pub fn clip(&self, ray: Ray, ray_t: Interval) -> Option<Interval> {
+        let mut ray_t = ray_t;
+        if Self::axis_overlap_check(ray.origin.x, ray.direction.x, self.x, &mut ray_t)
+            && Self::axis_overlap_check(ray.origin.y, ray.direction.y, self.y, &mut ray_t)
+            && Self::axis_overlap_check(ray.origin.z, ray.direction.z, self.z, &mut ray_t) {
+            Some(ray_t)
+        } else {
+            None
+        }
+    }
+
     #[inline]
     fn axis_overlap_check(ray_pos: f64, ray_dir: f64, axis: Interval, ray_t: &mut Interval) -> bool{
         if ray_dir == 0.0 {
@@ -116,6 +156,23 @@ impl AABB {
         ray_t.upper_bound >= ray_t.lower_bound
     }
 
+    // 0 for an EMPTY box
+    pub fn surface_area(&self) -> f64 {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        if x < 0.0 || y < 0.0 || z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    pub fn center(&self) -> Vec3 {
+        Vec3::new(
+            (self.x.lower_bound + self.x.upper_bound) / 2.0,
+            (self.y.lower_bound + self.y.upper_bound) / 2.0,
+            (self.z.lower_bound + self.z.upper_bound) / 2.0,
+        )
+    }
+
     pub fn longest_axis(&self) -> usize {
         if self.x.size() > self.y.size() {
             if self.x.size() > self.z.size() {
@@ -0,0 +1,399 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::camera::Camera;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::ray::Ray;
+use crate::vector::{Quat, Vec3};
+use std::ops::{Add, Mul, Sub};
+use std::sync::Arc;
+
+// Animation time is the same clock as Ray::time, in seconds.
+// Camera::render_frames points the shutter at the frame being rendered,
+// so anything that reads ray.time (animated transforms, moving spheres) follows along.
+// Note that Sphere::new_moving moves between its two centers over time 0..1
+// and keeps going past that.
+
+pub trait Animatable: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f64, Output = Self> {
+    // Used by Linear segments
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+
+    // Applied to every sampled value, Bezier and Catmull-Rom blend componentwise
+    fn settle(self) -> Self {
+        self
+    }
+}
+
+impl Animatable for f64 {}
+impl Animatable for Vec3 {}
+
+// Linear keys slerp. For curved segments the handles and neighbouring keys
+// should be on the same side as the key (q and -q are the same rotation).
+impl Animatable for Quat {
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self.slerp(other, t)
+    }
+
+    fn settle(self) -> Self {
+        self.normalized()
+    }
+}
+
+// Interpolation used for the segment that starts at a keyframe
+#[derive(Copy, Clone, Debug)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    Bezier,
+    CatmullRom,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Keyframe<T: Animatable> {
+    pub time: f64,
+    pub value: T,
+    // Bezier control points, in value space.
+    // The segment between two keys uses this key's out_handle and the next key's in_handle.
+    pub in_handle: T,
+    pub out_handle: T,
+    pub interpolation: Interpolation,
+}
+
+impl<T: Animatable> Keyframe<T> {
+    pub fn new(time: f64, value: T, interpolation: Interpolation) -> Self {
+        Self {
+            time,
+            value,
+            in_handle: value,
+            out_handle: value,
+            interpolation,
+        }
+    }
+
+    pub fn bezier(time: f64, value: T, in_handle: T, out_handle: T) -> Self {
+        Self {
+            time,
+            value,
+            in_handle,
+            out_handle,
+            interpolation: Interpolation::Bezier,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Track<T: Animatable> {
+    pub keys: Vec<Keyframe<T>>,
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new() -> Self {
+        Self { keys: vec![] }
+    }
+
+    // Keeps the keys sorted by time
+    pub fn add(&mut self, key: Keyframe<T>) {
+        let index = self.keys.partition_point(|k| k.time <= key.time);
+        self.keys.insert(index, key);
+    }
+
+    pub fn with_key(mut self, key: Keyframe<T>) -> Self {
+        self.add(key);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn time_range(&self) -> Option<Interval> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        Some(Interval::new(first.time, last.time))
+    }
+
+    // Holds the first and last values outside of the keyed range.
+    pub fn sample(&self, time: f64) -> Option<T> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        if time <= first.time {
+            return Some(first.value.settle());
+        }
+        if time >= last.time {
+            return Some(last.value.settle());
+        }
+        let i = self.keys.partition_point(|k| k.time <= time) - 1;
+        let k1 = &self.keys[i];
+        let k2 = &self.keys[i + 1];
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 { (time - k1.time) / span } else { 1.0 };
+        let value = match k1.interpolation {
+            Interpolation::Step => k1.value,
+            Interpolation::Linear => k1.value.lerp(k2.value, t),
+            Interpolation::Bezier => bezier(k1.value, k1.out_handle, k2.in_handle, k2.value, t),
+            Interpolation::CatmullRom => {
+                let p0 = if i > 0 { self.keys[i - 1].value } else { k1.value };
+                let p3 = if i + 2 < self.keys.len() { self.keys[i + 2].value } else { k2.value };
+                catmull_rom(p0, k1.value, k2.value, p3, t)
+            }
+        };
+        Some(value.settle())
+    }
+}
+
+impl Track<Vec3> {
+    // Box around every value the track takes. Curved segments stay inside the box of their
+    // Bezier control points, Catmull-Rom segments are converted to Bezier for that.
+    pub fn bounds(&self) -> Option<AABB> {
+        let first = self.keys.first()?;
+        let mut bbox = AABB::from_corners(first.value, first.value);
+        let mut add = |point: Vec3| bbox = AABB::from_aabbs(bbox, AABB::from_corners(point, point));
+        for (i, pair) in self.keys.windows(2).enumerate() {
+            let (k1, k2) = (&pair[0], &pair[1]);
+            add(k2.value);
+            match k1.interpolation {
+                Interpolation::Bezier => {
+                    add(k1.out_handle);
+                    add(k2.in_handle);
+                }
+                Interpolation::CatmullRom => {
+                    let p0 = if i > 0 { self.keys[i - 1].value } else { k1.value };
+                    let p3 = if i + 2 < self.keys.len() { self.keys[i + 2].value } else { k2.value };
+                    add(k1.value + (k2.value - p0) / 6.0);
+                    add(k2.value - (p3 - k1.value) / 6.0);
+                }
+                Interpolation::Step | Interpolation::Linear => {}
+            }
+        }
+        Some(bbox)
+    }
+}
+
+fn bezier<T: Animatable>(p0: T, p1: T, p2: T, p3: T, t: f64) -> T {
+    let s = 1.0 - t;
+    p0 * (s * s * s) + p1 * (3.0 * s * s * t) + p2 * (3.0 * s * t * t) + p3 * (t * t * t)
+}
+
+// Uniform Catmull-Rom spline through p1 and p2
+fn catmull_rom<T: Animatable>(p0: T, p1: T, p2: T, p3: T, t: f64) -> T {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let m1 = (p2 - p0) * 0.5;
+    let m2 = (p3 - p1) * 0.5;
+    p1 * (2.0 * t3 - 3.0 * t2 + 1.0)
+        + m1 * (t3 - 2.0 * t2 + t)
+        + p2 * (-2.0 * t3 + 3.0 * t2)
+        + m2 * (t3 - t2)
+}
+
+// Empty tracks leave the corresponding camera parameter alone.
+#[derive(Clone, Debug)]
+pub struct CameraAnimation {
+    pub look_from: Track<Vec3>,
+    pub look_at: Track<Vec3>,
+    pub field_of_view: Track<f64>,
+    pub focus_dist: Track<f64>,
+}
+
+impl CameraAnimation {
+    pub fn new() -> Self {
+        Self {
+            look_from: Track::new(),
+            look_at: Track::new(),
+            field_of_view: Track::new(),
+            focus_dist: Track::new(),
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera, time: f64) {
+        if let Some(look_from) = self.look_from.sample(time) {
+            camera.look_from = look_from;
+        }
+        if let Some(look_at) = self.look_at.sample(time) {
+            camera.look_at = look_at;
+        }
+        if let Some(field_of_view) = self.field_of_view.sample(time) {
+            camera.field_of_view = field_of_view;
+        }
+        if let Some(focus_dist) = self.focus_dist.sample(time) {
+            camera.focus_dist = focus_dist;
+        }
+    }
+
+    pub fn moves(&self) -> bool {
+        !self.look_from.is_empty() || !self.look_at.is_empty()
+    }
+}
+
+// Translation and rotation, both keyframed. The rotation is the `rotation` track
+// applied after a rotation about the y axis (in degrees, like RotateY).
+// The object is rotated first, then translated.
+pub struct AnimatedTransform {
+    object: Arc<dyn Hittable>,
+    translation: Track<Vec3>,
+    rotation_y: Track<f64>,
+    rotation: Track<Quat>,
+    bbox: AABB,
+}
+
+impl AnimatedTransform {
+    pub fn new(object: Arc<dyn Hittable>, translation: Track<Vec3>, rotation_y: Track<f64>) -> Self {
+        let mut animated = Self {
+            object,
+            translation,
+            rotation_y,
+            rotation: Track::new(),
+            bbox: AABB::EMPTY,
+        };
+        animated.bbox = animated.animation_bounding_box();
+        animated
+    }
+
+    pub fn with_rotation(mut self, rotation: Track<Quat>) -> Self {
+        self.rotation = rotation;
+        self.bbox = self.animation_bounding_box();
+        self
+    }
+
+    fn transform_at(&self, time: f64) -> (Vec3, Quat) {
+        let offset = self.translation.sample(time).unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+        let radians = self.rotation_y.sample(time).unwrap_or(0.0).to_radians();
+        let rotation = self.rotation.sample(time).unwrap_or(Quat::IDENTITY);
+        (offset, rotation * Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), radians))
+    }
+
+    // Conservative rather than sampled, so the object can't slip out of its box between samples:
+    // offsets stay inside the translation track's bounds, and a rotating object stays inside the
+    // sphere around its origin that holds its box.
+    fn animation_bounding_box(&self) -> AABB {
+        let object_bbox = self.object.bounding_box();
+        if object_bbox.is_unbounded() {
+            return AABB::UNIVERSE;
+        }
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        let offsets = self.translation.bounds().unwrap_or(AABB::from_corners(zero, zero));
+        let rotated = if self.rotation_y.keys.len() <= 1 && self.rotation.keys.len() <= 1 {
+            let (_, rotation) = self.transform_at(0.0);
+            object_bbox.corners().iter()
+                .map(|&corner| rotation.rotate(corner))
+                .fold(AABB::EMPTY, |bbox, corner| AABB::from_aabbs(bbox, AABB::from_corners(corner, corner)))
+        } else {
+            let radius = object_bbox.corners().iter().map(|corner| corner.length()).fold(0.0, f64::max);
+            AABB::from_corners(Vec3::new(-radius, -radius, -radius), Vec3::new(radius, radius, radius))
+        };
+        offsets.swept(rotated)
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let (offset, rotation) = self.transform_at(ray.time);
+        let inverse = rotation.conjugate();
+        let object_ray = Ray::with_time(
+            inverse.rotate(ray.origin - offset),
+            inverse.rotate(ray.direction),
+            ray.time,
+        );
+
+        if !self.object.first_hit_on_interval(object_ray, interval, hit_record) {
+            return false;
+        }
+
+        hit_record.point = rotation.rotate(hit_record.point) + offset;
+        hit_record.normal = rotation.rotate(hit_record.normal);
+        hit_record.tangent = rotation.rotate(hit_record.tangent);
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::sphere::Sphere;
+    use crate::raytracing::material::Lambertian;
+
+    #[test]
+    fn animated_box_holds_the_object_at_every_time() {
+        let radius = 0.5;
+        let local_center = Vec3::new(3.0, 0.0, 0.0);
+        let sphere = Arc::new(Sphere::new(local_center, radius, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))));
+        // Catmull-Rom overshoots the keys, and the spin swings the sphere around between them
+        let translation = Track::new()
+            .with_key(Keyframe::new(0.0, Vec3::new(0.0, 0.0, 0.0), Interpolation::CatmullRom))
+            .with_key(Keyframe::new(1.0, Vec3::new(5.0, 4.0, 0.0), Interpolation::CatmullRom))
+            .with_key(Keyframe::new(2.0, Vec3::new(0.0, 4.0, 0.0), Interpolation::CatmullRom));
+        let spin = Track::new()
+            .with_key(Keyframe::new(0.0, 0.0, Interpolation::Linear))
+            .with_key(Keyframe::new(2.0, 720.0, Interpolation::Linear));
+        let animated = AnimatedTransform::new(sphere, translation, spin);
+        let bbox = animated.bounding_box();
+        for i in 0..=2000 {
+            let (offset, rotation) = animated.transform_at(i as f64 / 1000.0);
+            let center = rotation.rotate(local_center) + offset;
+            for axis in 0..3 {
+                assert!(bbox.axis_interval(axis as i32).contains(center[axis] - radius), "time {}", i);
+                assert!(bbox.axis_interval(axis as i32).contains(center[axis] + radius), "time {}", i);
+            }
+        }
+    }
+    #[test]
+    fn track_sampling() {
+        let track = Track::new()
+            .with_key(Keyframe::new(2.0, 10.0, Interpolation::Step))
+            .with_key(Keyframe::new(0.0, 0.0, Interpolation::Linear))
+            .with_key(Keyframe::new(3.0, 20.0, Interpolation::Linear));
+        assert_eq!(track.time_range().map(|range| (range.lower_bound, range.upper_bound)), Some((0.0, 3.0)));
+        // Held before the first and after the last key
+        assert_eq!(track.sample(-1.0), Some(0.0));
+        assert_eq!(track.sample(5.0), Some(20.0));
+        assert_eq!(track.sample(1.0), Some(5.0));
+        assert_eq!(track.sample(2.5), Some(10.0));
+        assert_eq!(Track::<f64>::new().sample(1.0), None);
+    }
+
+    #[test]
+    fn curves_pass_through_their_keys() {
+        let catmull_rom = Track::new()
+            .with_key(Keyframe::new(0.0, 0.0, Interpolation::CatmullRom))
+            .with_key(Keyframe::new(1.0, 1.0, Interpolation::CatmullRom))
+            .with_key(Keyframe::new(2.0, 4.0, Interpolation::CatmullRom));
+        assert!((catmull_rom.sample(1.0).unwrap() - 1.0).abs() < 1e-12);
+        assert!((catmull_rom.sample(1.0 - 1e-9).unwrap() - 1.0).abs() < 1e-6);
+
+        let bezier = Track::new()
+            .with_key(Keyframe::bezier(0.0, 0.0, 0.0, 1.0))
+            .with_key(Keyframe::bezier(1.0, 0.0, 1.0, 0.0));
+        // Both handles at 1, halfway is 3/4 of the way up
+        assert!((bezier.sample(0.5).unwrap() - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rotation_tracks_slerp() {
+        let track = Track::new()
+            .with_key(Keyframe::new(0.0, Quat::IDENTITY, Interpolation::Linear))
+            .with_key(Keyframe::new(1.0, Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 2.0), Interpolation::Linear));
+        let (axis, angle) = track.sample(0.25).unwrap().to_axis_angle();
+        assert!((angle - 0.5).abs() < 1e-12);
+        assert!((axis - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn translation_bounds_hold_every_sample() {
+        let track = Track::new()
+            .with_key(Keyframe::new(0.0, Vec3::new(0.0, 0.0, 0.0), Interpolation::CatmullRom))
+            .with_key(Keyframe::new(1.0, Vec3::new(4.0, 1.0, 0.0), Interpolation::Bezier))
+            .with_key(Keyframe::bezier(2.0, Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 9.0, -3.0), Vec3::new(0.0, 2.0, 0.0)));
+        let bounds = track.bounds().unwrap();
+        for i in 0..=200 {
+            let value = track.sample(i as f64 / 100.0).unwrap();
+            for axis in 0..3 {
+                assert!(bounds.axis_interval(axis as i32).contains(value[axis]));
+            }
+        }
+    }
+}
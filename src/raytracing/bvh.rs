@@ -4,64 +4,411 @@ use crate::raytracing::interval::*;
 use crate::raytracing::ray::*;
 use crate::image::Image;
 use crate::raytracing::camera::Camera;
+use std::fmt;
 use std::sync::Arc;
 use crate::random::*;
+use rayon::prelude::*;
 
 pub struct BVHNode {
     pub bbox: AABB,
     pub left: Arc<dyn Hittable>,
     pub right: Arc<dyn Hittable>,
+    // Objects without a finite bounding box (infinite planes), tested on every ray.
+    // Only the root node built by new() has any.
+    pub unbounded: Vec<Arc<dyn Hittable>>,
 }
 
-impl BVHNode {
-    pub fn new(list: &mut HittableList) -> Self {
-        let len = list.hittables.len();
-        Self::new_from_indices(&mut list.hittables, 0, len)
+// Builders hand nodes with at least this many objects to the thread pool,
+// below it the bookkeeping costs more than the work
+pub const PARALLEL_BUILD_SIZE: usize = 4096;
+
+// Union of the objects' boxes
+pub fn objects_bbox(objects: &[Arc<dyn Hittable>]) -> AABB {
+    union_of(objects, |object| object.bounding_box())
+}
+
+fn union_of(objects: &[Arc<dyn Hittable>], bbox: impl Fn(&Arc<dyn Hittable>) -> AABB + Sync + Send) -> AABB {
+    if objects.len() >= PARALLEL_BUILD_SIZE {
+        objects.par_iter().map(bbox).reduce(|| AABB::EMPTY, AABB::from_aabbs)
+    } else {
+        objects.iter().fold(AABB::EMPTY, |union, object| AABB::from_aabbs(union, bbox(object)))
+    }
+}
+
+// Box and object count of every bin, the bins along x, then y, then z
+struct Bins {
+    boxes: Vec<AABB>,
+    counts: Vec<usize>,
+}
+
+impl Bins {
+    fn new(bins: usize) -> Self {
+        Self { boxes: vec![AABB::EMPTY; 3 * bins], counts: vec![0; 3 * bins] }
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        for bin in 0..self.boxes.len() {
+            self.boxes[bin] = AABB::from_aabbs(self.boxes[bin], other.boxes[bin]);
+            self.counts[bin] += other.counts[bin];
+        }
+        self
+    }
+}
+
+// How BVHNode divides its objects between the two children
+#[derive(Copy, Clone, Debug)]
+pub enum BVHSplit {
+    // Sort by box minimum along the longest axis and cut at the middle object
+    Median,
+    // Binned surface area heuristic
+    SurfaceArea(SAHOptions),
+}
+
+impl BVHSplit {
+    // Reorders objects (bbox is their union) into the two children's runs and returns where
+    // the second one starts, or None when they should stay together in one leaf
+    pub fn partition(&self, objects: &mut [Arc<dyn Hittable>], bbox: AABB) -> Option<usize> {
+        match self {
+            BVHSplit::Median => {
+                if objects.len() <= 2 {
+                    return None;
+                }
+                let axis = bbox.longest_axis();// random_int(0..2);
+                let comparator = if axis == 0 {
+                    BVHNode::box_x_compare
+                } else if axis == 1 {
+                    BVHNode::box_y_compare
+                } else {
+                    BVHNode::box_z_compare
+                };
+                // Only which side each object lands on matters, the children order their own halves
+                let middle = objects.len() / 2;
+                objects.select_nth_unstable_by(middle, comparator);
+                Some(middle)
+            }
+            BVHSplit::SurfaceArea(options) => Self::sah_partition(objects, bbox, options),
+        }
     }
 
-    pub fn new_from_indices(objects: &mut Vec<Arc<dyn Hittable>>, start: usize, end: usize) -> Self {
-        let mut bbox = AABB::EMPTY;
-        for i in start..end {
-            bbox = AABB::from_aabbs(bbox, objects[i].bounding_box());
+    fn sah_partition(objects: &mut [Arc<dyn Hittable>], bbox: AABB, options: &SAHOptions) -> Option<usize> {
+        let area = bbox.surface_area();
+        let count = objects.len();
+        if count <= 1 {
+            return None;
         }
-        let axis = bbox.longest_axis();// random_int(0..2);
-        let comparator = if axis == 0 {
-            Self::box_x_compare
-        } else if axis == 1 {
-            Self::box_y_compare
+
+        let centers = union_of(objects, |object| {
+            let center = object.bounding_box().center();
+            AABB::from_corners(center, center)
+        });
+        let bins = options.bins.max(2);
+        let bin_index = |center: f64, axis: usize| {
+            let extent = centers.axis_interval(axis as i32);
+            let offset = (center - extent.lower_bound) / extent.size();
+            ((offset * bins as f64) as usize).min(bins - 1)
+        };
+        let bin_object = |mut binned: Bins, object: &Arc<dyn Hittable>| {
+            let bbox = object.bounding_box();
+            let center = bbox.center();
+            for axis in 0..3 {
+                let bin = axis * bins + bin_index(center[axis], axis);
+                binned.boxes[bin] = AABB::from_aabbs(binned.boxes[bin], bbox);
+                binned.counts[bin] += 1;
+            }
+            binned
+        };
+        let binned = if count >= PARALLEL_BUILD_SIZE {
+            objects.par_iter().fold(|| Bins::new(bins), bin_object).reduce(|| Bins::new(bins), Bins::merge)
         } else {
-            Self::box_z_compare
+            objects.iter().fold(Bins::new(bins), bin_object)
         };
 
-        let span = end - start;
-        let (left, right) = if span == 1 {
-            (objects[start].clone(), objects[start].clone())
-        } else if span == 2 {
-            (objects[start].clone(), objects[start + 1].clone())
+        // Cheapest cut as (axis, first bin of the right side, cost)
+        let mut best: Option<(usize, usize, f64)> = None;
+        for axis in 0..3 {
+            if centers.axis_interval(axis as i32).size() <= 0.0 {
+                continue;
+            }
+            let bin_boxes = &binned.boxes[axis * bins..(axis + 1) * bins];
+            let bin_counts = &binned.counts[axis * bins..(axis + 1) * bins];
+            // Area and count of everything from bin i on, then sweep the left side up to meet it
+            let mut right_area = vec![0.0; bins];
+            let mut right_count = vec![0usize; bins];
+            let mut right_box = AABB::EMPTY;
+            let mut right_total = 0;
+            for bin in (1..bins).rev() {
+                right_box = AABB::from_aabbs(right_box, bin_boxes[bin]);
+                right_total += bin_counts[bin];
+                right_area[bin] = right_box.surface_area();
+                right_count[bin] = right_total;
+            }
+            let mut left_box = AABB::EMPTY;
+            let mut left_count = 0;
+            for bin in 1..bins {
+                left_box = AABB::from_aabbs(left_box, bin_boxes[bin - 1]);
+                left_count += bin_counts[bin - 1];
+                if left_count == 0 || right_count[bin] == 0 {
+                    continue;
+                }
+                let cost = options.traversal_cost
+                    + options.intersection_cost * (left_box.surface_area() * left_count as f64 + right_area[bin] * right_count[bin] as f64) / area;
+                if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                    best = Some((axis, bin, cost));
+                }
+            }
+        }
+
+        let leaf_cost = options.intersection_cost * count as f64;
+        match best {
+            Some((_, _, cost)) if count <= options.max_leaf_size && cost >= leaf_cost => None,
+            Some((axis, split_bin, _)) => {
+                let mut middle = 0;
+                for i in 0..count {
+                    if bin_index(objects[i].bounding_box().center()[axis], axis) < split_bin {
+                        objects.swap(i, middle);
+                        middle += 1;
+                    }
+                }
+                Some(middle)
+            }
+            // All centers in one spot, binning can't separate them
+            None if count > options.max_leaf_size => Some(count / 2),
+            None => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SAHOptions {
+    // Object centers are binned along each axis, the candidate cuts are the bin borders
+    pub bins: usize,
+    // Cost of testing a node's box and of testing one object, only their ratio matters
+    pub traversal_cost: f64,
+    pub intersection_cost: f64,
+    // Up to this many objects stay in one leaf when splitting them doesn't pay off
+    pub max_leaf_size: usize,
+}
+
+impl Default for SAHOptions {
+    fn default() -> Self {
+        Self {
+            bins: 12,
+            traversal_cost: 1.0,
+            intersection_cost: 1.0,
+            max_leaf_size: 4,
+        }
+    }
+}
+
+// Shape of a built tree. Areas are summed unnormalized, see sah_cost.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BVHStats {
+    pub nodes: usize,
+    pub max_depth: usize,
+    // Object tests over all nodes, a median leaf that holds one object twice counts it twice
+    pub object_tests: usize,
+    pub build_seconds: f64,
+    root_area: f64,
+    node_area: f64,
+    object_area: f64,
+}
+
+impl BVHStats {
+    // Expected cost of a ray that hits the root box: every node is entered with probability
+    // area(node) / area(root) and then tests its box and the objects directly under it
+    pub fn sah_cost(&self, traversal_cost: f64, intersection_cost: f64) -> f64 {
+        if self.root_area <= 0.0 {
+            return 0.0;
+        }
+        (traversal_cost * self.node_area + intersection_cost * self.object_area) / self.root_area
+    }
+
+    // Folds in the stats of a subtree built on another thread
+    fn merge(&mut self, other: &BVHStats) {
+        self.nodes += other.nodes;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.object_tests += other.object_tests;
+        self.node_area += other.node_area;
+        self.object_area += other.object_area;
+    }
+
+    fn add_objects(&mut self, area: f64, count: usize) {
+        self.object_tests += count;
+        self.object_area += area * count as f64;
+    }
+}
+
+// Both builders run on the same objects, costs use the SAH options' constants
+pub struct BVHComparison {
+    pub options: SAHOptions,
+    pub median: BVHStats,
+    pub sah: BVHStats,
+}
+
+impl BVHComparison {
+    pub fn new(list: &HittableList, options: SAHOptions) -> Self {
+        Self {
+            options,
+            median: BVHNode::build_with_stats(list, BVHSplit::Median).1,
+            sah: BVHNode::build_with_stats(list, BVHSplit::SurfaceArea(options)).1,
+        }
+    }
+}
+
+impl fmt::Display for BVHComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let row = |f: &mut fmt::Formatter<'_>, name: &str, stats: &BVHStats| {
+            writeln!(
+                f,
+                "{:>6}: SAH cost {:.2}, {} nodes, depth {}, {} object tests, built in {:.3} s",
+                name,
+                stats.sah_cost(self.options.traversal_cost, self.options.intersection_cost),
+                stats.nodes,
+                stats.max_depth,
+                stats.object_tests,
+                stats.build_seconds,
+            )
+        };
+        row(f, "median", &self.median)?;
+        row(f, "SAH", &self.sah)?;
+        let median_cost = self.median.sah_cost(self.options.traversal_cost, self.options.intersection_cost);
+        let sah_cost = self.sah.sah_cost(self.options.traversal_cost, self.options.intersection_cost);
+        if median_cost > 0.0 {
+            write!(f, "SAH tree costs {:.1}% of the median tree", 100.0 * sah_cost / median_cost)
         } else {
-            objects[start..end].sort_by(comparator);
+            write!(f, "No objects, nothing to compare")
+        }
+    }
+}
+
+impl BVHNode {
+    pub fn new(list: &mut HittableList) -> Self {
+        Self::with_split(list, BVHSplit::Median)
+    }
+
+    pub fn with_split(list: &mut HittableList, split: BVHSplit) -> Self {
+        Self::build_with_stats(list, split).0
+    }
+
+    pub fn build_with_stats(list: &HittableList, split: BVHSplit) -> (Self, BVHStats) {
+        let time = std::time::Instant::now();
+        let (mut bounded, unbounded): (Vec<_>, Vec<_>) = list.hittables.iter().cloned()
+            .partition(|object| !object.bounding_box().is_unbounded());
+        let mut stats = BVHStats::default();
+        let mut root = if bounded.is_empty() {
+            let empty: Arc<dyn Hittable> = Arc::new(HittableList::new());
+            Self { bbox: AABB::EMPTY, left: empty.clone(), right: empty, unbounded: vec![] }
+        } else {
+            Self::build(&mut bounded, split, &mut stats, 1)
+        };
+        root.unbounded = unbounded;
+        stats.root_area = root.bbox.surface_area();
+        stats.build_seconds = time.elapsed().as_secs_f64();
+        (root, stats)
+    }
+
+    pub fn new_from_indices(objects: &mut [Arc<dyn Hittable>], start: usize, end: usize) -> Self {
+        Self::build(&mut objects[start..end], BVHSplit::Median, &mut BVHStats::default(), 1)
+    }
+
+    fn build(objects: &mut [Arc<dyn Hittable>], split: BVHSplit, stats: &mut BVHStats, depth: usize) -> Self {
+        let bbox = objects_bbox(objects);
+        stats.nodes += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+        stats.node_area += bbox.surface_area();
 
-            let mid = start + span / 2;
-            let left: Arc<dyn Hittable> = Arc::new(BVHNode::new_from_indices(objects, start, mid));
-            let right: Arc<dyn Hittable> = Arc::new(BVHNode::new_from_indices(objects, mid, end));
-            (left, right)
+        let (left, right) = match split {
+            BVHSplit::Median => Self::median_children(objects, bbox, stats, depth),
+            BVHSplit::SurfaceArea(options) => Self::sah_children(objects, bbox, options, stats, depth),
         };
 
         Self {
             left: left,
             right: right,
             bbox: bbox,
+            unbounded: vec![],
+        }
+    }
+
+    fn median_children(objects: &mut [Arc<dyn Hittable>], bbox: AABB, stats: &mut BVHStats, depth: usize) -> (Arc<dyn Hittable>, Arc<dyn Hittable>) {
+        let Some(middle) = BVHSplit::Median.partition(objects, bbox) else {
+            stats.add_objects(bbox.surface_area(), 2);
+            return (objects[0].clone(), objects[objects.len() - 1].clone());
+        };
+        let (left, right) = objects.split_at_mut(middle);
+        let size = left.len().max(right.len());
+        Self::join(
+            stats,
+            |stats| Arc::new(Self::build(left, BVHSplit::Median, stats, depth + 1)) as Arc<dyn Hittable>,
+            |stats| Arc::new(Self::build(right, BVHSplit::Median, stats, depth + 1)) as Arc<dyn Hittable>,
+            size,
+        )
+    }
+
+    fn sah_children(objects: &mut [Arc<dyn Hittable>], bbox: AABB, options: SAHOptions, stats: &mut BVHStats, depth: usize) -> (Arc<dyn Hittable>, Arc<dyn Hittable>) {
+        let area = bbox.surface_area();
+        let count = objects.len();
+        // Only a root with a single object gets here with one
+        if count == 1 {
+            stats.add_objects(area, 1);
+            return (objects[0].clone(), Arc::new(HittableList::new()));
+        }
+        let Some(middle) = BVHSplit::SurfaceArea(options).partition(objects, bbox) else {
+            // Leaf, its objects go to the two children directly or as lists
+            stats.add_objects(area, count);
+            let (left, right) = objects.split_at(count / 2);
+            return (Self::leaf_child(left), Self::leaf_child(right));
+        };
+        let (left, right) = objects.split_at_mut(middle);
+        let size = left.len().max(right.len());
+        Self::join(
+            stats,
+            |stats| Self::sah_child(left, bbox, options, stats, depth),
+            |stats| Self::sah_child(right, bbox, options, stats, depth),
+            size,
+        )
+    }
+
+    // Builds two subtrees, side by side on the thread pool when either is big enough to be worth it
+    fn join<A: Send, B: Send>(
+        stats: &mut BVHStats,
+        left: impl FnOnce(&mut BVHStats) -> A + Send,
+        right: impl FnOnce(&mut BVHStats) -> B + Send,
+        size: usize,
+    ) -> (A, B) {
+        if size < PARALLEL_BUILD_SIZE {
+            return (left(stats), right(stats));
+        }
+        let mut right_stats = BVHStats::default();
+        let result = rayon::join(|| left(stats), || right(&mut right_stats));
+        stats.merge(&right_stats);
+        result
+    }
+
+    // A side with one object holds it directly instead of a node around it
+    fn sah_child(objects: &mut [Arc<dyn Hittable>], parent_bbox: AABB, options: SAHOptions, stats: &mut BVHStats, depth: usize) -> Arc<dyn Hittable> {
+        if objects.len() == 1 {
+            stats.add_objects(parent_bbox.surface_area(), 1);
+            return objects[0].clone();
         }
+        Arc::new(Self::build(objects, BVHSplit::SurfaceArea(options), stats, depth + 1))
+    }
+
+    fn leaf_child(objects: &[Arc<dyn Hittable>]) -> Arc<dyn Hittable> {
+        if objects.len() == 1 {
+            return objects[0].clone();
+        }
+        let mut list = HittableList::new();
+        for object in objects {
+            list.add(object.clone());
+        }
+        Arc::new(list)
     }
 
     fn box_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis_index: i32) -> std::cmp::Ordering {
         let a_axis_interval = *a.bounding_box().axis_interval(axis_index);
         let b_axis_interval = *b.bounding_box().axis_interval(axis_index);
-        if a_axis_interval.lower_bound < b_axis_interval.lower_bound {
-            std::cmp::Ordering::Less
-        } else {
-            std::cmp::Ordering::Greater
-        }
+        a_axis_interval.lower_bound.total_cmp(&b_axis_interval.lower_bound)
     }
 
     fn box_x_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>) -> std::cmp::Ordering {
@@ -82,18 +429,106 @@ impl BVHNode {
 
 impl Hittable for BVHNode {
     fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        // Unbounded objects first, a hit there shortens the interval for the tree
+        let mut hit_unbounded = false;
+        for object in &self.unbounded {
+            if object.first_hit_on_interval(ray, interval, hit_record) {
+                hit_unbounded = true;
+            }
+        }
         if !self.bbox.hit(ray, interval) {
-            return false;
+            return hit_unbounded;
         }
 
-        let mut interval = interval.clone();
-        let hit_left = self.left.first_hit_on_interval(ray, &mut interval, hit_record);
+        let mut left_interval = interval.clone();
+        let hit_left = self.left.first_hit_on_interval(ray, &mut left_interval, hit_record);
         let mut right_interval = Interval::new(interval.lower_bound, if hit_left { hit_record.t } else { interval.upper_bound });
         let hit_right = self.right.first_hit_on_interval(ray, &mut right_interval, hit_record);
-        hit_left || hit_right
+        // Callers keep searching with the interval, so it has to end at the nearest hit
+        if hit_left || hit_right {
+            interval.upper_bound = hit_record.t;
+        }
+        hit_unbounded || hit_left || hit_right
     }
 
     fn bounding_box(&self) -> AABB {
-        self.bbox
+        if self.unbounded.is_empty() { self.bbox } else { AABB::UNIVERSE }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::plane::Plane;
+    use crate::raytracing::implicits::sphere::Sphere;
+    use crate::raytracing::material::Lambertian;
+    use crate::vector::Vec3;
+
+    fn spheres(count: usize) -> HittableList {
+        let mut list = HittableList::new();
+        let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        for i in 0..count {
+            let center = Vec3::new((i % 7) as f64 * 3.0, (i % 5) as f64 * 2.0, (i / 35) as f64 * 4.0);
+            list.add(Arc::new(Sphere::new(center, 0.5, material.clone())));
+        }
+        list
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<f64> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record.t)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn splits_find_the_same_hits_as_the_plain_list() {
+        let list = spheres(100);
+        let median = BVHNode::new(&mut spheres(100));
+        let sah = BVHNode::with_split(&mut spheres(100), BVHSplit::SurfaceArea(SAHOptions::default()));
+        for x in 0..30 {
+            for y in 0..20 {
+                let ray = Ray::new(Vec3::new(x as f64 * 0.7 - 1.0, y as f64 * 0.5 - 1.0, -10.0), Vec3::new(0.01, 0.02, 1.0));
+                let expected = first_hit(&list, ray);
+                assert_eq!(first_hit(&median, ray), expected);
+                assert_eq!(first_hit(&sah, ray), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn planes_stay_outside_the_tree() {
+        let with_floor = || {
+            let mut list = spheres(20);
+            list.add(Arc::new(Plane::new(Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, 1.0), Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))));
+            list
+        };
+        let bvh = BVHNode::new(&mut with_floor());
+        assert_eq!(bvh.unbounded.len(), 1);
+        assert!(bvh.bounding_box().is_unbounded());
+        let list = with_floor();
+        for x in 0..30 {
+            let ray = Ray::new(Vec3::new(x as f64 * 0.7 - 1.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+            assert_eq!(first_hit(&bvh, ray), first_hit(&list, ray));
+        }
+        // Behind the spheres, only the plane is in front
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(first_hit(&bvh, ray), Some(8.0));
+
+        let mut only_plane = HittableList::new();
+        only_plane.add(Arc::new(Plane::new(Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, 1.0), Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))));
+        assert_eq!(first_hit(&BVHNode::new(&mut only_plane), ray), Some(8.0));
+    }
+
+    #[test]
+    fn sah_is_no_worse_than_median() {
+        let comparison = BVHComparison::new(&spheres(200), SAHOptions::default());
+        let options = comparison.options;
+        assert!(comparison.sah.sah_cost(options.traversal_cost, options.intersection_cost)
+            <= comparison.median.sah_cost(options.traversal_cost, options.intersection_cost));
+    }
+
+    #[test]
+    fn comparison_of_an_empty_list() {
+        let text = BVHComparison::new(&HittableList::new(), SAHOptions::default()).to_string();
+        assert!(!text.contains("NaN") && !text.contains("inf"), "{text}");
+    }
+}
@@ -5,6 +5,8 @@ use crate::raytracing::hittable::*;
 use crate::raytracing::interval::*;
 use crate::raytracing::ray::*;
 use crate::random::*;
+use crate::raytracing::material::Material;
+use crate::raytracing::animation::CameraAnimation;
 use std::sync::{Mutex, Arc};
 use rayon::prelude::*;
 use progress_bar;
@@ -16,6 +18,99 @@ pub enum RayTracingMethod {
     ThreadedPlusSIMD,
 }
 
+// How the shutter opens and closes over the exposure.
+// Box: fully open for the whole interval.
+// Triangle: opens linearly, fully open halfway through, then closes linearly.
+// RollingShutter: each scanline is exposed for `exposure` (a fraction of the interval),
+// with the top row exposed first and the bottom row last.
+#[derive(Copy, Clone, Debug)]
+pub enum ShutterCurve {
+    Box,
+    Triangle,
+    RollingShutter { exposure: f64 },
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CameraPose {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub up: Vec3,
+}
+
+impl CameraPose {
+    pub fn new(look_from: Vec3, look_at: Vec3, up: Vec3) -> Self {
+        Self { look_from, look_at, up }
+    }
+
+    pub fn lerp(&self, other: &CameraPose, t: f64) -> CameraPose {
+        CameraPose {
+            look_from: (1.0 - t) * self.look_from + t * other.look_from,
+            look_at: (1.0 - t) * self.look_at + t * other.look_at,
+            up: (1.0 - t) * self.up + t * other.up,
+        }
+    }
+}
+
+// Equirectangular covers the full sphere around the camera, longitude across the image
+// and latitude down it. eye_offset moves the ray origin sideways along a circle around
+// the camera (negative is left), which gives omni-directional stereo panoramas.
+#[derive(Copy, Clone, Debug)]
+pub enum Projection {
+    Perspective,
+    Equirectangular { eye_offset: f64 },
+}
+
+pub enum StereoMode {
+    Parallel,
+    ToeIn,
+    OmniDirectional,
+}
+
+// Left eye goes on the left or on top
+pub enum StereoLayout {
+    SideBySide,
+    TopBottom,
+}
+
+// Everything get_ray needs for one camera pose
+#[derive(Copy, Clone, Debug)]
+struct CameraFrame {
+    position: Vec3,
+    pixel00_top_left: Vec3,
+    pixel00_center: Vec3,
+    pixel_delta_u: Vec3,
+    pixel_delta_v: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    defocus_disk_u: Vec3,
+    defocus_disk_v: Vec3,
+}
+
+impl CameraFrame {
+    const ZERO: CameraFrame = CameraFrame {
+        position: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+        pixel00_top_left: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+        pixel00_center: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+        pixel_delta_u: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+        pixel_delta_v: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+        u: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+        v: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+        w: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+        defocus_disk_u: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+        defocus_disk_v: Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+    };
+}
+
+// Result of casting a primary ray through the viewport, used for autofocus and
+// for finding out which object a pixel shows.
+pub struct ScenePick {
+    pub distance: f64,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub mat: Option<Arc<dyn Material>>,
+}
+
 pub struct Camera {
     pub viewport: Image,
     pub aspect_ratio: f64,
@@ -30,20 +125,20 @@ pub struct Camera {
     pub defocus_angle: f64,
     pub focus_dist: f64,
     pub background: Color,
+    // Ray times are sampled in [shutter_open, shutter_close].
+    // The camera is at the look_from/look_at pose when the shutter opens,
+    // and moves to end_pose (if there is one) by the time it closes.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    pub shutter_curve: ShutterCurve,
+    pub end_pose: Option<CameraPose>,
+    pub projection: Projection,
     // uninit
     right: Vec3,
     focal_length: f64,
     viewport_height: f64,
     viewport_width: f64,
-    pixel00_top_left: Vec3,
-    pixel00_center: Vec3,
-    pixel_delta_u: Vec3,
-    pixel_delta_v: Vec3,
-    u: Vec3,
-    v: Vec3,
-    w: Vec3,
-    defocus_disk_u: Vec3,
-    defocus_disk_v: Vec3,
+    frame: CameraFrame,
     pixel_samples_scale: f64,
 }
 
@@ -63,20 +158,17 @@ impl Camera {
             defocus_angle: 0.0,
             focus_dist: 10.0,
             background: Color::new(0.0, 0.0, 0.0),
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            shutter_curve: ShutterCurve::Box,
+            end_pose: None,
+            projection: Projection::Perspective,
             // uninit:
             right: Vec3::new(1.0, 0.0, 0.0),
             viewport_width: 1.0,
             viewport_height: 1.0,
-            pixel00_top_left: Vec3::new(0.0, 0.0, 0.0),
-            pixel00_center: Vec3::new(0.0, 0.0, 0.0),
             focal_length: 1.0,
-            pixel_delta_u: Vec3::new(0.0, 0.0, 0.0),
-            pixel_delta_v: Vec3::new(0.0, 0.0, 0.0),
-            u: Vec3::new(0.0, 0.0, 0.0),
-            v: Vec3::new(0.0, 0.0, 0.0),
-            w: Vec3::new(0.0, 0.0, 0.0),
-            defocus_disk_u: Vec3::new(0.0, 0.0, 0.0),
-            defocus_disk_v: Vec3::new(0.0, 0.0, 0.0),
+            frame: CameraFrame::ZERO,
             // unused:
             field_of_view: 90.0,
             pixel_samples_scale: 0.1,
@@ -99,20 +191,17 @@ impl Camera {
             defocus_angle: 0.0,
             focus_dist: 10.0,
             background: Color::new(0.0, 0.0, 0.0),
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            shutter_curve: ShutterCurve::Box,
+            end_pose: None,
+            projection: Projection::Perspective,
             // uninit:
             right: Vec3::new(1.0, 0.0, 0.0),
             viewport_width: 1.0,
             viewport_height: 1.0,
-            pixel00_top_left: Vec3::new(0.0, 0.0, 0.0),
-            pixel00_center: Vec3::new(0.0, 0.0, 0.0),
             focal_length: 1.0,
-            pixel_delta_u: Vec3::new(0.0, 0.0, 0.0),
-            pixel_delta_v: Vec3::new(0.0, 0.0, 0.0),
-            u: Vec3::new(0.0, 0.0, 0.0),
-            v: Vec3::new(0.0, 0.0, 0.0),
-            w: Vec3::new(0.0, 0.0, 0.0),
-            defocus_disk_u: Vec3::new(0.0, 0.0, 0.0),
-            defocus_disk_v: Vec3::new(0.0, 0.0, 0.0),
+            frame: CameraFrame::ZERO,
             // unused:
             field_of_view: 90.0,
             pixel_samples_scale: 0.1,
@@ -126,22 +215,70 @@ impl Camera {
         let h = (theta / 2.0).tan();
         self.viewport_width = 2.0 * h * self.focus_dist;
         self.viewport_height = self.viewport_width / self.aspect_ratio;
-        self.w = (self.look_from - self.look_at).normalized();
-        self.u = (self.up.cross(self.w)).normalized();
-        self.v = self.w.cross(self.u);
-        let viewport_u = self.u * self.viewport_width;
-        let viewport_v = -self.v * self.viewport_height;
-        self.pixel_delta_u = (1.0 / self.viewport.width as f64) * viewport_u;
-        self.pixel_delta_v = (1.0 / self.viewport.height as f64) * viewport_v;
-        self.pixel00_top_left =
-            self.position - self.focus_dist * self.w
+        self.frame = self.frame_for_pose(self.start_pose());
+    }
+
+    pub fn start_pose(&self) -> CameraPose {
+        CameraPose::new(self.look_from, self.look_at, self.up)
+    }
+
+    fn frame_for_pose(&self, pose: CameraPose) -> CameraFrame {
+        let w = (pose.look_from - pose.look_at).normalized();
+        let u = (pose.up.cross(w)).normalized();
+        let v = w.cross(u);
+        let viewport_u = u * self.viewport_width;
+        let viewport_v = -v * self.viewport_height;
+        let pixel_delta_u = (1.0 / self.viewport.width as f64) * viewport_u;
+        let pixel_delta_v = (1.0 / self.viewport.height as f64) * viewport_v;
+        let pixel00_top_left =
+            pose.look_from - self.focus_dist * w
                 - viewport_u / 2.0
                 - viewport_v / 2.0;
-        self.pixel00_center = self.pixel00_top_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+        let pixel00_center = pixel00_top_left + 0.5 * (pixel_delta_u + pixel_delta_v);
 
         let defocus_radius = self.focus_dist * (self.defocus_angle / 2.0).to_radians().tan();
-        self.defocus_disk_u = self.u * defocus_radius;
-        self.defocus_disk_v = self.v * defocus_radius;
+        CameraFrame {
+            position: pose.look_from,
+            pixel00_top_left,
+            pixel00_center,
+            pixel_delta_u,
+            pixel_delta_v,
+            u,
+            v,
+            w,
+            defocus_disk_u: u * defocus_radius,
+            defocus_disk_v: v * defocus_radius,
+        }
+    }
+
+    fn frame_at(&self, time: f64) -> CameraFrame {
+        match &self.end_pose {
+            Some(end_pose) => {
+                let duration = self.shutter_close - self.shutter_open;
+                let t = if duration > 0.0 { (time - self.shutter_open) / duration } else { 0.0 };
+                self.frame_for_pose(self.start_pose().lerp(end_pose, t))
+            }
+            None => self.frame,
+        }
+    }
+
+    // row is the continuous scanline coordinate, only used by the rolling shutter
+    fn sample_time(&self, row: f64) -> f64 {
+        let open = self.shutter_open;
+        let duration = self.shutter_close - self.shutter_open;
+        match self.shutter_curve {
+            ShutterCurve::Box => open + random_range(0.0..1.0) * duration,
+            ShutterCurve::Triangle => {
+                let t = 0.5 * (random_range(0.0..1.0) + random_range(0.0..1.0));
+                open + t * duration
+            }
+            ShutterCurve::RollingShutter { exposure } => {
+                let exposure = Interval::new(0.0, 1.0).clamp(exposure);
+                let row_fraction = Interval::new(0.0, 1.0).clamp(row / self.viewport.height as f64);
+                let row_open = row_fraction * (1.0 - exposure);
+                open + (row_open + random_range(0.0..1.0) * exposure) * duration
+            }
+        }
     }
 
     pub fn render(&mut self, scene_objects: Arc<dyn Hittable>) {
@@ -224,6 +361,78 @@ impl Camera {
         self.viewport = img;
     }
 
+    // Renders both eyes with the same scene and returns them packed into one image.
+    // Toe-in converges the eyes on look_at. Each eye is the size of the viewport.
+    pub fn render_stereo(&mut self, scene_objects: &impl Hittable, interocular_distance: f64,
+                         mode: StereoMode, layout: StereoLayout) -> Image {
+        let (look_from, look_at, projection) = (self.look_from, self.look_at, self.projection);
+        let right = self.up.cross(look_from - look_at).normalized();
+        let half_distance = 0.5 * interocular_distance;
+        let mut eyes = vec![];
+        for eye_offset in [-half_distance, half_distance] {
+            match mode {
+                StereoMode::Parallel => {
+                    self.look_from = look_from + eye_offset * right;
+                    self.look_at = look_at + eye_offset * right;
+                }
+                StereoMode::ToeIn => {
+                    self.look_from = look_from + eye_offset * right;
+                }
+                StereoMode::OmniDirectional => {
+                    self.projection = Projection::Equirectangular { eye_offset };
+                }
+            }
+            self.render_threaded(scene_objects);
+            eyes.push(self.viewport.clone());
+        }
+        self.look_from = look_from;
+        self.look_at = look_at;
+        self.projection = projection;
+
+        let (width, height) = (self.viewport.width, self.viewport.height);
+        let (mut stereo, right_x, right_y) = match layout {
+            StereoLayout::SideBySide => (Image::with_dimensions(2 * width, height), width, 0),
+            StereoLayout::TopBottom => (Image::with_dimensions(width, 2 * height), 0, height),
+        };
+        stereo.blit(&eyes[0], 0, 0);
+        stereo.blit(&eyes[1], right_x, right_y);
+        stereo
+    }
+
+    // Renders frames with the scene built once, writing file_prefix_0000.ppm, file_prefix_0001.ppm, ...
+    // Frame n starts at time n / frames_per_second. shutter_open and shutter_close are
+    // taken as fractions of a frame, so the default 0..1 exposes for the whole frame.
+    pub fn render_frames(&mut self, scene_objects: &impl Hittable, animation: &CameraAnimation,
+                         frames: std::ops::Range<usize>, frames_per_second: f64, file_prefix: &str) {
+        // Everything the animation and the frame loop touch, put back afterwards
+        let (shutter_open, shutter_close) = (self.shutter_open, self.shutter_close);
+        let end_pose = self.end_pose;
+        let (look_from, look_at) = (self.look_from, self.look_at);
+        let (field_of_view, focus_dist) = (self.field_of_view, self.focus_dist);
+        let frame_duration = 1.0 / frames_per_second;
+        for frame in frames {
+            let frame_time = frame as f64 * frame_duration;
+            self.shutter_open = frame_time + shutter_open * frame_duration;
+            self.shutter_close = frame_time + shutter_close * frame_duration;
+            if animation.moves() {
+                animation.apply(self, self.shutter_close);
+                self.end_pose = Some(self.start_pose());
+            }
+            animation.apply(self, self.shutter_open);
+
+            println!("Rendering frame {}", frame);
+            self.render_threaded(scene_objects);
+            self.viewport.write_to_file(format!("{}_{:04}.ppm", file_prefix, frame).as_str());
+        }
+        self.shutter_open = shutter_open;
+        self.shutter_close = shutter_close;
+        self.end_pose = end_pose;
+        self.look_from = look_from;
+        self.look_at = look_at;
+        self.field_of_view = field_of_view;
+        self.focus_dist = focus_dist;
+    }
+
     #[inline]
     fn pixel_kernel(&self, index: usize, width: usize, scene_objects: &impl Hittable, pixel: &mut Color) {
         let y = index / width;
@@ -302,22 +511,92 @@ impl Camera {
 
     fn get_ray(&self, i: usize, j: usize) -> Ray {
         let offset = sample_square();
-        let pixel_sample = self.pixel00_center
-        + (i as f64 + offset.x) * self.pixel_delta_u
-        + (j as f64 + offset.y) * self.pixel_delta_v;
+        let ray_time = self.sample_time(j as f64 + 0.5 + offset.y);
+        let frame = self.frame_at(ray_time);
+        if let Projection::Equirectangular { eye_offset } = self.projection {
+            let s = (i as f64 + 0.5 + offset.x) / self.viewport.width as f64;
+            let t = (j as f64 + 0.5 + offset.y) / self.viewport.height as f64;
+            return Self::equirectangular_ray(&frame, eye_offset, s, t, ray_time);
+        }
+        let pixel_sample = frame.pixel00_center
+        + (i as f64 + offset.x) * frame.pixel_delta_u
+        + (j as f64 + offset.y) * frame.pixel_delta_v;
         let ray_origin = if self.defocus_angle <= 0.0 {
-            self.position
+            frame.position
         } else {
-            self.defocus_disk_sample()
+            Self::defocus_disk_sample(&frame)
         };
         let ray_direction = pixel_sample - ray_origin;
-        let ray_time = random_range(0.0..1.0);
         Ray::with_time(ray_origin, ray_direction, ray_time)
     }
 
-    fn defocus_disk_sample(&self) -> Vec3 {
+    // s and t in 0..1 across the whole panorama, s = 0.5 looks straight ahead
+    fn equirectangular_ray(frame: &CameraFrame, eye_offset: f64, s: f64, t: f64, time: f64) -> Ray {
+        let longitude = (s - 0.5) * 2.0 * std::f64::consts::PI;
+        let latitude = (0.5 - t) * std::f64::consts::PI;
+        let horizontal = longitude.sin() * frame.u - longitude.cos() * frame.w;
+        let right = longitude.cos() * frame.u + longitude.sin() * frame.w;
+        let direction = latitude.cos() * horizontal + latitude.sin() * frame.v;
+        Ray::with_time(frame.position + eye_offset * right, direction, time)
+    }
+
+    // Ray from the center of the lens through normalized screen coordinates,
+    // (0, 0) being the top left corner of the image and (1, 1) the bottom right.
+    // No jitter, defocus or time sampling, so the same input always gives the same ray.
+    pub fn primary_ray(&self, s: f64, t: f64) -> Ray {
+        let frame = &self.frame;
+        if let Projection::Equirectangular { eye_offset } = self.projection {
+            return Self::equirectangular_ray(frame, eye_offset, s, t, 0.0);
+        }
+        let viewport_u = self.viewport.width as f64 * frame.pixel_delta_u;
+        let viewport_v = self.viewport.height as f64 * frame.pixel_delta_v;
+        let screen_point = frame.pixel00_top_left + s * viewport_u + t * viewport_v;
+        Ray::new(frame.position, screen_point - frame.position)
+    }
+
+    pub fn pick_screen(&mut self, s: f64, t: f64, scene_objects: &impl Hittable) -> Option<ScenePick> {
+        self.initialize();
+        let ray = self.primary_ray(s, t);
+        let mut hit_record = HitRecord::new();
+        let mut interval = Interval::new(1.0e-8, f64::INFINITY);
+        if !scene_objects.first_hit_on_interval(ray, &mut interval, &mut hit_record) {
+            return None;
+        }
+        Some(ScenePick {
+            distance: hit_record.t * ray.direction.length(),
+            point: hit_record.point,
+            normal: hit_record.normal,
+            mat: hit_record.mat,
+        })
+    }
+
+    pub fn pick_pixel(&mut self, x: usize, y: usize, scene_objects: &impl Hittable) -> Option<ScenePick> {
+        let s = (x as f64 + 0.5) / self.viewport.width as f64;
+        let t = (y as f64 + 0.5) / self.viewport.height as f64;
+        self.pick_screen(s, t, scene_objects)
+    }
+
+    // Moves the focus plane onto whatever the pixel shows.
+    // Returns false and leaves focus_dist alone if the pixel only sees background.
+    pub fn autofocus(&mut self, x: usize, y: usize, scene_objects: &impl Hittable) -> bool {
+        match self.pick_pixel(x, y, scene_objects) {
+            Some(pick) => {
+                // The focus plane is perpendicular to the view direction,
+                // so focus on the depth of the hit point, not its distance.
+                // Panoramas look every way, there only the distance makes sense.
+                self.focus_dist = match self.projection {
+                    Projection::Perspective => (pick.point - self.frame.position).dot(-self.frame.w),
+                    Projection::Equirectangular { .. } => pick.distance,
+                };
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn defocus_disk_sample(frame: &CameraFrame) -> Vec3 {
         let point = random_in_unit_disk();
-        self.position + point.x * self.defocus_disk_u + point.y * self.defocus_disk_v
+        frame.position + point.x * frame.defocus_disk_u + point.y * frame.defocus_disk_v
     }
 }
 
@@ -350,4 +629,136 @@ fn linear_to_srgb(linear_color: Color) -> Color {
         y: linear_to_srgb_float(linear_color.y),
         z: linear_to_srgb_float(linear_color.z),
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::animation::{Interpolation, Keyframe};
+    use crate::raytracing::implicits::sphere::Sphere;
+    use crate::raytracing::material::Lambertian;
+    use std::sync::Arc;
+
+    fn sphere_at(center: Vec3) -> Sphere {
+        Sphere::new(center, 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))
+    }
+
+    #[test]
+    fn shutter_curves_stay_in_the_interval() {
+        let mut camera = Camera::from_aspect_ratio(100, 1.0);
+        camera.shutter_open = 0.25;
+        camera.shutter_close = 0.75;
+        let middle_fraction = |camera: &Camera| {
+            let times: Vec<f64> = (0..4000).map(|_| camera.sample_time(50.0)).collect();
+            assert!(times.iter().all(|t| (0.25..=0.75).contains(t)));
+            times.iter().filter(|t| (0.375..0.625).contains(*t)).count() as f64 / times.len() as f64
+        };
+        // Half the box's samples are in the middle half of the interval, three quarters of the triangle's
+        assert!((middle_fraction(&camera) - 0.5).abs() < 0.05);
+        camera.shutter_curve = ShutterCurve::Triangle;
+        assert!((middle_fraction(&camera) - 0.75).abs() < 0.05);
+
+        camera.shutter_curve = ShutterCurve::RollingShutter { exposure: 0.2 };
+        for _ in 0..1000 {
+            assert!((0.25..=0.35).contains(&camera.sample_time(0.0)));
+            assert!((0.65..=0.75).contains(&camera.sample_time(100.0)));
+        }
+    }
+
+    #[test]
+    fn moving_camera_follows_the_shutter() {
+        let mut camera = Camera::from_aspect_ratio(10, 1.0);
+        camera.look_from = Vec3::new(0.0, 0.0, 0.0);
+        camera.look_at = Vec3::new(0.0, 0.0, -1.0);
+        camera.shutter_open = 1.0;
+        camera.shutter_close = 3.0;
+        camera.end_pose = Some(CameraPose::new(Vec3::new(4.0, 0.0, 0.0), Vec3::new(4.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0)));
+        camera.initialize();
+        for _ in 0..100 {
+            let ray = camera.get_ray(5, 5);
+            assert!((1.0..=3.0).contains(&ray.time));
+            assert!((ray.origin - Vec3::new(2.0 * (ray.time - 1.0), 0.0, 0.0)).length() < 1e-9);
+            assert!(ray.direction.z < 0.0);
+        }
+    }
+
+    #[test]
+    fn perspective_pick_looks_through_the_center() {
+        let mut camera = Camera::from_aspect_ratio(100, 1.0);
+        let pick = camera.pick_screen(0.5, 0.5, &sphere_at(Vec3::new(0.0, 0.0, -5.0))).unwrap();
+        assert!((pick.distance - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn equirectangular_pick_sees_behind_the_camera() {
+        let mut camera = Camera::from_aspect_ratio(200, 2.0);
+        camera.projection = Projection::Equirectangular { eye_offset: 0.0 };
+        let behind = sphere_at(Vec3::new(0.0, 0.0, 5.0));
+        let pick = camera.pick_screen(0.0, 0.5, &behind).unwrap();
+        assert!((pick.distance - 4.0).abs() < 1e-9);
+        // To the right is a quarter turn from straight ahead
+        let right = sphere_at(Vec3::new(5.0, 0.0, 0.0));
+        assert!(camera.pick_screen(0.75, 0.5, &right).is_some());
+        assert!(camera.pick_screen(0.5, 0.5, &right).is_none());
+
+        assert!(camera.autofocus(0, 50, &behind));
+        assert!((camera.focus_dist - 4.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn stereo_eyes_see_the_sphere_shifted() {
+        let mut camera = Camera::from_aspect_ratio(20, 2.0);
+        camera.samples_per_pixel = 4;
+        // Hits come back black, misses white
+        camera.max_depth = 1;
+        camera.background = Color::new(1.0, 1.0, 1.0);
+        let scene = sphere_at(Vec3::new(0.0, 0.0, -5.0));
+        let stereo = camera.render_stereo(&scene, 4.0, StereoMode::Parallel, StereoLayout::SideBySide);
+        assert_eq!((stereo.width, stereo.height), (40, 10));
+        // The left eye sees the sphere right of center, the right eye left of it
+        assert!(stereo.index_2d(14, 5).x < 0.5 && stereo.index_2d(6, 5).x > 0.5);
+        assert!(stereo.index_2d(26, 5).x < 0.5 && stereo.index_2d(34, 5).x > 0.5);
+        assert!((camera.look_from - Vec3::new(0.0, 0.0, 0.0)).length() == 0.0);
+
+        let stereo = camera.render_stereo(&scene, 4.0, StereoMode::ToeIn, StereoLayout::TopBottom);
+        assert_eq!((stereo.width, stereo.height), (20, 20));
+    }
+
+    #[test]
+    fn omni_directional_eyes_sit_on_a_circle() {
+        let mut camera = Camera::from_aspect_ratio(100, 2.0);
+        camera.initialize();
+        for i in 0..=20 {
+            let s = i as f64 / 20.0;
+            let ray = Camera::equirectangular_ray(&camera.frame, -0.5, s, 0.5, 0.0);
+            assert!(((ray.origin - camera.look_from).length() - 0.5).abs() < 1e-12);
+            assert!((ray.origin - camera.look_from).dot(ray.direction).abs() < 1e-12);
+            // The left eye is on the left when looking along the ray
+            assert!((ray.origin - camera.look_from).dot(ray.direction.cross(camera.up)) < 0.0);
+        }
+    }
+
+    #[test]
+    fn render_frames_puts_the_camera_back() {
+        let mut camera = Camera::from_aspect_ratio(4, 1.0);
+        camera.samples_per_pixel = 1;
+        camera.look_from = Vec3::new(1.0, 2.0, 3.0);
+        let mut animation = CameraAnimation::new();
+        animation.look_from.add(Keyframe::new(0.0, Vec3::new(0.0, 0.0, 5.0), Interpolation::Linear));
+        animation.look_from.add(Keyframe::new(1.0, Vec3::new(5.0, 0.0, 5.0), Interpolation::Linear));
+        animation.field_of_view.add(Keyframe::new(0.0, 30.0, Interpolation::Linear));
+        animation.focus_dist.add(Keyframe::new(0.0, 3.0, Interpolation::Linear));
+        let prefix = std::env::temp_dir().join(format!("render_frames_test_{}", std::process::id()));
+        let prefix = prefix.to_str().unwrap();
+        camera.render_frames(&HittableList::new(), &animation, 0..2, 2.0, prefix);
+        for frame in 0..2 {
+            let _ = std::fs::remove_file(format!("{}_{:04}.ppm", prefix, frame));
+        }
+
+        assert!((camera.look_from - Vec3::new(1.0, 2.0, 3.0)).length() == 0.0);
+        assert!((camera.look_at - Vec3::new(0.0, 0.0, -1.0)).length() == 0.0);
+        assert_eq!(camera.field_of_view, 90.0);
+        assert_eq!(camera.focus_dist, 10.0);
+        assert_eq!((camera.shutter_open, camera.shutter_close), (0.0, 1.0));
+        assert!(camera.end_pose.is_none());
+    }
+}
@@ -0,0 +1,230 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::ray::Ray;
+use crate::solid::{Solid, SolidHittable};
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Constructive solid geometry. Each operand's surface crossings along the ray are collected
+// into entry/exit lists, and the result's surface is wherever the combined inside/outside changes.
+// Operands have to be closed solids, and Csg nodes can be operands themselves.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // a minus b
+    Difference,
+}
+
+impl CsgOperation {
+    fn combine(&self, inside_a: bool, inside_b: bool) -> bool {
+        match self {
+            CsgOperation::Union => inside_a || inside_b,
+            CsgOperation::Intersection => inside_a && inside_b,
+            CsgOperation::Difference => inside_a && !inside_b,
+        }
+    }
+}
+
+// Where the ray goes through an operand's surface
+struct Crossing {
+    t: f64,
+    entering: bool,
+    from_b: bool,
+    hit_record: HitRecord,
+}
+
+pub struct Csg {
+    operation: CsgOperation,
+    a: Arc<dyn SolidHittable>,
+    b: Arc<dyn SolidHittable>,
+    bbox: AABB,
+}
+
+impl Csg {
+    // Stops collecting crossings after this many per operand, in case a surface keeps reporting the same hit
+    const MAX_CROSSINGS: usize = 64;
+    // How far past a crossing the search for the next one starts
+    const CROSSING_EPSILON: f64 = 1e-7;
+
+    pub fn new(operation: CsgOperation, a: Arc<dyn SolidHittable>, b: Arc<dyn SolidHittable>) -> Self {
+        let (box_a, box_b) = (a.bounding_box(), b.bounding_box());
+        let bbox = match operation {
+            CsgOperation::Union => AABB::from_aabbs(box_a, box_b),
+            CsgOperation::Intersection => AABB::new(
+                Interval::new(f64::max(box_a.x.lower_bound, box_b.x.lower_bound), f64::min(box_a.x.upper_bound, box_b.x.upper_bound)),
+                Interval::new(f64::max(box_a.y.lower_bound, box_b.y.lower_bound), f64::min(box_a.y.upper_bound, box_b.y.upper_bound)),
+                Interval::new(f64::max(box_a.z.lower_bound, box_b.z.lower_bound), f64::min(box_a.z.upper_bound, box_b.z.upper_bound)),
+            ),
+            CsgOperation::Difference => box_a,
+        };
+        Self { operation, a, b, bbox }
+    }
+
+    pub fn union(a: Arc<dyn SolidHittable>, b: Arc<dyn SolidHittable>) -> Self {
+        Self::new(CsgOperation::Union, a, b)
+    }
+
+    pub fn intersection(a: Arc<dyn SolidHittable>, b: Arc<dyn SolidHittable>) -> Self {
+        Self::new(CsgOperation::Intersection, a, b)
+    }
+
+    pub fn difference(a: Arc<dyn SolidHittable>, b: Arc<dyn SolidHittable>) -> Self {
+        Self::new(CsgOperation::Difference, a, b)
+    }
+
+    // Whether the ray starts inside the operand, and every crossing after that in order
+    fn crossings(object: &Arc<dyn SolidHittable>, ray: Ray, interval: Interval, from_b: bool) -> (bool, Vec<Crossing>) {
+        let starts_inside = object.is_point_inside(ray.at(interval.lower_bound));
+        let mut crossings = vec![];
+        let mut lower_bound = interval.lower_bound;
+        while crossings.len() < Self::MAX_CROSSINGS {
+            let mut search = Interval::new(lower_bound, interval.upper_bound);
+            let mut hit_record = HitRecord::new();
+            if !object.first_hit_on_interval(ray, &mut search, &mut hit_record) {
+                break;
+            }
+            lower_bound = hit_record.t + Self::CROSSING_EPSILON * f64::max(1.0, hit_record.t.abs());
+            crossings.push(Crossing {
+                t: hit_record.t,
+                entering: hit_record.front_face,
+                from_b,
+                hit_record,
+            });
+        }
+        (starts_inside, crossings)
+    }
+}
+
+impl Hittable for Csg {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        if !self.bbox.hit(ray, interval) {
+            return false;
+        }
+        let (mut inside_a, crossings_a) = Self::crossings(&self.a, ray, *interval, false);
+        let (mut inside_b, crossings_b) = Self::crossings(&self.b, ray, *interval, true);
+        let mut crossings: Vec<Crossing> = crossings_a.into_iter().chain(crossings_b).collect();
+        crossings.sort_by(|x, y| x.t.total_cmp(&y.t));
+
+        let mut inside = self.operation.combine(inside_a, inside_b);
+        for crossing in crossings {
+            if crossing.from_b {
+                inside_b = crossing.entering;
+            } else {
+                inside_a = crossing.entering;
+            }
+            let now_inside = self.operation.combine(inside_a, inside_b);
+            if now_inside == inside {
+                continue;
+            }
+            inside = now_inside;
+
+            let record = crossing.hit_record;
+            let mut outward_normal = if record.front_face { record.normal } else { -record.normal };
+            // The inside of b is the outside of the result, and the cut keeps b's material
+            if self.operation == CsgOperation::Difference && crossing.from_b {
+                outward_normal = -outward_normal;
+            }
+            interval.upper_bound = crossing.t;
+            *hit_record = record;
+            hit_record.set_face_normal(ray, outward_normal);
+            return true;
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+impl Solid for Csg {
+    fn is_point_inside(&self, point: Vec3) -> bool {
+        self.operation.combine(self.a.is_point_inside(point), self.b.is_point_inside(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::cuboid::Cuboid;
+    use crate::raytracing::implicits::sphere::Sphere;
+    use crate::raytracing::material::{Lambertian, Material};
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+    // Unit spheres covering z in [-6, -4] and [-5, -3]
+    fn spheres(b_material: Arc<dyn Material>) -> (Arc<dyn SolidHittable>, Arc<dyn SolidHittable>) {
+        (Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0, material())), Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -4.0), 1.0, b_material)))
+    }
+
+    #[test]
+    fn combine() {
+        let cases = [(false, false), (true, false), (false, true), (true, true)];
+        let results = |operation: CsgOperation| cases.map(|(a, b)| operation.combine(a, b));
+        assert_eq!(results(CsgOperation::Union), [false, true, true, true]);
+        assert_eq!(results(CsgOperation::Intersection), [false, false, false, true]);
+        assert_eq!(results(CsgOperation::Difference), [false, true, false, false]);
+    }
+
+    #[test]
+    fn first_surface_of_each_operation() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        for (operation, t) in [(CsgOperation::Union, 3.0), (CsgOperation::Intersection, 4.0), (CsgOperation::Difference, 5.0)] {
+            let (a, b) = spheres(material());
+            let hit = first_hit(&Csg::new(operation, a, b), ray).unwrap();
+            assert!((hit.t - t).abs() < 1e-9, "{:?}", operation);
+            assert!(hit.front_face && (hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9, "{:?}", operation);
+        }
+        // Beside the intersection's lens, but through both spheres' boxes
+        let (a, b) = spheres(material());
+        assert!(first_hit(&Csg::intersection(a, b), Ray::new(Vec3::new(0.0, 0.95, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+    }
+
+    #[test]
+    fn difference_keeps_the_cutters_material() {
+        let cutter_material = material();
+        let (a, b) = spheres(cutter_material.clone());
+        let difference = Csg::difference(a, b);
+        let hit = first_hit(&difference, Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!(Arc::ptr_eq(hit.mat.as_ref().unwrap(), &cutter_material));
+        // Starting inside the result, the way out is a back face
+        let hit = first_hit(&difference, Ray::new(Vec3::new(0.0, 0.0, -5.5), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.t - 0.5).abs() < 1e-9 && !hit.front_face);
+    }
+
+    #[test]
+    fn nested_operands() {
+        let (a, b) = spheres(material());
+        let union: Arc<dyn SolidHittable> = Arc::new(Csg::union(a, b));
+        let slab = Arc::new(Cuboid::new(Vec3::new(-2.0, -2.0, -4.8), Vec3::new(2.0, 2.0, -4.2), material()));
+        let cut = Csg::difference(union, slab);
+        assert!(cut.is_point_inside(Vec3::new(0.0, 0.0, -3.5)));
+        assert!(!cut.is_point_inside(Vec3::new(0.0, 0.0, -4.5)));
+        assert!(cut.is_point_inside(Vec3::new(0.0, 0.0, -5.5)));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hits: Vec<f64> = (0..4)
+            .scan(0.001, |lower_bound, _| {
+                let mut interval = Interval::new(*lower_bound, f64::INFINITY);
+                let mut hit_record = HitRecord::new();
+                cut.first_hit_on_interval(ray, &mut interval, &mut hit_record).then(|| {
+                    *lower_bound = hit_record.t + 1e-6;
+                    hit_record.t
+                })
+            })
+            .collect();
+        let expected = [3.0, 4.2, 4.8, 6.0];
+        assert_eq!(hits.len(), 4);
+        assert!(hits.iter().zip(expected).all(|(t, expected)| (t - expected).abs() < 1e-9), "{:?}", hits);
+    }
+}
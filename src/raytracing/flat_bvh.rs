@@ -0,0 +1,437 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::bvh::{objects_bbox, BVHSplit, PARALLEL_BUILD_SIZE};
+use crate::raytracing::hittable::{HitRecord, Hittable, HittableList};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::ray::Ray;
+use crate::vector::Vec3;
+use rayon::prelude::*;
+use std::sync::Arc;
+
+// Box stored as f32, rounded outwards so it still contains everything the f64 box did
+#[derive(Copy, Clone)]
+struct FlatNode {
+    min: [f32; 3],
+    max: [f32; 3],
+    // Leaves: first object. Inner nodes: second child, the first one follows the node.
+    offset: u32,
+    // 0 for inner nodes
+    count: u16,
+    // Inner nodes: axis the children are ordered along, rays going down it visit the second child first
+    axis: u16,
+}
+
+const _: () = assert!(std::mem::size_of::<FlatNode>() == 32);
+
+impl FlatNode {
+    fn from_aabb(bbox: AABB) -> Self {
+        let mut node = Self { min: [0.0; 3], max: [0.0; 3], offset: 0, count: 0, axis: 0 };
+        for axis in 0..3 {
+            let interval = bbox.axis_interval(axis as i32);
+            let (min, max) = (interval.lower_bound as f32, interval.upper_bound as f32);
+            node.min[axis] = if (min as f64) > interval.lower_bound { min.next_down() } else { min };
+            node.max[axis] = if (max as f64) < interval.upper_bound { max.next_up() } else { max };
+        }
+        node
+    }
+
+    #[inline]
+    fn hit(&self, ray: &Ray, inverse_direction: [f64; 3], interval: &Interval) -> bool {
+        let mut lower = interval.lower_bound;
+        let mut upper = interval.upper_bound;
+        for (axis, inverse) in inverse_direction.iter().enumerate() {
+            let origin = ray.origin[axis];
+            if ray.direction[axis] == 0.0 {
+                if origin < self.min[axis] as f64 || origin > self.max[axis] as f64 {
+                    return false;
+                }
+                continue;
+            }
+            let t0 = (self.min[axis] as f64 - origin) * inverse;
+            let t1 = (self.max[axis] as f64 - origin) * inverse;
+            lower = lower.max(t0.min(t1));
+            upper = upper.min(t0.max(t1));
+            if upper < lower {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// The same tree as BVHNode, laid out in one array: nodes in depth-first order and leaves
+// pointing at ranges of a reordered object array, so a leaf never tests an object twice.
+// Traversal walks it with a fixed stack and visits the nearer child first,
+// so hits there shorten the interval before the farther child's box is tested.
+pub struct FlatBVH {
+    nodes: Vec<FlatNode>,
+    objects: Vec<Arc<dyn Hittable>>,
+    // Objects without a finite bounding box, tested on every ray
+    unbounded: Vec<Arc<dyn Hittable>>,
+    bbox: AABB,
+}
+
+impl FlatBVH {
+    // Past this depth the builder falls back to median splits, which halve the objects every level,
+    // so no path gets longer than the traversal stack
+    const MAX_SPLIT_DEPTH: usize = 32;
+    const STACK_SIZE: usize = 64;
+    const MORTON_LEAF_SIZE: usize = 4;
+
+    pub fn new(list: &mut HittableList) -> Self {
+        Self::with_split(list, BVHSplit::Median)
+    }
+
+    pub fn with_split(list: &mut HittableList, split: BVHSplit) -> Self {
+        let (mut objects, unbounded): (Vec<_>, Vec<_>) = list.hittables.iter().cloned()
+            .partition(|object| !object.bounding_box().is_unbounded());
+        let mut nodes = vec![];
+        let mut bbox = AABB::EMPTY;
+        if !objects.is_empty() {
+            Self::build_node(&mut nodes, &mut objects, 0, split, 0);
+            bbox = objects_bbox(&objects);
+        }
+        Self { nodes, objects, unbounded, bbox }
+    }
+
+    // Linear BVH for very large inputs: the objects are sorted along a Morton curve through their
+    // centers and every node splits where the next bit of the codes flips. A lot quicker to build
+    // than a SAH tree, a bit slower to trace.
+    pub fn morton(list: &mut HittableList) -> Self {
+        let (objects, unbounded): (Vec<_>, Vec<_>) = list.hittables.iter().cloned()
+            .partition(|object| !object.bounding_box().is_unbounded());
+        let boxes: Vec<AABB> = objects.par_iter().map(|object| object.bounding_box()).collect();
+        let centers = boxes.par_iter()
+            .map(|bbox| {
+                let center = bbox.center();
+                AABB::from_corners(center, center)
+            })
+            .reduce(|| AABB::EMPTY, AABB::from_aabbs);
+        let mut order: Vec<(u32, usize)> = boxes.par_iter().enumerate()
+            .map(|(i, bbox)| (morton_code(bbox.center(), &centers), i))
+            .collect();
+        order.par_sort_unstable();
+
+        let codes: Vec<u32> = order.iter().map(|&(code, _)| code).collect();
+        let boxes: Vec<AABB> = order.iter().map(|&(_, i)| boxes[i]).collect();
+        let objects: Vec<Arc<dyn Hittable>> = order.iter().map(|&(_, i)| objects[i].clone()).collect();
+        let mut nodes = vec![];
+        let mut bbox = AABB::EMPTY;
+        if !objects.is_empty() {
+            bbox = Self::build_morton_node(&mut nodes, &codes, &boxes, 0, 0);
+        }
+        Self { nodes, objects, unbounded, bbox }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Nodes and object pointers, not the objects themselves
+    pub fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(&self.nodes[..])
+            + std::mem::size_of_val(&self.objects[..])
+            + std::mem::size_of_val(&self.unbounded[..])
+    }
+
+    // Builds the node for objects, which sit at first.. in the final object array, returns its index
+    fn build_node(nodes: &mut Vec<FlatNode>, objects: &mut [Arc<dyn Hittable>], first: usize, split: BVHSplit, depth: usize) -> usize {
+        let bbox = objects_bbox(objects);
+        let index = nodes.len();
+        nodes.push(FlatNode::from_aabb(bbox));
+        let split = if depth < Self::MAX_SPLIT_DEPTH { split } else { BVHSplit::Median };
+        let middle = match split.partition(objects, bbox) {
+            Some(middle) => middle,
+            // Every leaf's count has to fit the node, anything bigger is split in half
+            None if objects.len() > u16::MAX as usize => objects.len() / 2,
+            None => {
+                nodes[index].offset = first as u32;
+                nodes[index].count = objects.len() as u16;
+                return index;
+            }
+        };
+        let (left, right) = objects.split_at_mut(middle);
+        Self::build_children(
+            nodes,
+            index,
+            left.len().max(right.len()),
+            |nodes| Self::build_node(nodes, left, first, split, depth + 1),
+            |nodes| Self::build_node(nodes, right, first + middle, split, depth + 1),
+        );
+        index
+    }
+
+    // codes are sorted, boxes belong to the objects at first.., returns the node's box
+    fn build_morton_node(nodes: &mut Vec<FlatNode>, codes: &[u32], boxes: &[AABB], first: usize, depth: usize) -> AABB {
+        let index = nodes.len();
+        nodes.push(FlatNode::from_aabb(AABB::EMPTY));
+        if codes.len() <= Self::MORTON_LEAF_SIZE {
+            let bbox = boxes.iter().fold(AABB::EMPTY, |bbox, object| AABB::from_aabbs(bbox, *object));
+            nodes[index] = FlatNode::from_aabb(bbox);
+            nodes[index].offset = first as u32;
+            nodes[index].count = codes.len() as u16;
+            return bbox;
+        }
+        let (first_code, last_code) = (codes[0], codes[codes.len() - 1]);
+        let middle = if first_code == last_code || depth >= Self::MAX_SPLIT_DEPTH {
+            codes.len() / 2
+        } else {
+            let bit = 1 << (31 - (first_code ^ last_code).leading_zeros());
+            codes.partition_point(|code| code & bit == 0)
+        };
+        let ((left_codes, right_codes), (left_boxes, right_boxes)) = (codes.split_at(middle), boxes.split_at(middle));
+        let (left_bbox, right_bbox) = Self::build_children(
+            nodes,
+            index,
+            middle.max(codes.len() - middle),
+            |nodes| Self::build_morton_node(nodes, left_codes, left_boxes, first, depth + 1),
+            |nodes| Self::build_morton_node(nodes, right_codes, right_boxes, first + middle, depth + 1),
+        );
+        let bbox = AABB::from_aabbs(left_bbox, right_bbox);
+        let (offset, axis) = (nodes[index].offset, nodes[index].axis);
+        nodes[index] = FlatNode::from_aabb(bbox);
+        (nodes[index].offset, nodes[index].axis) = (offset, axis);
+        bbox
+    }
+
+    // Builds the children of inner node index, the left one right after it. Big subtrees are built
+    // on the thread pool into their own arrays and appended, with their inner nodes' offsets moved along.
+    fn build_children<A: Send, B: Send>(
+        nodes: &mut Vec<FlatNode>,
+        index: usize,
+        size: usize,
+        left: impl FnOnce(&mut Vec<FlatNode>) -> A + Send,
+        right: impl FnOnce(&mut Vec<FlatNode>) -> B + Send,
+    ) -> (A, B) {
+        let left_index = nodes.len();
+        let (result, right_index) = if size < PARALLEL_BUILD_SIZE {
+            let left_result = left(nodes);
+            let right_index = nodes.len();
+            ((left_result, right(nodes)), right_index)
+        } else {
+            let ((left_nodes, left_result), (right_nodes, right_result)) = rayon::join(
+                || {
+                    let mut nodes = vec![];
+                    let result = left(&mut nodes);
+                    (nodes, result)
+                },
+                || {
+                    let mut nodes = vec![];
+                    let result = right(&mut nodes);
+                    (nodes, result)
+                },
+            );
+            Self::append_subtree(nodes, left_nodes);
+            let right_index = nodes.len();
+            Self::append_subtree(nodes, right_nodes);
+            ((left_result, right_result), right_index)
+        };
+
+        // Both splits put the lower centers on the left, order along the axis where the
+        // second child lies furthest beyond the first
+        let center = |node: &FlatNode, axis: usize| node.min[axis] + node.max[axis];
+        let (left_node, right_node) = (&nodes[left_index], &nodes[right_index]);
+        let separation = |axis| center(right_node, axis) - center(left_node, axis);
+        let axis = (0..3).max_by(|&a, &b| separation(a).total_cmp(&separation(b))).unwrap_or(0);
+        nodes[index].axis = axis as u16;
+        nodes[index].offset = right_index as u32;
+        result
+    }
+
+    fn append_subtree(nodes: &mut Vec<FlatNode>, subtree: Vec<FlatNode>) {
+        let base = nodes.len() as u32;
+        nodes.extend(subtree.into_iter().map(|mut node| {
+            if node.count == 0 {
+                node.offset += base;
+            }
+            node
+        }));
+    }
+}
+
+// 10 bits of each coordinate of point within bounds, interleaved x, y, z from the top
+fn morton_code(point: Vec3, bounds: &AABB) -> u32 {
+    let mut code = 0;
+    for axis in 0..3 {
+        let extent = bounds.axis_interval(axis as i32);
+        let offset = if extent.size() > 0.0 { (point[axis] - extent.lower_bound) / extent.size() } else { 0.0 };
+        let mut cell = (offset * 1024.0).clamp(0.0, 1023.0) as u32;
+        // Spread the 10 bits out to every third bit
+        cell = (cell | (cell << 16)) & 0x030000ff;
+        cell = (cell | (cell << 8)) & 0x0300f00f;
+        cell = (cell | (cell << 4)) & 0x030c30c3;
+        cell = (cell | (cell << 2)) & 0x09249249;
+        code |= cell << (2 - axis);
+    }
+    code
+}
+
+impl Hittable for FlatBVH {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let mut hit_anything = false;
+        for object in &self.unbounded {
+            if object.first_hit_on_interval(ray, interval, hit_record) {
+                hit_anything = true;
+                interval.upper_bound = hit_record.t;
+            }
+        }
+        if self.nodes.is_empty() {
+            return hit_anything;
+        }
+
+        let inverse_direction = [1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z];
+        let mut stack = [0u32; Self::STACK_SIZE];
+        let mut stack_size = 0;
+        let mut node_index = 0;
+        loop {
+            let node = &self.nodes[node_index];
+            if node.hit(&ray, inverse_direction, interval) {
+                if node.count == 0 {
+                    let (near, far) = if ray.direction[node.axis as usize] < 0.0 {
+                        (node.offset as usize, node_index + 1)
+                    } else {
+                        (node_index + 1, node.offset as usize)
+                    };
+                    stack[stack_size] = far as u32;
+                    stack_size += 1;
+                    node_index = near;
+                    continue;
+                }
+                let first = node.offset as usize;
+                for object in &self.objects[first..first + node.count as usize] {
+                    if object.first_hit_on_interval(ray, interval, hit_record) {
+                        hit_anything = true;
+                        // Not every hittable shortens the interval itself
+                        interval.upper_bound = hit_record.t;
+                    }
+                }
+            }
+            if stack_size == 0 {
+                return hit_anything;
+            }
+            stack_size -= 1;
+            node_index = stack[stack_size] as usize;
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        if self.unbounded.is_empty() { self.bbox } else { AABB::UNIVERSE }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::bvh::{BVHNode, SAHOptions};
+    use crate::raytracing::implicits::sphere::Sphere;
+    use crate::raytracing::material::Lambertian;
+
+    fn sphere(center: Vec3, radius: f64) -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(center, radius, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<f64> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record.t)
+    }
+
+    #[test]
+    fn nearest_hit_through_a_nested_bvh() {
+        let mut inner = HittableList::new();
+        inner.add(sphere(Vec3::new(0.0, 0.0, -5.0), 1.0));
+        let mut list = HittableList::new();
+        list.add(Arc::new(BVHNode::new(&mut inner)));
+        list.add(sphere(Vec3::new(0.0, 0.0, -20.0), 1.0));
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let t = first_hit(&FlatBVH::new(&mut list), ray).unwrap();
+        assert!((t - 4.0).abs() < 1e-9, "t = {t}");
+    }
+
+    // Rows of spheres, every row its own BVHNode, so FlatBVH leaves hold whole subtrees
+    fn nested_rows() -> HittableList {
+        let mut list = HittableList::new();
+        for row in 0..6 {
+            let mut spheres = HittableList::new();
+            for column in 0..6 {
+                let center = Vec3::new(column as f64 * 1.5 - 4.0, row as f64 * 1.5 - 4.0, -5.0 - ((row * 7 + column * 3) % 5) as f64);
+                spheres.add(sphere(center, 0.5 + 0.1 * ((row + column) % 4) as f64));
+            }
+            list.add(Arc::new(BVHNode::new(&mut spheres)));
+        }
+        list
+    }
+
+    #[test]
+    fn matches_bvh_node_on_nested_hittables() {
+        let reference = BVHNode::new(&mut nested_rows());
+        let flat = [
+            FlatBVH::new(&mut nested_rows()),
+            FlatBVH::with_split(&mut nested_rows(), BVHSplit::SurfaceArea(SAHOptions::default())),
+            FlatBVH::morton(&mut nested_rows()),
+        ];
+        for x in -20..=20 {
+            for y in -20..=20 {
+                let ray = Ray::new(Vec3::new(0.0, 0.0, 2.0), Vec3::new(x as f64 * 0.02, y as f64 * 0.02, -1.0));
+                let expected = first_hit(&reference, ray);
+                for bvh in &flat {
+                    assert_eq!(first_hit(bvh, ray), expected, "ray {x} {y}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn morton_codes_interleave_from_x() {
+        let bounds = AABB::from_corners(Vec3::new(-1.0, 0.0, 2.0), Vec3::new(1.0, 4.0, 3.0));
+        assert_eq!(morton_code(Vec3::new(-1.0, 0.0, 2.0), &bounds), 0);
+        assert_eq!(morton_code(Vec3::new(1.0, 4.0, 3.0), &bounds), (1 << 30) - 1);
+        assert_eq!(morton_code(Vec3::new(1.0, 0.0, 2.0), &bounds), 0x24924924);
+        assert_eq!(morton_code(Vec3::new(-1.0, 4.0, 2.0), &bounds), 0x12492492);
+        assert_eq!(morton_code(Vec3::new(-1.0, 0.0, 3.0), &bounds), 0x09249249);
+        // Points outside the bounds are clamped
+        assert_eq!(morton_code(Vec3::new(-5.0, 9.0, 2.5), &bounds), morton_code(Vec3::new(-1.0, 4.0, 2.5), &bounds));
+        // The top bit is x's upper half, then y's, then z's
+        let code = |x, y, z| morton_code(Vec3::new(x, y, z), &bounds);
+        assert!(code(0.1, 0.0, 2.0) > code(-0.1, 3.9, 2.9));
+        assert!(code(-0.1, 2.1, 2.0) > code(-0.1, 1.9, 2.9));
+        assert!(code(-0.1, 1.9, 2.6) > code(-0.1, 1.9, 2.4));
+        // Flat bounds give 0 along that axis
+        let flat = AABB::from_corners(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(morton_code(Vec3::new(0.0, 0.0, 5.0), &flat), 0);
+    }
+
+    // More spheres than PARALLEL_BUILD_SIZE, so the top of every tree is built on the thread pool
+    fn sphere_field() -> HittableList {
+        let mut list = HittableList::new();
+        for i in 0..PARALLEL_BUILD_SIZE + 904 {
+            let center = Vec3::new((i % 100) as f64 * 0.3 - 15.0, (i / 100) as f64 * 0.3 - 7.5, -5.0 - ((i * 13) % 17) as f64 * 0.4);
+            list.add(sphere(center, 0.08 + 0.02 * (i % 5) as f64));
+        }
+        list
+    }
+
+    #[test]
+    fn parallel_builds_match_a_plain_list() {
+        let reference = sphere_field();
+        let trees: [Box<dyn Hittable>; 4] = [
+            Box::new(FlatBVH::new(&mut sphere_field())),
+            Box::new(FlatBVH::with_split(&mut sphere_field(), BVHSplit::SurfaceArea(SAHOptions::default()))),
+            Box::new(FlatBVH::morton(&mut sphere_field())),
+            Box::new(BVHNode::new(&mut sphere_field())),
+        ];
+        let bounds = |bbox: AABB| (bbox.x.lower_bound, bbox.x.upper_bound, bbox.y.lower_bound, bbox.y.upper_bound);
+        for tree in &trees {
+            assert_eq!(bounds(tree.bounding_box()), bounds(reference.bounding_box()));
+        }
+        for x in -30..=30 {
+            for y in -15..=15 {
+                let ray = Ray::new(Vec3::new(x as f64 * 0.5 + 0.01, y as f64 * 0.5 + 0.02, 2.0), Vec3::new(-0.05, 0.03, -1.0));
+                let expected = first_hit(&reference, ray);
+                for tree in &trees {
+                    assert_eq!(first_hit(tree.as_ref(), ray), expected, "ray {x} {y}");
+                }
+            }
+        }
+    }
+}
@@ -8,6 +8,8 @@ use crate::random::random_range;
 use crate::raytracing::material::*;
 use crate::raytracing::aabb::*;
 use crate::raytracing::texture::*;
+use crate::matrix::Mat4;
+use crate::vector::Quat;
 
 #[derive(Clone)]
 pub struct HitRecord {
@@ -18,6 +20,10 @@ pub struct HitRecord {
     pub v: f64,
     pub front_face: bool,
     pub mat: Option<Arc<dyn Material>>,
+    // Direction along the surface for curves, zero for everything else
+    pub tangent: Vec3,
+    // The surface's own color (point clouds), materials multiply it in
+    pub color: Option<Color>,
 }
 
 impl HitRecord {
@@ -30,11 +36,25 @@ impl HitRecord {
             v: 0.0,
             front_face: true,
             mat: Option::None,
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            color: None,
         }
     }
     pub fn set_face_normal(&mut self, ray: Ray, outward_normal: Vec3) {
         self.front_face = ray.direction.dot(outward_normal) < 0.0;
         self.normal = if self.front_face { outward_normal } else { -outward_normal };
+        // Every hit goes through here, so a curve's tangent or a point's color doesn't outlive
+        // a closer hit on something else
+        self.tangent = Vec3::new(0.0, 0.0, 0.0);
+        self.color = None;
+    }
+
+    // color multiplied by the surface's own color, if it has one
+    pub fn tinted(&self, color: Color) -> Color {
+        match self.color {
+            Some(own) => own * color,
+            None => color,
+        }
     }
 }
 
@@ -66,6 +86,7 @@ impl HittableList {
     #[expect(unused)]
     pub fn clear(&mut self) {
         self.hittables.clear();
+        self.bbox = AABB::EMPTY;
     }
 }
 
@@ -83,7 +104,7 @@ impl Hittable for HittableList {
         hit_anything
     }
     fn bounding_box(&self) -> AABB {
-        AABB::EMPTY
+        self.bbox
     }
 }
 
@@ -189,11 +210,11 @@ impl RotateY {
                     let tester = Vec3::new(newx, y, newz);
 
                     min.x = f64::min(min.x, tester.x);
-                    max.x = f64::min(max.x, tester.x);
+                    max.x = f64::max(max.x, tester.x);
                     min.y = f64::min(min.y, tester.y);
-                    max.y = f64::min(max.y, tester.y);
+                    max.y = f64::max(max.y, tester.y);
                     min.z = f64::min(min.z, tester.z);
-                    max.z = f64::min(max.z, tester.z);
+                    max.z = f64::max(max.z, tester.z);
                 }
             }
         }
@@ -239,6 +260,186 @@ impl Hittable for RotateY {
             z: -self.sin_theta * hit_record.normal.x + self.cos_theta * hit_record.normal.z,
         };
 
+        hit_record.tangent = Vec3 {
+            x: self.cos_theta * hit_record.tangent.x + self.sin_theta * hit_record.tangent.z,
+            y: hit_record.tangent.y,
+            z: -self.sin_theta * hit_record.tangent.x + self.cos_theta * hit_record.tangent.z,
+        };
+
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+// Any affine transform of an object: rays go into object space through the inverse,
+// hit points come back through the matrix and normals through its inverse transpose.
+pub struct Transform {
+    object: Arc<dyn Hittable>,
+    matrix: Mat4,
+    inverse: Mat4,
+    normal_matrix: Mat4,
+    bbox: AABB,
+}
+
+impl Transform {
+    // The matrix has to be invertible
+    pub fn new(object: Arc<dyn Hittable>, matrix: Mat4) -> Transform {
+        let inverse = matrix.inverse().expect("Transform matrix is not invertible");
+        let normal_matrix = inverse.transpose();
+        let bbox = Self::transformed_bbox(object.bounding_box(), &matrix);
+        Self {
+            object,
+            matrix,
+            inverse,
+            normal_matrix,
+            bbox,
+        }
+    }
+
+    // Box around the 8 transformed corners
+    pub fn transformed_bbox(bbox: AABB, matrix: &Mat4) -> AABB {
+        if bbox.is_unbounded() {
+            return AABB::UNIVERSE;
+        }
+        let mut transformed = AABB::EMPTY;
+        for x in [bbox.x.lower_bound, bbox.x.upper_bound] {
+            for y in [bbox.y.lower_bound, bbox.y.upper_bound] {
+                for z in [bbox.z.lower_bound, bbox.z.upper_bound] {
+                    let corner = matrix.transform_point(Vec3::new(x, y, z));
+                    transformed = AABB::from_aabbs(transformed, AABB::from_corners(corner, corner));
+                }
+            }
+        }
+        transformed
+    }
+}
+
+impl Hittable for Transform {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        // The direction isn't normalized, so t means the same thing in both spaces
+        let object_ray = Ray::with_time(
+            self.inverse.transform_point(ray.origin),
+            self.inverse.transform_vector(ray.direction),
+            ray.time,
+        );
+        if !self.object.first_hit_on_interval(object_ray, interval, hit_record) {
+            return false;
+        }
+        hit_record.point = self.matrix.transform_point(hit_record.point);
+        // front_face stays valid, the inverse transpose keeps the sign of dot(normal, direction)
+        hit_record.normal = self.normal_matrix.transform_vector(hit_record.normal).normalized();
+        if hit_record.tangent.length_squared() > 0.0 {
+            hit_record.tangent = self.matrix.transform_vector(hit_record.tangent).normalized();
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+// Transform motion blur: moves from `start` at time 0 to `end` at time 1, like Sphere::new_moving.
+// Both matrices are split into translation, rotation and scale, and the rotation is slerped,
+// so a spinning object keeps its shape in between.
+pub struct MovingTransform {
+    object: Arc<dyn Hittable>,
+    start: (Vec3, Quat, Vec3),
+    end: (Vec3, Quat, Vec3),
+    // Times of the start and end matrices, before and after it the object holds still
+    time_range: Interval,
+    bbox: AABB,
+}
+
+impl MovingTransform {
+    // Moves from start at time 0 to end at time 1.
+    // Both matrices have to be affine and invertible.
+    pub fn new(object: Arc<dyn Hittable>, start: Mat4, end: Mat4) -> MovingTransform {
+        Self::over_time(object, start, end, Interval::new(0.0, 1.0))
+    }
+
+    // Moves from start to end over time_range, e.g. the seconds of an animation rendered with render_frames
+    pub fn over_time(object: Arc<dyn Hittable>, start: Mat4, end: Mat4, time_range: Interval) -> MovingTransform {
+        let start = start.decompose().expect("MovingTransform start matrix can't be decomposed");
+        let end = end.decompose().expect("MovingTransform end matrix can't be decomposed");
+        let mut moving = Self {
+            object,
+            start,
+            end,
+            time_range,
+            bbox: AABB::EMPTY,
+        };
+        moving.bbox = moving.motion_bounding_box();
+        moving
+    }
+
+    // Translation and scale are linear in time, so with a fixed rotation every corner moves in a
+    // straight line and the two ends bound it. A rotating object stays inside the sphere around its
+    // origin through the farthest scaled corner, which is farthest at one of the ends.
+    fn motion_bounding_box(&self) -> AABB {
+        let object_bbox = self.object.bounding_box();
+        let ((start_translation, start_rotation, start_scale), (end_translation, end_rotation, end_scale)) = (self.start, self.end);
+        let (start, _) = self.interpolated(0.0);
+        let (end, _) = self.interpolated(1.0);
+        let start_bbox = Transform::transformed_bbox(object_bbox, &start);
+        let end_bbox = Transform::transformed_bbox(object_bbox, &end);
+        let fixed_rotation = (start_rotation.w, start_rotation.x, start_rotation.y, start_rotation.z)
+            == (end_rotation.w, end_rotation.x, end_rotation.y, end_rotation.z);
+        if fixed_rotation || object_bbox.is_unbounded() {
+            return AABB::from_aabbs(start_bbox, end_bbox);
+        }
+        let radius = object_bbox.corners().iter()
+            .flat_map(|&corner| [start_scale, end_scale].map(|scale| (corner * scale).length()))
+            .fold(0.0, f64::max);
+        let sphere = AABB::from_corners(Vec3::new(-radius, -radius, -radius), Vec3::new(radius, radius, radius));
+        AABB::from_corners(start_translation, end_translation).swept(sphere)
+    }
+
+    // The matrix and its inverse at this time. Clamped to the time range, so the object
+    // never leaves the box bounding its motion.
+    fn matrices_at(&self, time: f64) -> (Mat4, Mat4) {
+        let fraction = if self.time_range.size() > 0.0 {
+            (time - self.time_range.lower_bound) / self.time_range.size()
+        } else if time < self.time_range.lower_bound {
+            0.0
+        } else {
+            1.0
+        };
+        self.interpolated(fraction.clamp(0.0, 1.0))
+    }
+
+    // fraction 0 is the start, 1 the end
+    fn interpolated(&self, fraction: f64) -> (Mat4, Mat4) {
+        let (start_translation, start_rotation, start_scale) = self.start;
+        let (end_translation, end_rotation, end_scale) = self.end;
+        let translation = start_translation + fraction * (end_translation - start_translation);
+        let rotation = start_rotation.slerp(end_rotation, fraction);
+        let scale = start_scale + fraction * (end_scale - start_scale);
+        let inverse_scale = Vec3::new(1.0 / scale.x, 1.0 / scale.y, 1.0 / scale.z);
+        let inverse = Mat4::scale(inverse_scale) * rotation.conjugate().to_mat4() * Mat4::translation(-translation);
+        (Mat4::from_trs(translation, rotation, scale), inverse)
+    }
+}
+
+impl Hittable for MovingTransform {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let (matrix, inverse) = self.matrices_at(ray.time);
+        let object_ray = Ray::with_time(
+            inverse.transform_point(ray.origin),
+            inverse.transform_vector(ray.direction),
+            ray.time,
+        );
+        if !self.object.first_hit_on_interval(object_ray, interval, hit_record) {
+            return false;
+        }
+        hit_record.point = matrix.transform_point(hit_record.point);
+        hit_record.normal = inverse.transpose().transform_vector(hit_record.normal).normalized();
+        if hit_record.tangent.length_squared() > 0.0 {
+            hit_record.tangent = matrix.transform_vector(hit_record.tangent).normalized();
+        }
         true
     }
 
@@ -307,9 +508,12 @@ impl Hittable for ConstantMedium {
         }
         
         hit_record.t = rec1.t + hit_distance / ray_length;
+        interval.upper_bound = hit_record.t;
         hit_record.point = ray.at(hit_record.t);
         hit_record.normal = Vec3::new(1.0, 0.0, 0.0);
         hit_record.front_face = true;
+        hit_record.tangent = Vec3::new(0.0, 0.0, 0.0);
+        hit_record.color = None;
         hit_record.mat = Some(self.phase_function.clone());
         
         true
@@ -318,4 +522,79 @@ impl Hittable for ConstantMedium {
     fn bounding_box(&self) -> AABB {
         self.boundary.bounding_box()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::sphere::Sphere;
+
+    fn contains(bbox: &AABB, point: Vec3) -> bool {
+        bbox.x.contains(point.x) && bbox.y.contains(point.y) && bbox.z.contains(point.z)
+    }
+
+    #[test]
+    fn transform_squashes_a_sphere() {
+        let sphere = Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        let matrix = Mat4::translation(Vec3::new(0.0, 0.0, -5.0)) * Mat4::scale(Vec3::new(1.0, 1.0, 0.5));
+        let transform = Transform::new(sphere, matrix);
+        assert!((transform.bounding_box().z.lower_bound + 5.5).abs() < 1e-12);
+        assert!((transform.bounding_box().z.upper_bound + 4.5).abs() < 1e-12);
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        assert!(transform.first_hit_on_interval(ray, &mut interval, &mut hit_record));
+        assert!((hit_record.t - 4.5).abs() < 1e-9);
+        assert!((hit_record.point - Vec3::new(0.0, 0.0, -4.5)).length() < 1e-9);
+        // Off center the normal of the squashed sphere tilts less towards x than the sphere's would
+        let ray = Ray::new(Vec3::new(0.6, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        assert!(transform.first_hit_on_interval(ray, &mut interval, &mut hit_record));
+        let z = 0.5 * (1.0 - 0.6 * 0.6_f64).sqrt();
+        let expected = Vec3::new(0.6, 0.0, z / 0.25).normalized();
+        assert!((hit_record.normal - expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn moving_transform_box_holds_a_spinning_object() {
+        let radius = 0.5;
+        let sphere = Arc::new(Sphere::new(Vec3::new(3.0, 0.0, 0.0), radius, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        let end = Mat4::translation(Vec3::new(1.0, 2.0, 0.0))
+            * Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), 170.0_f64.to_radians())
+            * Mat4::scale(Vec3::new(2.0, 2.0, 2.0));
+        let moving = MovingTransform::new(sphere, Mat4::IDENTITY, end);
+        for i in 0..=1000 {
+            let (matrix, _) = moving.matrices_at(i as f64 / 1000.0);
+            let center = matrix.transform_point(Vec3::new(3.0, 0.0, 0.0));
+            let scaled_radius = radius * matrix.transform_vector(Vec3::new(1.0, 0.0, 0.0)).length();
+            for axis in 0..3 {
+                let mut offset = Vec3::new(0.0, 0.0, 0.0);
+                offset[axis] = scaled_radius;
+                assert!(contains(&moving.bbox, center + offset) && contains(&moving.bbox, center - offset), "time {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn moving_transform_clamps_time() {
+        let sphere = Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        let moving = MovingTransform::new(sphere, Mat4::IDENTITY, Mat4::translation(Vec3::new(10.0, 0.0, 0.0)));
+        let ray = Ray::with_time(Vec3::new(10.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 5.0);
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        assert!(moving.first_hit_on_interval(ray, &mut interval, &mut hit_record));
+        assert!((hit_record.t - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn constant_medium_shortens_the_interval() {
+        let boundary = Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        // Dense enough to scatter right at the boundary
+        let medium = ConstantMedium::new(boundary, 1e9, Color::new(1.0, 1.0, 1.0));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        assert!(medium.first_hit_on_interval(ray, &mut interval, &mut hit_record));
+        assert_eq!(interval.upper_bound, hit_record.t);
+    }
+}
@@ -1,6 +1,27 @@
 pub mod sphere;
 pub mod quad;
+pub mod planar;
 pub mod plane;
+pub mod triangle;
+pub mod triangle_mesh;
+pub mod cylinder;
+pub mod cone;
+pub mod disk;
+pub mod torus;
+pub mod capsule;
+pub mod ellipsoid;
+pub mod cuboid;
+pub mod heightfield;
+pub mod voxels;
+pub mod curve;
+
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::HitRecord;
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::vector::Vec3;
+use std::sync::Arc;
 
 fn quadratic_formula(a: f64, b: f64, c: f64) -> (bool, f64, f64) {
     let denominator = 2.0 * a;
@@ -10,4 +31,269 @@ fn quadratic_formula(a: f64, b: f64, c: f64) -> (bool, f64, f64) {
     let t0 = (-b - root) / denominator;
     let t1 = (-b + root) / denominator;
     (solution_exists, t0, t1)
-}
\ No newline at end of file
+}
+
+// Roots of x^3 + a x^2 + b x + c, from Schwarze's "Cubic and Quartic Roots" (Graphics Gems I)
+fn cubic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    const EPSILON: f64 = 1e-12;
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c) / 2.0;
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+
+    let mut roots = if d.abs() < EPSILON {
+        if q.abs() < EPSILON {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if d < 0.0 {
+        // three real roots
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + std::f64::consts::FRAC_PI_3).cos(),
+            -t * (phi - std::f64::consts::FRAC_PI_3).cos(),
+        ]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+    for root in roots.iter_mut() {
+        *root -= a / 3.0;
+    }
+    roots
+}
+
+// Real roots of c[0] x^4 + c[1] x^3 + c[2] x^2 + c[3] x + c[4], sorted ascending.
+// Ferrari's method, then a couple of Newton steps since the closed form loses precision.
+fn quartic_roots(c: [f64; 5]) -> Vec<f64> {
+    const EPSILON: f64 = 1e-12;
+    if c[0] == 0.0 {
+        return vec![];
+    }
+    let (a, b, cc, d) = (c[1] / c[0], c[2] / c[0], c[3] / c[0], c[4] / c[0]);
+
+    // substitute x = y - a/4 to get rid of the cubic term: y^4 + p y^2 + q y + r
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + cc;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * cc / 4.0 + d;
+
+    let mut roots = vec![];
+    if r.abs() < EPSILON {
+        // y (y^3 + p y + q) = 0
+        roots.push(0.0);
+        roots.extend(cubic_roots(0.0, p, q));
+    } else {
+        // one root of the resolvent cubic splits the quartic into two quadratics
+        let z = cubic_roots(-p / 2.0, -r, r * p / 2.0 - q * q / 8.0)[0];
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        if u < -EPSILON || v < -EPSILON {
+            return vec![];
+        }
+        let u = u.max(0.0).sqrt();
+        let v = v.max(0.0).sqrt();
+        let v = if q < 0.0 { -v } else { v };
+        for (linear, constant) in [(v, z - u), (-v, z + u)] {
+            let (exists, y0, y1) = quadratic_formula(1.0, linear, constant);
+            if exists {
+                roots.push(y0);
+                roots.push(y1);
+            }
+        }
+    }
+
+    let evaluate = |x: f64| (((c[0] * x + c[1]) * x + c[2]) * x + c[3]) * x + c[4];
+    let derivative = |x: f64| ((4.0 * c[0] * x + 3.0 * c[1]) * x + 2.0 * c[2]) * x + c[3];
+    for root in roots.iter_mut() {
+        *root -= a / 4.0;
+        for _ in 0..2 {
+            let slope = derivative(*root);
+            if slope != 0.0 {
+                *root -= evaluate(*root) / slope;
+            }
+        }
+    }
+    roots.sort_by(|x, y| x.total_cmp(y));
+    roots
+}
+
+// Orthonormal frame for shapes defined around an axis.
+// Local coordinates have the axis along z and the origin at `origin`.
+#[derive(Copy, Clone)]
+struct LocalFrame {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl LocalFrame {
+    fn new(origin: Vec3, axis: Vec3) -> Self {
+        let w = axis.normalized();
+        let helper = if w.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = w.cross(helper).normalized();
+        let u = v.cross(w);
+        Self { origin, u, v, w }
+    }
+
+    fn vector_to_local(&self, vector: Vec3) -> Vec3 {
+        Vec3::new(vector.dot(self.u), vector.dot(self.v), vector.dot(self.w))
+    }
+
+    fn point_to_local(&self, point: Vec3) -> Vec3 {
+        self.vector_to_local(point - self.origin)
+    }
+
+    fn vector_to_world(&self, vector: Vec3) -> Vec3 {
+        vector.x * self.u + vector.y * self.v + vector.z * self.w
+    }
+
+    fn ray_to_local(&self, ray: Ray) -> Ray {
+        Ray::with_time(self.point_to_local(ray.origin), self.vector_to_local(ray.direction), ray.time)
+    }
+
+    // Tight box around a circle of `radius` centered on the axis at height z
+    fn circle_bbox(&self, z: f64, radius: f64) -> AABB {
+        let center = self.origin + z * self.w;
+        let extent = Vec3::new(
+            radius * (1.0 - self.w.x * self.w.x).max(0.0).sqrt(),
+            radius * (1.0 - self.w.y * self.w.y).max(0.0).sqrt(),
+            radius * (1.0 - self.w.z * self.w.z).max(0.0).sqrt(),
+        );
+        AABB::from_corners(center - extent, center + extent)
+    }
+}
+
+// Angle around the local z axis mapped to [0, 1)
+fn azimuth_u(local: Vec3) -> f64 {
+    (local.y.atan2(local.x) / (2.0 * std::f64::consts::PI)).rem_euclid(1.0)
+}
+
+// Hit found in a shape's LocalFrame. The frame is orthonormal, so t is the same as in world space.
+struct LocalHit {
+    t: f64,
+    outward_normal: Vec3,
+    u: f64,
+    v: f64,
+}
+
+// Keeps the closest candidate that lies in the interval
+fn keep_closest(best: &mut Option<LocalHit>, interval: &Interval, candidate: LocalHit) {
+    if interval.contains(candidate.t) && best.as_ref().is_none_or(|b| candidate.t < b.t) {
+        *best = Some(candidate);
+    }
+}
+
+fn record_local_hit(frame: &LocalFrame, ray: Ray, hit: Option<LocalHit>, mat: &Arc<dyn Material>,
+                    interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+    let hit = match hit {
+        Some(hit) => hit,
+        None => return false,
+    };
+    interval.upper_bound = hit.t;
+    hit_record.t = hit.t;
+    hit_record.point = ray.at(hit.t);
+    hit_record.set_face_normal(ray, frame.vector_to_world(hit.outward_normal).normalized());
+    hit_record.u = hit.u;
+    hit_record.v = hit.v;
+    hit_record.mat = Some(mat.clone());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solid::SolidHittable;
+
+    // Shoots rays at a closed solid from all around and follows each one through the solid.
+    // Every hit has to be in the bounding box, with the inside just behind the outward normal
+    // and the outside just in front of it, and the crossings have to alternate entering and leaving.
+    pub(super) fn check_closed_surface(object: &dyn SolidHittable, center: Vec3, distance: f64) {
+        const EPSILON: f64 = 1e-6;
+        let bbox = object.bounding_box();
+        let mut hits = 0;
+        for i in 0..400 {
+            // Spiral of directions over the sphere, aimed a little off center
+            let z = 1.0 - (i as f64 + 0.5) / 200.0;
+            let angle = i as f64 * 2.399963;
+            let r = (1.0 - z * z).sqrt();
+            let direction = Vec3::new(r * angle.cos(), r * angle.sin(), z);
+            let target = center + 0.3 * distance * Vec3::new((i as f64 * 0.37).sin(), (i as f64 * 0.61).cos(), (i as f64 * 0.83).sin()) / 4.0;
+            let ray = Ray::new(center + distance * direction, target - (center + distance * direction));
+            let mut lower_bound = 1e-9;
+            let mut entering = true;
+            for _ in 0..8 {
+                let mut interval = Interval::new(lower_bound, f64::INFINITY);
+                let mut hit_record = HitRecord::new();
+                if !object.first_hit_on_interval(ray, &mut interval, &mut hit_record) {
+                    break;
+                }
+                hits += 1;
+                let p = hit_record.point;
+                assert_eq!(hit_record.front_face, entering, "ray {} at {:?}", i, p);
+                let outward = if hit_record.front_face { hit_record.normal } else { -hit_record.normal };
+                assert!((outward.length() - 1.0).abs() < 1e-9);
+                assert!(object.is_point_inside(p - EPSILON * outward), "ray {} at {:?}", i, p);
+                assert!(!object.is_point_inside(p + EPSILON * outward), "ray {} at {:?}", i, p);
+                assert!(bbox.x.expand(EPSILON).contains(p.x) && bbox.y.expand(EPSILON).contains(p.y) && bbox.z.expand(EPSILON).contains(p.z));
+                entering = !entering;
+                lower_bound = hit_record.t + 1e-7;
+            }
+            assert!(entering, "ray {} ends inside", i);
+        }
+        assert!(hits > 100, "only {} hits", hits);
+    }
+
+    fn assert_roots(mut roots: Vec<f64>, expected: &[f64]) {
+        roots.sort_by(|a, b| a.total_cmp(b));
+        roots.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+        assert_eq!(roots.len(), expected.len(), "{:?}", roots);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-9, "{:?}", roots);
+        }
+    }
+
+    #[test]
+    fn cubic() {
+        // (x - 1)(x - 2)(x + 3)
+        assert_roots(cubic_roots(0.0, -7.0, 6.0), &[-3.0, 1.0, 2.0]);
+        // (x - 2)(x^2 + 1)
+        assert_roots(cubic_roots(-2.0, 1.0, -2.0), &[2.0]);
+    }
+
+    #[test]
+    fn quartic() {
+        // 2 (x - 1)(x - 2)(x + 3)(x - 0.5)
+        assert_roots(quartic_roots([2.0, -1.0, -14.0, 19.0, -6.0]), &[-3.0, 0.5, 1.0, 2.0]);
+        // (x^2 - 4)(x^2 + 1)
+        assert_roots(quartic_roots([1.0, 0.0, -3.0, 0.0, -4.0]), &[-2.0, 2.0]);
+        // x (x - 1)(x + 1)(x - 3)
+        assert_roots(quartic_roots([1.0, -3.0, -1.0, 3.0, 0.0]), &[-1.0, 0.0, 1.0, 3.0]);
+        assert!(quartic_roots([1.0, 0.0, 0.0, 0.0, 1.0]).is_empty());
+        assert!(quartic_roots([0.0, 1.0, 0.0, 0.0, 0.0]).is_empty());
+    }
+
+    #[test]
+    fn local_frame_round_trip() {
+        for axis in [Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, -2.0, 0.5)] {
+            let frame = LocalFrame::new(Vec3::new(1.0, 2.0, 3.0), axis);
+            let local_axis = frame.vector_to_local(axis.normalized());
+            assert!((local_axis - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+            let v = Vec3::new(0.3, -4.0, 2.0);
+            assert!((frame.vector_to_world(frame.vector_to_local(v)) - v).length() < 1e-12);
+            // The circle's box holds points all around it
+            let bbox = frame.circle_bbox(2.0, 1.5);
+            for i in 0..16 {
+                let angle = i as f64 * std::f64::consts::TAU / 16.0;
+                let p = frame.origin + frame.vector_to_world(Vec3::new(1.5 * angle.cos(), 1.5 * angle.sin(), 2.0));
+                assert!(bbox.x.expand(1e-9).contains(p.x) && bbox.y.expand(1e-9).contains(p.y) && bbox.z.expand(1e-9).contains(p.z));
+            }
+        }
+    }
+}
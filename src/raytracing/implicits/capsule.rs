@@ -0,0 +1,132 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::implicits::{azimuth_u, keep_closest, quadratic_formula, record_local_hit, LocalFrame, LocalHit};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::solid::Solid;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Every point within `radius` of the segment a-b: a cylinder with hemispheres on both ends.
+// u goes around the axis, v runs from the tip past a (0) to the tip past b (1).
+pub struct Capsule {
+    frame: LocalFrame,
+    length: f64,
+    radius: f64,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Capsule {
+    pub fn new(a: Vec3, b: Vec3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        let length = (b - a).length();
+        // a == b is just a sphere, any axis will do
+        let axis = if length > 0.0 { b - a } else { Vec3::new(0.0, 0.0, 1.0) };
+        let frame = LocalFrame::new(a, axis);
+        let offset = Vec3::new(radius, radius, radius);
+        let bbox = AABB::from_aabbs(AABB::from_corners(a - offset, a + offset), AABB::from_corners(b - offset, b + offset));
+        Self { frame, length, radius, mat, bbox }
+    }
+
+    fn local_hit(&self, local: Ray, t: f64) -> LocalHit {
+        let p = local.at(t);
+        let axis_point = Vec3::new(0.0, 0.0, p.z.clamp(0.0, self.length));
+        LocalHit {
+            t,
+            outward_normal: (p - axis_point) / self.radius,
+            u: azimuth_u(p),
+            v: ((p.z + self.radius) / (self.length + 2.0 * self.radius)).clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Hittable for Capsule {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let local = self.frame.ray_to_local(ray);
+        let (o, d) = (local.origin, local.direction);
+        let radius_squared = self.radius * self.radius;
+        let mut best = None;
+
+        // body
+        let (solution_exists, t0, t1) = quadratic_formula(
+            d.x * d.x + d.y * d.y,
+            2.0 * (o.x * d.x + o.y * d.y),
+            o.x * o.x + o.y * o.y - radius_squared,
+        );
+        if solution_exists {
+            for t in [t0, t1] {
+                let z = o.z + t * d.z;
+                if z >= 0.0 && z <= self.length {
+                    keep_closest(&mut best, interval, self.local_hit(local, t));
+                }
+            }
+        }
+
+        // end caps, only the half of each sphere beyond the segment
+        for (center_z, beyond) in [(0.0, -1.0), (self.length, 1.0)] {
+            let oc = o - Vec3::new(0.0, 0.0, center_z);
+            let (solution_exists, t0, t1) = quadratic_formula(d.dot(d), 2.0 * oc.dot(d), oc.dot(oc) - radius_squared);
+            if solution_exists {
+                for t in [t0, t1] {
+                    let z = o.z + t * d.z;
+                    if (z - center_z) * beyond >= 0.0 {
+                        keep_closest(&mut best, interval, self.local_hit(local, t));
+                    }
+                }
+            }
+        }
+
+        record_local_hit(&self.frame, ray, best, &self.mat, interval, hit_record)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+impl Solid for Capsule {
+    fn is_point_inside(&self, point: Vec3) -> bool {
+        let p = self.frame.point_to_local(point);
+        let axis_point = Vec3::new(0.0, 0.0, p.z.clamp(0.0, self.length));
+        (p - axis_point).length_squared() < self.radius * self.radius
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::tests::check_closed_surface;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+
+    #[test]
+    fn tips_and_side() {
+        let capsule = Capsule::new(Vec3::new(0.0, -1.0, -5.0), Vec3::new(0.0, 1.0, -5.0), 0.5, material());
+        let hit = first_hit(&capsule, Ray::new(Vec3::new(0.0, 5.0, -5.0), Vec3::new(0.0, -1.0, 0.0))).unwrap();
+        assert!((hit.t - 3.5).abs() < 1e-12 && (hit.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        assert!((hit.v - 1.0).abs() < 1e-12);
+        let hit = first_hit(&capsule, Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-12 && (hit.v - 0.5).abs() < 1e-12);
+        assert!(first_hit(&capsule, Ray::new(Vec3::new(0.45, 1.45, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+    }
+
+    #[test]
+    fn closed_surface() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let axis = Vec3::new(0.3, -1.0, 1.0).normalized();
+        check_closed_surface(&Capsule::new(center - axis, center + axis, 0.6, material()), center, 5.0);
+        // Both ends at the same point make a sphere
+        check_closed_surface(&Capsule::new(center, center, 0.6, material()), center, 5.0);
+    }
+}
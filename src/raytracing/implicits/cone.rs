@@ -0,0 +1,155 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::implicits::{azimuth_u, keep_closest, quadratic_formula, record_local_hit, LocalFrame, LocalHit};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::solid::Solid;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Cone or truncated cone (frustum). The radius goes linearly from base_radius
+// at the base to top_radius at the top, so a plain cone has top_radius 0.
+// uv is the same as Cylinder.
+pub struct Cone {
+    frame: LocalFrame,
+    base_radius: f64,
+    top_radius: f64,
+    height: f64,
+    capped: bool,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, apex: Vec3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        Self::new_truncated(base, apex, radius, 0.0, mat)
+    }
+
+    pub fn new_truncated(base: Vec3, top: Vec3, base_radius: f64, top_radius: f64, mat: Arc<dyn Material>) -> Self {
+        let frame = LocalFrame::new(base, top - base);
+        let height = (top - base).length();
+        let bbox = AABB::from_aabbs(frame.circle_bbox(0.0, base_radius), frame.circle_bbox(height, top_radius));
+        Self { frame, base_radius, top_radius, height, capped: true, mat, bbox }
+    }
+
+    pub fn uncapped(mut self) -> Self {
+        self.capped = false;
+        self
+    }
+
+    // Change in radius per unit of height
+    fn slope(&self) -> f64 {
+        (self.top_radius - self.base_radius) / self.height
+    }
+
+    fn radius_at(&self, z: f64) -> f64 {
+        self.base_radius + self.slope() * z
+    }
+}
+
+impl Hittable for Cone {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let local = self.frame.ray_to_local(ray);
+        let (o, d) = (local.origin, local.direction);
+        let k = self.slope();
+        let mut best = None;
+
+        // x^2 + y^2 = (base_radius + k z)^2
+        let radius_at_origin = self.radius_at(o.z);
+        let a = d.x * d.x + d.y * d.y - k * k * d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.y * d.y - k * radius_at_origin * d.z);
+        let c = o.x * o.x + o.y * o.y - radius_at_origin * radius_at_origin;
+        let (solution_exists, t0, t1) = quadratic_formula(a, b, c);
+        if solution_exists {
+            for t in [t0, t1] {
+                let p = local.at(t);
+                // The equation also describes the mirrored cone on the other side of the apex,
+                // which always lies outside 0..height.
+                if p.z >= 0.0 && p.z <= self.height {
+                    keep_closest(&mut best, interval, LocalHit {
+                        t,
+                        outward_normal: Vec3::new(p.x, p.y, -k * self.radius_at(p.z)).normalized(),
+                        u: azimuth_u(p),
+                        v: p.z / self.height,
+                    });
+                }
+            }
+        }
+
+        if self.capped && d.z != 0.0 {
+            for (z, radius, normal_z) in [(0.0, self.base_radius, -1.0), (self.height, self.top_radius, 1.0)] {
+                if radius <= 0.0 {
+                    continue;
+                }
+                let t = (z - o.z) / d.z;
+                let p = local.at(t);
+                if p.x * p.x + p.y * p.y <= radius * radius {
+                    keep_closest(&mut best, interval, LocalHit {
+                        t,
+                        outward_normal: Vec3::new(0.0, 0.0, normal_z),
+                        u: 0.5 + p.x / (2.0 * radius),
+                        v: 0.5 + p.y / (2.0 * radius),
+                    });
+                }
+            }
+        }
+
+        record_local_hit(&self.frame, ray, best, &self.mat, interval, hit_record)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+impl Solid for Cone {
+    fn is_point_inside(&self, point: Vec3) -> bool {
+        let p = self.frame.point_to_local(point);
+        let radius = self.radius_at(p.z);
+        p.z > 0.0 && p.z < self.height && p.x * p.x + p.y * p.y < radius * radius
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::tests::check_closed_surface;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+
+    #[test]
+    fn side_and_base() {
+        // Radius 1 at y = -1 down to the apex at y = 1
+        let cone = Cone::new(Vec3::new(0.0, -1.0, -5.0), Vec3::new(0.0, 1.0, -5.0), 1.0, material());
+        let hit = first_hit(&cone, Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-12);
+        let expected = Vec3::new(0.0, 1.0, 2.0).normalized();
+        assert!((hit.normal - expected).length() < 1e-12);
+        let hit = first_hit(&cone, Ray::new(Vec3::new(0.3, -5.0, -5.0), Vec3::new(0.0, 1.0, 0.0))).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-12 && (hit.normal - Vec3::new(0.0, -1.0, 0.0)).length() < 1e-12);
+        // The mirrored nappe above the apex isn't part of the cone
+        assert!(first_hit(&cone, Ray::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+        let open = Cone::new(Vec3::new(0.0, -1.0, -5.0), Vec3::new(0.0, 1.0, -5.0), 1.0, material()).uncapped();
+        let hit = first_hit(&open, Ray::new(Vec3::new(0.3, -5.0, -5.0), Vec3::new(0.0, 1.0, 0.0))).unwrap();
+        assert!(hit.t > 4.0 && !hit.front_face);
+    }
+
+    #[test]
+    fn closed_surface() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let axis = Vec3::new(-1.0, 0.5, 2.0).normalized();
+        check_closed_surface(&Cone::new(center - axis, center + axis, 0.8, material()), center, 5.0);
+        check_closed_surface(&Cone::new_truncated(center - axis, center + axis, 0.8, 0.3, material()), center, 5.0);
+    }
+}
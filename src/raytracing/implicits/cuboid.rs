@@ -0,0 +1,123 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::solid::Solid;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Solid axis-aligned box. Unlike a box made of six quads it has an inside, so it works with CSG.
+// uv runs across each face along the next two axes (x face: y and z, y face: z and x, z face: x and y).
+pub struct Cuboid {
+    min: Vec3,
+    max: Vec3,
+    mat: Arc<dyn Material>,
+}
+
+impl Cuboid {
+    // Any two opposite corners
+    pub fn new(a: Vec3, b: Vec3, mat: Arc<dyn Material>) -> Self {
+        Self {
+            min: Vec3::new(f64::min(a.x, b.x), f64::min(a.y, b.y), f64::min(a.z, b.z)),
+            max: Vec3::new(f64::max(a.x, b.x), f64::max(a.y, b.y), f64::max(a.z, b.z)),
+            mat,
+        }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        // Slab test, remembering which axis each end of the span came from
+        let (mut t_near, mut near_axis) = (f64::NEG_INFINITY, 0);
+        let (mut t_far, mut far_axis) = (f64::INFINITY, 0);
+        for axis in 0..3 {
+            if ray.direction[axis] == 0.0 {
+                if ray.origin[axis] < self.min[axis] || ray.origin[axis] > self.max[axis] {
+                    return false;
+                }
+                continue;
+            }
+            let inverse = 1.0 / ray.direction[axis];
+            let t0 = (self.min[axis] - ray.origin[axis]) * inverse;
+            let t1 = (self.max[axis] - ray.origin[axis]) * inverse;
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            if t0 > t_near {
+                t_near = t0;
+                near_axis = axis;
+            }
+            if t1 < t_far {
+                t_far = t1;
+                far_axis = axis;
+            }
+        }
+        if t_near > t_far {
+            return false;
+        }
+        let (t, axis, sign) = if interval.contains(t_near) {
+            (t_near, near_axis, -ray.direction[near_axis].signum())
+        } else if interval.contains(t_far) {
+            (t_far, far_axis, ray.direction[far_axis].signum())
+        } else {
+            return false;
+        };
+
+        let point = ray.at(t);
+        let mut outward_normal = Vec3::new(0.0, 0.0, 0.0);
+        outward_normal[axis] = sign;
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        interval.upper_bound = t;
+        hit_record.t = t;
+        hit_record.point = point;
+        hit_record.u = (point[u_axis] - self.min[u_axis]) / (self.max[u_axis] - self.min[u_axis]);
+        hit_record.v = (point[v_axis] - self.min[v_axis]) / (self.max[v_axis] - self.min[v_axis]);
+        hit_record.mat = Some(self.mat.clone());
+        hit_record.set_face_normal(ray, outward_normal);
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        AABB::from_corners(self.min, self.max)
+    }
+}
+
+impl Solid for Cuboid {
+    fn is_point_inside(&self, point: Vec3) -> bool {
+        (0..3).all(|axis| point[axis] > self.min[axis] && point[axis] < self.max[axis])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::sphere::Sphere;
+    use crate::raytracing::implicits::tests::check_closed_surface;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn faces_and_uvs() {
+        let cuboid = Cuboid::new(Vec3::new(1.0, 2.0, -4.0), Vec3::new(-1.0, 0.0, -6.0), material());
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        let ray = Ray::new(Vec3::new(0.5, 1.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(cuboid.first_hit_on_interval(ray, &mut interval, &mut hit_record));
+        assert!((hit_record.t - 4.0).abs() < 1e-12 && (hit_record.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!((hit_record.u - 0.75).abs() < 1e-12 && (hit_record.v - 0.75).abs() < 1e-12);
+        // From the inside, the far face is a back face
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let ray = Ray::new(Vec3::new(0.0, 1.0, -5.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(cuboid.first_hit_on_interval(ray, &mut interval, &mut hit_record));
+        assert!((hit_record.t - 1.0).abs() < 1e-12 && !hit_record.front_face);
+    }
+
+    #[test]
+    fn closed_surface() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        check_closed_surface(&Cuboid::new(center - Vec3::new(0.5, 1.0, 1.5), center + Vec3::new(0.5, 1.0, 1.5), material()), center, 5.0);
+        check_closed_surface(&Sphere::new(center, 1.2, material()), center, 5.0);
+    }
+}
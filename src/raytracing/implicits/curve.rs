@@ -0,0 +1,394 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::implicits::LocalFrame;
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CurveBasis {
+    Bezier,
+    // Uniform cubic B-spline, smooth across segments but doesn't pass through its control points
+    BSpline,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CurveMode {
+    // Flat strip that always faces the ray, cheapest and fine for hair and fur
+    Ribbon,
+    // Round cross-section, for cables and anything seen up close
+    Tube,
+}
+
+// One cubic segment of a hair, blade of grass or cable, with a radius that goes
+// linearly from radius.0 at its start to radius.1 at its end.
+// Found like pbrt's curves: the segment is moved to a space where the ray runs along z,
+// then split in halves until the pieces are nearly straight, skipping the halves whose
+// bounds the ray misses. Tubes start from the ribbon's hit and bisect along the ray
+// for the round surface around it.
+// u runs across the curve and v along it (see with_v_range), tangent points along it.
+pub struct Curve {
+    // Always Bezier control points, B-splines are converted
+    points: [Vec3; 4],
+    radius: (f64, f64),
+    mode: CurveMode,
+    v_range: (f64, f64),
+    max_depth: u32,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Curve {
+    // Hits closer than this fraction of the radius are the surface the ray left from
+    const SELF_HIT_FRACTION: f64 = 0.01;
+    const CLOSEST_POINT_STEPS: usize = 3;
+    const TUBE_BISECTION_STEPS: usize = 30;
+
+    pub fn new(points: [Vec3; 4], basis: CurveBasis, radius: (f64, f64), mode: CurveMode, mat: Arc<dyn Material>) -> Self {
+        let [p0, p1, p2, p3] = points;
+        let points = match basis {
+            CurveBasis::Bezier => points,
+            CurveBasis::BSpline => [
+                (p0 + 4.0 * p1 + p2) / 6.0,
+                (2.0 * p1 + p2) / 3.0,
+                (p1 + 2.0 * p2) / 3.0,
+                (p1 + 4.0 * p2 + p3) / 6.0,
+            ],
+        };
+        let max_radius = radius.0.max(radius.1);
+        let expand = Vec3::new(max_radius, max_radius, max_radius);
+        let bbox = points.iter().fold(AABB::EMPTY, |bbox, p| AABB::from_aabbs(bbox, AABB::from_corners(*p - expand, *p + expand)));
+
+        // Enough halvings for the pieces to be within a tenth of the radius of straight
+        // (pbrt's bound from the control points' second differences)
+        let bend = (0..2)
+            .map(|i| (points[i] - 2.0 * points[i + 1] + points[i + 2]).length())
+            .fold(0.0, f64::max);
+        let tolerance = 0.1 * max_radius;
+        let max_depth = if bend > 0.0 && tolerance > 0.0 {
+            ((std::f64::consts::SQRT_2 * 6.0 * bend / (8.0 * tolerance)).log2() / 2.0).clamp(0.0, 10.0) as u32
+        } else {
+            0
+        };
+        Self {
+            points,
+            radius,
+            mode,
+            v_range: (0.0, 1.0),
+            max_depth,
+            mat,
+            bbox,
+        }
+    }
+
+    // v at the segment's start and end, for segments that are part of a longer strand
+    pub fn with_v_range(mut self, start: f64, end: f64) -> Self {
+        self.v_range = (start, end);
+        self
+    }
+
+    // A whole strand: a Bezier strand has 3n + 1 points with neighbouring segments sharing their end point,
+    // a B-spline strand has one segment per point after the third. The radius goes from radius.0 at the
+    // root to radius.1 at the tip, and v from 0 to 1.
+    pub fn strand(points: &[Vec3], basis: CurveBasis, radius: (f64, f64), mode: CurveMode, mat: Arc<dyn Material>) -> Vec<Curve> {
+        let segments: Vec<[Vec3; 4]> = match basis {
+            CurveBasis::Bezier => {
+                assert!(points.len() >= 4 && (points.len() - 1).is_multiple_of(3), "a Bezier strand needs 3n + 1 points, got {}", points.len());
+                points.windows(4).step_by(3).map(|w| [w[0], w[1], w[2], w[3]]).collect()
+            }
+            CurveBasis::BSpline => {
+                assert!(points.len() >= 4, "a B-spline strand needs at least 4 points, got {}", points.len());
+                points.windows(4).map(|w| [w[0], w[1], w[2], w[3]]).collect()
+            }
+        };
+        let count = segments.len() as f64;
+        let radius_at = |v: f64| radius.0 + v * (radius.1 - radius.0);
+        segments.iter().enumerate().map(|(i, segment)| {
+            let (start, end) = (i as f64 / count, (i + 1) as f64 / count);
+            Curve::new(*segment, basis, (radius_at(start), radius_at(end)), mode, mat.clone()).with_v_range(start, end)
+        }).collect()
+    }
+
+    fn evaluate(&self, u: f64) -> Vec3 {
+        let [p0, p1, p2, p3] = self.points;
+        let s = 1.0 - u;
+        s * s * s * p0 + 3.0 * s * s * u * p1 + 3.0 * s * u * u * p2 + u * u * u * p3
+    }
+
+    fn tangent(&self, u: f64) -> Vec3 {
+        let [p0, p1, p2, p3] = self.points;
+        let s = 1.0 - u;
+        let derivative = s * s * (p1 - p0) + 2.0 * s * u * (p2 - p1) + u * u * (p3 - p2);
+        // Control points on top of each other at an end leave no derivative there
+        if derivative.length_squared() > 0.0 { derivative.normalized() } else { (p3 - p0).normalized() }
+    }
+
+    fn radius_at(&self, u: f64) -> f64 {
+        self.radius.0 + u * (self.radius.1 - self.radius.0)
+    }
+
+    fn second_derivative(&self, u: f64) -> Vec3 {
+        let [p0, p1, p2, p3] = self.points;
+        6.0 * ((1.0 - u) * (p2 - 2.0 * p1 + p0) + u * (p3 - 2.0 * p2 + p1))
+    }
+
+    // Signed distance from point to the tube's surface, negative inside, measured from
+    // the curve's closest point (Newton steps from u). Also returns the closest point's u.
+    fn tube_distance(&self, point: Vec3, u: f64) -> (f64, f64) {
+        let [p0, p1, p2, p3] = self.points;
+        let mut u = u;
+        for _ in 0..Self::CLOSEST_POINT_STEPS {
+            let s = 1.0 - u;
+            let first = 3.0 * (s * s * (p1 - p0) + 2.0 * s * u * (p2 - p1) + u * u * (p3 - p2));
+            let offset = self.evaluate(u) - point;
+            let slope = first.dot(first) + offset.dot(self.second_derivative(u));
+            if slope <= 0.0 {
+                break;
+            }
+            u = (u - offset.dot(first) / slope).clamp(0.0, 1.0);
+        }
+        ((point - self.evaluate(u)).length() - self.radius_at(u), u)
+    }
+
+    // Bisects for where the ray (unit direction) crosses the tube's surface between
+    // inside_z, which has to be inside, and outside_z. Returns the crossing's u and z.
+    fn tube_crossing(&self, origin: Vec3, direction: Vec3, u: f64, inside_z: f64, outside_z: f64) -> Option<(f64, f64)> {
+        let (mut inside, mut outside) = (inside_z, outside_z);
+        if self.tube_distance(origin + inside * direction, u).0 > 0.0 || self.tube_distance(origin + outside * direction, u).0 < 0.0 {
+            return None;
+        }
+        let mut u = u;
+        for _ in 0..Self::TUBE_BISECTION_STEPS {
+            let middle = 0.5 * (inside + outside);
+            let (distance, closest) = self.tube_distance(origin + middle * direction, u);
+            u = closest;
+            if distance < 0.0 {
+                inside = middle;
+            } else {
+                outside = middle;
+            }
+        }
+        Some((u, 0.5 * (inside + outside)))
+    }
+
+    // cp is a piece of the curve in ray space (ray from the origin along +z), from u_start to u_end.
+    // Keeps the nearest hit in best as (u, distance along the ray), and shrinks z to it.
+    fn intersect_piece(&self, ray: Ray, cp: [Vec3; 4], (u_start, u_end): (f64, f64), depth: u32, z: &mut Interval, best: &mut Option<(f64, f64)>) {
+        let max_radius = self.radius_at(u_start).max(self.radius_at(u_end));
+        let (min, max) = cp.iter().fold((cp[0], cp[0]), |(min, max), p| {
+            (Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)), Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)))
+        });
+        if min.x - max_radius > 0.0 || max.x + max_radius < 0.0 || min.y - max_radius > 0.0 || max.y + max_radius < 0.0
+            || min.z - max_radius > z.upper_bound || max.z + max_radius < z.lower_bound {
+            return;
+        }
+
+        if depth > 0 {
+            // de Casteljau split at the middle
+            let [p0, p1, p2, p3] = cp;
+            let (a, b, c) = (0.5 * (p0 + p1), 0.5 * (p1 + p2), 0.5 * (p2 + p3));
+            let (d, e) = (0.5 * (a + b), 0.5 * (b + c));
+            let middle = 0.5 * (d + e);
+            let u_middle = 0.5 * (u_start + u_end);
+            self.intersect_piece(ray, [p0, a, d, middle], (u_start, u_middle), depth - 1, z, best);
+            self.intersect_piece(ray, [middle, e, c, p3], (u_middle, u_end), depth - 1, z, best);
+            return;
+        }
+
+        // The piece is nearly straight. The ray has to pass between the lines
+        // perpendicular to it at both ends, so neighbouring pieces don't both claim a hit.
+        let [p0, p1, p2, p3] = cp;
+        if (p1.x - p0.x) * -p0.x + (p1.y - p0.y) * -p0.y < 0.0 || (p2.x - p3.x) * -p3.x + (p2.y - p3.y) * -p3.y < 0.0 {
+            return;
+        }
+        let (dx, dy) = (p3.x - p0.x, p3.y - p0.y);
+        let length_squared = dx * dx + dy * dy;
+        if length_squared == 0.0 {
+            return;
+        }
+        let w = ((-p0.x * dx - p0.y * dy) / length_squared).clamp(0.0, 1.0);
+        let u = u_start + w * (u_end - u_start);
+
+        // Back in world space: how close the ray passes to the curve at u, and where
+        let direction = ray.direction.normalized();
+        let center = self.evaluate(u);
+        let along = (center - ray.origin).dot(direction);
+        let distance = (ray.origin + along * direction - center).length();
+        let radius = self.radius_at(u);
+        if distance > radius {
+            return;
+        }
+        // A ray that starts on the curve (scattered off it) would find the ribbon facing it again,
+        // tubes are left through their far side instead.
+        // The point closest to the curve is inside the tube, the surface is found between it and
+        // a point far enough along the ray to be outside (further for rays running along the curve).
+        let starts_on_curve = (ray.origin - center).length() <= 1.01 * radius;
+        let reach = radius + radius / self.tangent(u).cross(direction).length().max(0.01);
+        let (u, hit_z) = match self.mode {
+            CurveMode::Ribbon if starts_on_curve => return,
+            CurveMode::Ribbon => (u, along),
+            CurveMode::Tube => {
+                let outside_z = if starts_on_curve { along + reach } else { along - reach };
+                match self.tube_crossing(ray.origin, direction, u, along, outside_z) {
+                    Some(crossing) => crossing,
+                    None => return,
+                }
+            }
+        };
+        if hit_z < Self::SELF_HIT_FRACTION * radius || !z.surrounds(hit_z) {
+            return;
+        }
+        z.upper_bound = hit_z;
+        *best = Some((u, hit_z));
+    }
+}
+
+impl Hittable for Curve {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        if self.bbox.clip(ray, *interval).is_none() {
+            return false;
+        }
+        let length = ray.direction.length();
+        if length == 0.0 {
+            return false;
+        }
+        let frame = LocalFrame::new(ray.origin, ray.direction);
+        let cp = self.points.map(|p| frame.point_to_local(p));
+        let mut z = Interval::new(interval.lower_bound * length, interval.upper_bound * length);
+        let mut best = None;
+        self.intersect_piece(ray, cp, (0.0, 1.0), self.max_depth, &mut z, &mut best);
+        let Some((u, hit_z)) = best else {
+            return false;
+        };
+
+        let t = hit_z / length;
+        let point = ray.at(t);
+        let direction = ray.direction / length;
+        let tangent = self.tangent(u);
+        let center = self.evaluate(u);
+        let side = tangent.cross(direction);
+        let across = side.dot(point - center);
+        let radius = self.radius_at(u);
+        let outward_normal = match self.mode {
+            // Perpendicular to the curve, in the plane of the curve and the ray
+            CurveMode::Ribbon if side.length_squared() > 0.0 => tangent.cross(side).normalized(),
+            CurveMode::Ribbon => -direction,
+            CurveMode::Tube => {
+                let radial = point - center;
+                let radial = radial - radial.dot(tangent) * tangent;
+                if radial.length_squared() > 0.0 { radial.normalized() } else { -direction }
+            }
+        };
+        interval.upper_bound = t;
+        hit_record.t = t;
+        hit_record.point = point;
+        hit_record.u = if side.length_squared() > 0.0 && radius > 0.0 {
+            (0.5 + 0.5 * across / (side.length() * radius)).clamp(0.0, 1.0)
+        } else {
+            0.5
+        };
+        hit_record.v = self.v_range.0 + u * (self.v_range.1 - self.v_range.0);
+        hit_record.mat = Some(self.mat.clone());
+        hit_record.set_face_normal(ray, outward_normal);
+        hit_record.tangent = tangent;
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+    // Straight along x at z = -5, from x = -1 to 1
+    fn straight(mode: CurveMode) -> Curve {
+        let points = [-1.0, -1.0 / 3.0, 1.0 / 3.0, 1.0].map(|x| Vec3::new(x, 0.0, -5.0));
+        Curve::new(points, CurveBasis::Bezier, (0.2, 0.2), mode, material())
+    }
+
+    #[test]
+    fn straight_tube_is_a_cylinder() {
+        let tube = straight(CurveMode::Tube);
+        for y in [0.0, 0.1, -0.15, 0.19] {
+            let hit = first_hit(&tube, Ray::new(Vec3::new(0.3, y, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+            let expected = 5.0 - (0.04 - y * y).sqrt();
+            assert!((hit.t - expected).abs() < 1e-4, "y {}: {} != {}", y, hit.t, expected);
+            let normal = Vec3::new(0.0, y, 5.0 - hit.t) / 0.2;
+            assert!((hit.normal - normal).length() < 1e-2, "y {}", y);
+            assert!((hit.v - 0.65).abs() < 1e-3);
+        }
+        assert!(first_hit(&tube, Ray::new(Vec3::new(0.3, 0.21, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+        assert!(first_hit(&tube, Ray::new(Vec3::new(1.1, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+    }
+
+    #[test]
+    fn ribbon_faces_the_ray() {
+        let ribbon = straight(CurveMode::Ribbon);
+        let hit = first_hit(&ribbon, Ray::new(Vec3::new(-0.5, 0.1, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-6);
+        assert!(hit.front_face && (hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-6);
+        assert!((hit.u - 0.25).abs() < 1e-6 || (hit.u - 0.75).abs() < 1e-6);
+        assert!((hit.tangent - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-6);
+    }
+
+    #[test]
+    fn bent_tube_hits_stay_on_the_surface() {
+        let points = [Vec3::new(-1.0, 0.0, -5.0), Vec3::new(-0.5, 1.5, -5.0), Vec3::new(0.5, -1.5, -5.5), Vec3::new(1.0, 0.5, -5.0)];
+        let curve = Curve::new(points, CurveBasis::Bezier, (0.15, 0.05), CurveMode::Tube, material());
+        let mut hits = 0;
+        for x in 0..40 {
+            for y in 0..40 {
+                let ray = Ray::new(Vec3::new(x as f64 * 0.055 - 1.1, y as f64 * 0.05 - 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+                let Some(hit) = first_hit(&curve, ray) else {
+                    continue;
+                };
+                hits += 1;
+                // Distance to the closest sampled point of the center line, against the radius there
+                let (distance, u) = (0..=2000)
+                    .map(|i| i as f64 / 2000.0)
+                    .map(|u| ((hit.point - curve.evaluate(u)).length(), u))
+                    .fold((f64::INFINITY, 0.0), |best, candidate| if candidate.0 < best.0 { candidate } else { best });
+                assert!((distance - curve.radius_at(u)).abs() < 0.01, "ray {} {}: {} from the center, radius {}", x, y, distance, curve.radius_at(u));
+            }
+        }
+        assert!(hits > 20);
+    }
+
+    #[test]
+    fn b_splines_and_strands() {
+        let points: Vec<Vec3> = (0..6).map(|i| Vec3::new(i as f64, 0.0, 0.0)).collect();
+        let curve = Curve::new([points[0], points[1], points[2], points[3]], CurveBasis::BSpline, (0.1, 0.1), CurveMode::Ribbon, material());
+        assert!((curve.evaluate(0.0) - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12);
+        assert!((curve.evaluate(1.0) - Vec3::new(2.0, 0.0, 0.0)).length() < 1e-12);
+
+        let strand = Curve::strand(&points, CurveBasis::BSpline, (0.2, 0.0), CurveMode::Tube, material());
+        assert_eq!(strand.len(), 3);
+        assert_eq!(strand[1].v_range, (1.0 / 3.0, 2.0 / 3.0));
+        assert!((strand[2].radius.1 - 0.0).abs() < 1e-12 && (strand[0].radius.0 - 0.2).abs() < 1e-12);
+        let bezier: Vec<Vec3> = (0..7).map(|i| Vec3::new(i as f64, 0.0, 0.0)).collect();
+        assert_eq!(Curve::strand(&bezier, CurveBasis::Bezier, (0.1, 0.1), CurveMode::Tube, material()).len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "3n + 1 points")]
+    fn bezier_strands_need_whole_segments() {
+        let points: Vec<Vec3> = (0..6).map(|i| Vec3::new(i as f64, 0.0, 0.0)).collect();
+        Curve::strand(&points, CurveBasis::Bezier, (0.1, 0.1), CurveMode::Tube, material());
+    }
+}
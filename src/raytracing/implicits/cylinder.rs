@@ -0,0 +1,129 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::implicits::{azimuth_u, keep_closest, quadratic_formula, record_local_hit, LocalFrame, LocalHit};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::solid::Solid;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Side uv: u goes around the axis, v from base (0) to top (1).
+// Cap uv: planar, the cap's circle mapped to the unit square.
+pub struct Cylinder {
+    frame: LocalFrame,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Cylinder {
+    // Capped cylinder between the centers of its two caps
+    pub fn new(base: Vec3, top: Vec3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        let frame = LocalFrame::new(base, top - base);
+        let height = (top - base).length();
+        let bbox = AABB::from_aabbs(frame.circle_bbox(0.0, radius), frame.circle_bbox(height, radius));
+        Self { frame, radius, height, capped: true, mat, bbox }
+    }
+
+    // Open tube, the inside is visible through the ends
+    pub fn uncapped(mut self) -> Self {
+        self.capped = false;
+        self
+    }
+}
+
+impl Hittable for Cylinder {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let local = self.frame.ray_to_local(ray);
+        let (o, d) = (local.origin, local.direction);
+        let mut best = None;
+
+        let a = d.x * d.x + d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.y * d.y);
+        let c = o.x * o.x + o.y * o.y - self.radius * self.radius;
+        let (solution_exists, t0, t1) = quadratic_formula(a, b, c);
+        if solution_exists {
+            for t in [t0, t1] {
+                let p = local.at(t);
+                if p.z >= 0.0 && p.z <= self.height {
+                    keep_closest(&mut best, interval, LocalHit {
+                        t,
+                        outward_normal: Vec3::new(p.x, p.y, 0.0) / self.radius,
+                        u: azimuth_u(p),
+                        v: p.z / self.height,
+                    });
+                }
+            }
+        }
+
+        if self.capped && d.z != 0.0 {
+            for (z, normal_z) in [(0.0, -1.0), (self.height, 1.0)] {
+                let t = (z - o.z) / d.z;
+                let p = local.at(t);
+                if p.x * p.x + p.y * p.y <= self.radius * self.radius {
+                    keep_closest(&mut best, interval, LocalHit {
+                        t,
+                        outward_normal: Vec3::new(0.0, 0.0, normal_z),
+                        u: 0.5 + p.x / (2.0 * self.radius),
+                        v: 0.5 + p.y / (2.0 * self.radius),
+                    });
+                }
+            }
+        }
+
+        record_local_hit(&self.frame, ray, best, &self.mat, interval, hit_record)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+impl Solid for Cylinder {
+    // An uncapped cylinder still counts as closed here
+    fn is_point_inside(&self, point: Vec3) -> bool {
+        let p = self.frame.point_to_local(point);
+        p.x * p.x + p.y * p.y < self.radius * self.radius && p.z > 0.0 && p.z < self.height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::tests::check_closed_surface;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+
+    #[test]
+    fn side_and_caps() {
+        let cylinder = Cylinder::new(Vec3::new(0.0, -1.0, -5.0), Vec3::new(0.0, 1.0, -5.0), 0.5, material());
+        let hit = first_hit(&cylinder, Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-12 && (hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!((hit.v - 0.75).abs() < 1e-12);
+        let hit = first_hit(&cylinder, Ray::new(Vec3::new(0.2, 5.0, -5.0), Vec3::new(0.0, -1.0, 0.0))).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-12 && (hit.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        // Down the open tube there is nothing to hit
+        let tube = Cylinder::new(Vec3::new(0.0, -1.0, -5.0), Vec3::new(0.0, 1.0, -5.0), 0.5, material()).uncapped();
+        assert!(first_hit(&tube, Ray::new(Vec3::new(0.2, 5.0, -5.0), Vec3::new(0.0, -1.0, 0.0))).is_none());
+    }
+
+    #[test]
+    fn closed_surface() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        let axis = Vec3::new(1.0, 2.0, -0.5).normalized();
+        check_closed_surface(&Cylinder::new(center - axis, center + axis, 0.7, material()), center, 5.0);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::implicits::{azimuth_u, record_local_hit, LocalFrame, LocalHit};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::solid::Solid;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Flat disk, or an annulus when inner_radius > 0.
+// uv is polar: u goes around the center, v from the inner (0) to the outer (1) edge.
+pub struct Disk {
+    frame: LocalFrame,
+    inner_radius: f64,
+    outer_radius: f64,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        Self::new_annulus(center, normal, 0.0, radius, mat)
+    }
+
+    pub fn new_annulus(center: Vec3, normal: Vec3, inner_radius: f64, outer_radius: f64, mat: Arc<dyn Material>) -> Self {
+        let frame = LocalFrame::new(center, normal);
+        // AABB::new pads the flat axis
+        let circle = frame.circle_bbox(0.0, outer_radius);
+        let bbox = AABB::new(circle.x, circle.y, circle.z);
+        Self { frame, inner_radius, outer_radius, mat, bbox }
+    }
+}
+
+impl Hittable for Disk {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let local = self.frame.ray_to_local(ray);
+        if local.direction.z.abs() < 1e-8 {
+            return false;
+        }
+        let t = -local.origin.z / local.direction.z;
+        if !interval.contains(t) {
+            return false;
+        }
+        let p = local.at(t);
+        let distance = (p.x * p.x + p.y * p.y).sqrt();
+        if distance < self.inner_radius || distance > self.outer_radius {
+            return false;
+        }
+        let hit = LocalHit {
+            t,
+            outward_normal: Vec3::new(0.0, 0.0, 1.0),
+            u: azimuth_u(p),
+            v: (distance - self.inner_radius) / (self.outer_radius - self.inner_radius),
+        };
+        record_local_hit(&self.frame, ray, Some(hit), &self.mat, interval, hit_record)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+impl Solid for Disk {
+    // No volume, nothing is inside
+    fn is_point_inside(&self, _point: Vec3) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+
+    #[test]
+    fn disk_and_annulus() {
+        let disk = Disk::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 2.0, material());
+        let hit = first_hit(&disk, Ray::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-12 && (hit.v - 0.5).abs() < 1e-12 && hit.front_face);
+        assert!(first_hit(&disk, Ray::new(Vec3::new(2.1, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+        assert!(first_hit(&disk, Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0))).is_none());
+        let hit = first_hit(&disk, Ray::new(Vec3::new(1.0, 0.0, -8.0), Vec3::new(0.0, 0.0, 1.0))).unwrap();
+        assert!(!hit.front_face);
+
+        let annulus = Disk::new_annulus(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 1.0, 2.0, material());
+        assert!(first_hit(&annulus, Ray::new(Vec3::new(0.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+        let hit = first_hit(&annulus, Ray::new(Vec3::new(0.0, 1.5, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.v - 0.5).abs() < 1e-12);
+        // A flat disk only has room along its normal because the box is padded
+        let bbox = annulus.bounding_box();
+        assert!(bbox.z.size() > 0.0 && (bbox.x.upper_bound - 2.0).abs() < 1e-12);
+        assert!(!annulus.is_point_inside(Vec3::new(0.0, 1.5, -5.0)));
+    }
+}
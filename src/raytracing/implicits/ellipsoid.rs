@@ -0,0 +1,114 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::implicits::sphere::Sphere;
+use crate::raytracing::implicits::{quadratic_formula, record_local_hit, LocalFrame, LocalHit};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::solid::Solid;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Axis-aligned ellipsoid, a unit sphere scaled by `radii`.
+// uv is the sphere's uv before scaling.
+pub struct Ellipsoid {
+    frame: LocalFrame,
+    radii: Vec3,
+    inverse_radii: Vec3,
+    mat: Arc<dyn Material>,
+}
+
+impl Ellipsoid {
+    pub fn new(center: Vec3, radii: Vec3, mat: Arc<dyn Material>) -> Self {
+        Self {
+            frame: LocalFrame::new(center, Vec3::new(0.0, 0.0, 1.0)),
+            radii,
+            inverse_radii: Vec3::new(1.0 / radii.x, 1.0 / radii.y, 1.0 / radii.z),
+            mat,
+        }
+    }
+}
+
+impl Hittable for Ellipsoid {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let local = self.frame.ray_to_local(ray);
+        // Scaling doesn't change t, so solve against the unit sphere
+        let o = local.origin.scaled_non_uniform(self.inverse_radii);
+        let d = local.direction.scaled_non_uniform(self.inverse_radii);
+        let (solution_exists, t0, t1) = quadratic_formula(d.dot(d), 2.0 * o.dot(d), o.dot(o) - 1.0);
+        if !solution_exists {
+            return false;
+        }
+        let t = if interval.contains(t0) {
+            t0
+        } else if interval.contains(t1) {
+            t1
+        } else {
+            return false;
+        };
+
+        let unit_point = o + t * d;
+        let (u, v) = Sphere::get_sphere_uv(unit_point);
+        let hit = LocalHit {
+            t,
+            // gradient of (x/a)^2 + (y/b)^2 + (z/c)^2
+            outward_normal: unit_point.scaled_non_uniform(self.inverse_radii).normalized(),
+            u,
+            v,
+        };
+        record_local_hit(&self.frame, ray, Some(hit), &self.mat, interval, hit_record)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        AABB::from_corners(self.frame.origin - self.radii, self.frame.origin + self.radii)
+    }
+}
+
+impl Solid for Ellipsoid {
+    fn is_point_inside(&self, point: Vec3) -> bool {
+        self.frame.point_to_local(point).scaled_non_uniform(self.inverse_radii).length_squared() < 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::tests::check_closed_surface;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+
+    #[test]
+    fn radii_along_each_axis() {
+        let ellipsoid = Ellipsoid::new(Vec3::new(0.0, 0.0, -10.0), Vec3::new(1.0, 2.0, 3.0), material());
+        let cases = [
+            (Vec3::new(5.0, 0.0, -10.0), Vec3::new(-1.0, 0.0, 0.0), 4.0),
+            (Vec3::new(0.0, 5.0, -10.0), Vec3::new(0.0, -1.0, 0.0), 3.0),
+            (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 7.0),
+        ];
+        for (origin, direction, t) in cases {
+            let hit = first_hit(&ellipsoid, Ray::new(origin, direction)).unwrap();
+            assert!((hit.t - t).abs() < 1e-12 && (hit.normal + direction).length() < 1e-12);
+        }
+        // Normals follow the gradient, not the direction from the center
+        let hit = first_hit(&ellipsoid, Ray::new(Vec3::new(0.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        let local = hit.point - Vec3::new(0.0, 0.0, -10.0);
+        let expected = Vec3::new(local.x, 0.0, local.z / 9.0).normalized();
+        assert!((hit.normal - expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn closed_surface() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        check_closed_surface(&Ellipsoid::new(center, Vec3::new(0.5, 1.5, 1.0), material()), center, 5.0);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::formats::FormatError;
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::implicits::triangle::intersect_triangle;
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::raytracing::texture::{ImageTexture, Texture};
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Terrain from a grid of height samples, without building a mesh.
+// The grid covers corner.x..corner.x + extent.x and corner.z..corner.z + extent.z,
+// and a sample of 1 is extent.y above corner.y. Each cell is split into two triangles,
+// rays walk the cells they cross in order (2D DDA) and stop at the first hit.
+// u runs along x and v along z over the whole grid.
+pub struct Heightfield {
+    corner: Vec3,
+    extent: Vec3,
+    // Samples along x and z
+    columns: usize,
+    rows: usize,
+    // World space y of each sample, row by row
+    heights: Vec<f64>,
+    normals: Vec<Vec3>,
+    // Lowest and highest y of each cell, to skip cells the ray passes over
+    cell_heights: Vec<Interval>,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Heightfield {
+    // samples has columns * rows values, rows run along +z
+    pub fn new(samples: Vec<f64>, columns: usize, rows: usize, corner: Vec3, extent: Vec3, mat: Arc<dyn Material>) -> Result<Self, FormatError> {
+        let sample_count = Self::sample_count(columns, rows)?;
+        if samples.len() != sample_count {
+            return Err(FormatError::parse(0, format!("heightfield has {} samples but {} x {} needs {}", samples.len(), columns, rows, sample_count)));
+        }
+        let heights: Vec<f64> = samples.iter().map(|s| corner.y + s * extent.y).collect();
+        let mut heightfield = Self {
+            corner,
+            extent,
+            columns,
+            rows,
+            heights,
+            normals: vec![],
+            cell_heights: vec![],
+            mat,
+            bbox: AABB::EMPTY,
+        };
+        heightfield.normals = (0..rows)
+            .flat_map(|j| (0..columns).map(move |i| (i, j)))
+            .map(|(i, j)| heightfield.sample_normal(i, j))
+            .collect();
+        let mut y_range = Interval::EMPTY;
+        for j in 0..rows - 1 {
+            for i in 0..columns - 1 {
+                let mut cell = Interval::EMPTY;
+                for (ci, cj) in [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)] {
+                    let y = heightfield.height(ci, cj);
+                    cell = Interval::from_intervals(cell, Interval::new(y, y));
+                }
+                heightfield.cell_heights.push(cell);
+                y_range = Interval::from_intervals(y_range, cell);
+            }
+        }
+        heightfield.bbox = AABB::from_corners(
+            Vec3::new(corner.x, y_range.lower_bound, corner.z),
+            Vec3::new(corner.x + extent.x, y_range.upper_bound, corner.z + extent.z),
+        );
+        Ok(heightfield)
+    }
+
+    fn sample_count(columns: usize, rows: usize) -> Result<usize, FormatError> {
+        if columns < 2 || rows < 2 {
+            return Err(FormatError::parse(0, format!("a heightfield needs at least 2 x 2 samples, not {} x {}", columns, rows)));
+        }
+        columns.checked_mul(rows)
+            .ok_or_else(|| FormatError::parse(0, format!("heightfield of {} x {} samples is too big", columns, rows)))
+    }
+
+    // height(u, v) for u and v in [0, 1], should return values in [0, 1]
+    pub fn from_function(height: impl Fn(f64, f64) -> f64, columns: usize, rows: usize, corner: Vec3, extent: Vec3, mat: Arc<dyn Material>) -> Result<Self, FormatError> {
+        Self::sample_count(columns, rows)?;
+        let samples = (0..rows)
+            .flat_map(|j| (0..columns).map(move |i| (i, j)))
+            .map(|(i, j)| height(i as f64 / (columns - 1) as f64, j as f64 / (rows - 1) as f64))
+            .collect();
+        Self::new(samples, columns, rows, corner, extent, mat)
+    }
+
+    // Brightness of a texture, for example an ImageTexture or a NoiseTexture.
+    // The texture gets the uv and the point on the grid's base plane.
+    pub fn from_texture(texture: &dyn Texture, columns: usize, rows: usize, corner: Vec3, extent: Vec3, mat: Arc<dyn Material>) -> Result<Self, FormatError> {
+        Self::from_function(|u, v| {
+            let point = corner + Vec3::new(u * extent.x, 0.0, v * extent.z);
+            let color = texture.value(u, v, point);
+            (color.x + color.y + color.z) / 3.0
+        }, columns, rows, corner, extent, mat)
+    }
+
+    // One sample per pixel of a grayscale image, the image's top row is at corner.z
+    pub fn from_image(image: &ImageTexture, corner: Vec3, extent: Vec3, mat: Arc<dyn Material>) -> Result<Self, FormatError> {
+        let (width, height) = (image.width(), image.height());
+        Self::sample_count(width, height)?;
+        let samples = (0..height)
+            .flat_map(|j| (0..width).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let color = image.value((i as f64 + 0.5) / width as f64, (j as f64 + 0.5) / height as f64, corner);
+                (color.x + color.y + color.z) / 3.0
+            })
+            .collect();
+        Self::new(samples, width, height, corner, extent, mat)
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        (self.extent.x / (self.columns - 1) as f64, self.extent.z / (self.rows - 1) as f64)
+    }
+
+    fn height(&self, i: usize, j: usize) -> f64 {
+        self.heights[j * self.columns + i]
+    }
+
+    fn point(&self, i: usize, j: usize) -> Vec3 {
+        let (dx, dz) = self.cell_size();
+        Vec3::new(self.corner.x + i as f64 * dx, self.height(i, j), self.corner.z + j as f64 * dz)
+    }
+
+    // Central differences, one-sided on the border
+    fn sample_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (dx, dz) = self.cell_size();
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.columns - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.rows - 1));
+        let slope_x = (self.height(i1, j) - self.height(i0, j)) / ((i1 - i0) as f64 * dx);
+        let slope_z = (self.height(i, j1) - self.height(i, j0)) / ((j1 - j0) as f64 * dz);
+        Vec3::new(-slope_x, 1.0, -slope_z).normalized()
+    }
+
+    fn hit_cell(&self, ray: Ray, i: usize, j: usize, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let corners = [(i, j), (i, j + 1), (i + 1, j + 1), (i + 1, j)];
+        let mut hit = false;
+        // Both triangles face +y: (00, 01, 11) and (00, 11, 10)
+        for triangle in [[corners[0], corners[1], corners[2]], [corners[0], corners[2], corners[3]]] {
+            let [a, b, c] = triangle.map(|(ci, cj)| self.point(ci, cj));
+            let Some(intersection) = intersect_triangle(ray, a, b, c) else {
+                continue;
+            };
+            if !interval.contains(intersection.t) {
+                continue;
+            }
+            let weights = intersection.barycentric;
+            let [na, nb, nc] = triangle.map(|(ci, cj)| self.normals[cj * self.columns + ci]);
+            let normal = (weights.x * na + weights.y * nb + weights.z * nc).normalized();
+            let point = ray.at(intersection.t);
+            interval.upper_bound = intersection.t;
+            hit_record.t = intersection.t;
+            hit_record.point = point;
+            hit_record.u = ((point.x - self.corner.x) / self.extent.x).clamp(0.0, 1.0);
+            hit_record.v = ((point.z - self.corner.z) / self.extent.z).clamp(0.0, 1.0);
+            hit_record.mat = Some(self.mat.clone());
+            hit_record.set_face_normal(ray, normal);
+            hit = true;
+        }
+        hit
+    }
+}
+
+impl Hittable for Heightfield {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let Some(range) = self.bbox.clip(ray, *interval) else {
+            return false;
+        };
+        let (dx, dz) = self.cell_size();
+        let (cells_x, cells_z) = (self.columns - 1, self.rows - 1);
+        let start = ray.at(range.lower_bound);
+        let mut i = (((start.x - self.corner.x) / dx).floor().max(0.0) as usize).min(cells_x - 1);
+        let mut j = (((start.z - self.corner.z) / dz).floor().max(0.0) as usize).min(cells_z - 1);
+
+        // t where the ray crosses the next cell boundary along x and z, and the t between boundaries
+        let next_boundary = |index: usize, size: f64, origin: f64, position: f64, direction: f64| {
+            if direction > 0.0 {
+                (origin + (index + 1) as f64 * size - position) / direction
+            } else if direction < 0.0 {
+                (origin + index as f64 * size - position) / direction
+            } else {
+                f64::INFINITY
+            }
+        };
+        let mut t_max_x = next_boundary(i, dx, self.corner.x, ray.origin.x, ray.direction.x);
+        let mut t_max_z = next_boundary(j, dz, self.corner.z, ray.origin.z, ray.direction.z);
+        let t_delta_x = if ray.direction.x != 0.0 { dx / ray.direction.x.abs() } else { f64::INFINITY };
+        let t_delta_z = if ray.direction.z != 0.0 { dz / ray.direction.z.abs() } else { f64::INFINITY };
+
+        let mut t_enter = range.lower_bound;
+        loop {
+            let t_exit = t_max_x.min(t_max_z).min(range.upper_bound);
+            // Skip the cell if the ray stays above or below it while crossing it
+            let (y0, y1) = (ray.at(t_enter).y, ray.at(t_exit).y);
+            let cell = self.cell_heights[j * cells_x + i];
+            if y0.min(y1) <= cell.upper_bound && y0.max(y1) >= cell.lower_bound
+                && self.hit_cell(ray, i, j, interval, hit_record) {
+                return true;
+            }
+            if t_exit >= range.upper_bound {
+                return false;
+            }
+            t_enter = t_exit;
+            if t_max_x < t_max_z {
+                if ray.direction.x > 0.0 {
+                    if i + 1 >= cells_x {
+                        return false;
+                    }
+                    i += 1;
+                } else {
+                    if i == 0 {
+                        return false;
+                    }
+                    i -= 1;
+                }
+                t_max_x += t_delta_x;
+            } else {
+                if ray.direction.z > 0.0 {
+                    if j + 1 >= cells_z {
+                        return false;
+                    }
+                    j += 1;
+                } else {
+                    if j == 0 {
+                        return false;
+                    }
+                    j -= 1;
+                }
+                t_max_z += t_delta_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<f64> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record.t)
+    }
+
+    #[test]
+    fn ramp_is_hit_where_the_plane_is() {
+        // height = u, so y = x over the 4 x 4 square at the origin
+        let ramp = Heightfield::from_function(|u, _| u, 9, 5, Vec3::new(0.0, 0.0, 0.0), Vec3::new(4.0, 4.0, 4.0), material()).unwrap();
+        for i in 0..20 {
+            let x = 0.1 + i as f64 * 0.19;
+            let ray = Ray::new(Vec3::new(x, 10.0, 1.3), Vec3::new(0.0, -1.0, 0.0));
+            let t = first_hit(&ramp, ray).unwrap();
+            assert!((t - (10.0 - x)).abs() < 1e-9, "x {x}");
+        }
+        // Slanted rays too, y = x meets x = 1 + s, y = 6 - s at s = 2.5
+        let ray = Ray::new(Vec3::new(1.0, 6.0, 2.0), Vec3::new(1.0, -1.0, 0.0));
+        assert!((first_hit(&ramp, ray).unwrap() - 2.5).abs() < 1e-9);
+        assert!(first_hit(&ramp, Ray::new(Vec3::new(5.0, 10.0, 1.0), Vec3::new(0.0, -1.0, 0.0))).is_none());
+    }
+
+    #[test]
+    fn bad_grid_sizes_are_errors() {
+        let corner = Vec3::new(0.0, 0.0, 0.0);
+        let extent = Vec3::new(1.0, 1.0, 1.0);
+        assert!(Heightfield::new(vec![0.0; 3], 1, 3, corner, extent, material()).is_err());
+        assert!(Heightfield::new(vec![0.0; 5], 2, 3, corner, extent, material()).is_err());
+        assert!(Heightfield::from_function(|_, _| 0.0, 0, 0, corner, extent, material()).is_err());
+        assert!(Heightfield::from_function(|_, _| 0.0, usize::MAX, 2, corner, extent, material()).is_err());
+    }
+}
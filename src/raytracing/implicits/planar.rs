@@ -0,0 +1,334 @@
+use crate::vector::Vec3;
+use crate::raytracing::ray::Ray;
+use crate::raytracing::aabb::AABB;
+use std::sync::Arc;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::*;
+
+// Decides which points of the plane belong to a Planar and what their uv is.
+// Points are given as (alpha, beta), where the point is q + alpha * u + beta * v.
+pub trait PlanarShape: Send + Sync {
+    // uv of the point if it's inside the shape
+    fn interior(&self, alpha: f64, beta: f64) -> Option<(f64, f64)>;
+    // Ranges of alpha and beta that cover the whole shape, for the bounding box
+    fn extent(&self) -> (Interval, Interval);
+}
+
+// alpha and beta in [0, 1], the original Quad
+pub struct Parallelogram;
+
+impl PlanarShape for Parallelogram {
+    fn interior(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        let unit_interval = Interval::new(0.0, 1.0);
+        if unit_interval.contains(alpha) && unit_interval.contains(beta) { Some((alpha, beta)) } else { None }
+    }
+
+    fn extent(&self) -> (Interval, Interval) {
+        (Interval::new(0.0, 1.0), Interval::new(0.0, 1.0))
+    }
+}
+
+// Corners at q, q + u and q + v. uv is (alpha, beta), like Triangle's default uvs.
+pub struct TriangleShape;
+
+impl PlanarShape for TriangleShape {
+    fn interior(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        if alpha >= 0.0 && beta >= 0.0 && alpha + beta <= 1.0 { Some((alpha, beta)) } else { None }
+    }
+
+    fn extent(&self) -> (Interval, Interval) {
+        (Interval::new(0.0, 1.0), Interval::new(0.0, 1.0))
+    }
+}
+
+// Centered on q with u and v as semi-axes. uv maps the bounding square to [0, 1].
+pub struct EllipseShape;
+
+impl PlanarShape for EllipseShape {
+    fn interior(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        if alpha * alpha + beta * beta <= 1.0 { Some((0.5 + 0.5 * alpha, 0.5 + 0.5 * beta)) } else { None }
+    }
+
+    fn extent(&self) -> (Interval, Interval) {
+        (Interval::new(-1.0, 1.0), Interval::new(-1.0, 1.0))
+    }
+}
+
+// Ellipse with a hole, inner_ratio is the hole's size relative to the outside (0..1).
+// uv is polar: u around the center, v from the inner (0) to the outer (1) edge.
+pub struct AnnulusShape {
+    pub inner_ratio: f64,
+}
+
+impl PlanarShape for AnnulusShape {
+    fn interior(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        let radius = (alpha * alpha + beta * beta).sqrt();
+        if radius < self.inner_ratio || radius > 1.0 {
+            return None;
+        }
+        let u = (beta.atan2(alpha) / (2.0 * std::f64::consts::PI)).rem_euclid(1.0);
+        Some((u, (radius - self.inner_ratio) / (1.0 - self.inner_ratio)))
+    }
+
+    fn extent(&self) -> (Interval, Interval) {
+        (Interval::new(-1.0, 1.0), Interval::new(-1.0, 1.0))
+    }
+}
+
+// Any simple or self-intersecting polygon, filled with the even-odd rule.
+// uv maps the polygon's bounding rectangle to [0, 1].
+pub struct PolygonShape {
+    points: Vec<(f64, f64)>,
+    alpha_range: Interval,
+    beta_range: Interval,
+}
+
+impl PolygonShape {
+    pub fn new(points: Vec<(f64, f64)>) -> Self {
+        let mut alpha_range = Interval::EMPTY;
+        let mut beta_range = Interval::EMPTY;
+        for &(alpha, beta) in &points {
+            alpha_range = Interval::from_intervals(alpha_range, Interval::new(alpha, alpha));
+            beta_range = Interval::from_intervals(beta_range, Interval::new(beta, beta));
+        }
+        Self { points, alpha_range, beta_range }
+    }
+}
+
+impl PlanarShape for PolygonShape {
+    fn interior(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        if self.points.len() < 3 || !self.alpha_range.contains(alpha) || !self.beta_range.contains(beta) {
+            return None;
+        }
+        // Count edges crossed by a ray from the point towards +alpha
+        let mut inside = false;
+        let mut previous = self.points[self.points.len() - 1];
+        for &current in &self.points {
+            let (a0, b0) = previous;
+            let (a1, b1) = current;
+            if (b0 > beta) != (b1 > beta) {
+                let crossing = a0 + (beta - b0) / (b1 - b0) * (a1 - a0);
+                if alpha < crossing {
+                    inside = !inside;
+                }
+            }
+            previous = current;
+        }
+        if !inside {
+            return None;
+        }
+        let u = (alpha - self.alpha_range.lower_bound) / self.alpha_range.size().max(f64::EPSILON);
+        let v = (beta - self.beta_range.lower_bound) / self.beta_range.size().max(f64::EPSILON);
+        Some((u, v))
+    }
+
+    fn extent(&self) -> (Interval, Interval) {
+        (self.alpha_range, self.beta_range)
+    }
+}
+
+// A flat shape in the plane through q spanned by u and v.
+// The plane intersection is shared, the PlanarShape decides what's inside.
+pub struct Planar {
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    shape: Arc<dyn PlanarShape>,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+    normal: Vec3,
+    d: f64,
+}
+
+impl Planar {
+    // Parallelogram with corners q, q + u, q + v and q + u + v
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Self {
+        Self::with_shape(q, u, v, Arc::new(Parallelogram), mat)
+    }
+
+    pub fn with_shape(q: Vec3, u: Vec3, v: Vec3, shape: Arc<dyn PlanarShape>, mat: Arc<dyn Material>) -> Self {
+        let (alpha_range, beta_range) = shape.extent();
+        let mut bbox = AABB::EMPTY;
+        for alpha in [alpha_range.lower_bound, alpha_range.upper_bound] {
+            for beta in [beta_range.lower_bound, beta_range.upper_bound] {
+                let corner = q + alpha * u + beta * v;
+                bbox = AABB::from_aabbs(bbox, AABB::from_corners(corner, corner));
+            }
+        }
+        let n = u.cross(v);
+        let normal = n.normalized();
+        let d = normal.dot(q);
+        let w = n / n.dot(n);
+        Self { q, u, v, w, shape, mat, bbox, normal, d }
+    }
+
+    pub fn new_triangle(a: Vec3, b: Vec3, c: Vec3, mat: Arc<dyn Material>) -> Self {
+        Self::with_shape(a, b - a, c - a, Arc::new(TriangleShape), mat)
+    }
+
+    // u and v are the semi-axes
+    pub fn new_ellipse(center: Vec3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Self {
+        Self::with_shape(center, u, v, Arc::new(EllipseShape), mat)
+    }
+
+    pub fn new_disk(center: Vec3, normal: Vec3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        let (u, v) = Self::basis(normal, radius);
+        Self::new_ellipse(center, u, v, mat)
+    }
+
+    pub fn new_annulus(center: Vec3, normal: Vec3, inner_radius: f64, outer_radius: f64, mat: Arc<dyn Material>) -> Self {
+        let (u, v) = Self::basis(normal, outer_radius);
+        Self::with_shape(center, u, v, Arc::new(AnnulusShape { inner_ratio: inner_radius / outer_radius }), mat)
+    }
+
+    // points are (alpha, beta) coordinates along u and v
+    pub fn new_polygon(q: Vec3, u: Vec3, v: Vec3, points: Vec<(f64, f64)>, mat: Arc<dyn Material>) -> Self {
+        Self::with_shape(q, u, v, Arc::new(PolygonShape::new(points)), mat)
+    }
+
+    // Polygon from its vertices, which should lie in (roughly) one plane.
+    // u runs along the first edge.
+    pub fn polygon_from_vertices(vertices: &[Vec3], mat: Arc<dyn Material>) -> Self {
+        if vertices.len() < 3 {
+            let origin = vertices.first().copied().unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+            return Self::new_polygon(origin, Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), vec![], mat);
+        }
+        // Newell's method (area-weighted), works for concave polygons too.
+        // Counter-clockwise vertices face the viewer.
+        let mut normal = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..vertices.len() {
+            normal += vertices[i].cross(vertices[(i + 1) % vertices.len()]);
+        }
+        let normal = normal.normalized();
+        let q = vertices[0];
+        let u = (vertices[1] - q).normalized();
+        let v = normal.cross(u);
+        let points = vertices.iter().map(|p| ((*p - q).dot(u), (*p - q).dot(v))).collect();
+        Self::new_polygon(q, u, v, points, mat)
+    }
+
+    // Perpendicular u and v of the given length in the plane with this normal
+    fn basis(normal: Vec3, length: f64) -> (Vec3, Vec3) {
+        let normal = normal.normalized();
+        let helper = if normal.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = normal.cross(helper).normalized();
+        let u = v.cross(normal);
+        (length * u, length * v)
+    }
+}
+
+impl Hittable for Planar {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(ray.direction);
+        if denom.abs() < 1e-8 {
+            false
+        } else {
+            let t = (self.d - self.normal.dot(ray.origin)) / denom;
+            if !interval.contains(t) {
+                false
+            } else {
+                let intersection = ray.at(t);
+                let planar_hitpt_vector = intersection - self.q;
+                let alpha = self.w.dot(planar_hitpt_vector.cross(self.v));
+                let beta = self.w.dot(self.u.cross(planar_hitpt_vector));
+                match self.shape.interior(alpha, beta) {
+                    None => false,
+                    Some((u, v)) => {
+                        interval.upper_bound = t;
+                        hit_record.t = t;
+                        hit_record.point = intersection;
+                        hit_record.u = u;
+                        hit_record.v = v;
+                        hit_record.mat = Some(self.mat.clone());
+                        hit_record.set_face_normal(ray, self.normal);
+                        true
+                    }
+                }
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+    #[test]
+    fn shape_interiors() {
+        assert!(Parallelogram.interior(1.0, 0.0).is_some() && Parallelogram.interior(1.1, 0.5).is_none());
+        assert!(TriangleShape.interior(0.5, 0.5).is_some() && TriangleShape.interior(0.6, 0.5).is_none());
+        assert_eq!(EllipseShape.interior(0.0, 0.0), Some((0.5, 0.5)));
+        assert!(EllipseShape.interior(0.8, 0.8).is_none());
+        let annulus = AnnulusShape { inner_ratio: 0.5 };
+        assert!(annulus.interior(0.2, 0.2).is_none() && annulus.interior(0.8, 0.8).is_none());
+        let (u, v) = annulus.interior(0.0, 0.75).unwrap();
+        assert!((u - 0.25).abs() < 1e-12 && (v - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn polygons_use_even_odd() {
+        // L shape, the notch at the top right is outside
+        let l_shape = PolygonShape::new(vec![(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (1.0, 1.0), (1.0, 2.0), (0.0, 2.0)]);
+        assert!(l_shape.interior(1.5, 0.5).is_some() && l_shape.interior(0.5, 1.5).is_some());
+        assert!(l_shape.interior(1.5, 1.5).is_none() && l_shape.interior(2.5, 0.5).is_none());
+        assert_eq!(l_shape.interior(1.0, 0.5), Some((0.5, 0.25)));
+        // Pentagram: the points are inside, the center is covered twice so it's a hole
+        let star: Vec<(f64, f64)> = (0..5)
+            .map(|i| {
+                let angle = std::f64::consts::FRAC_PI_2 + i as f64 * 4.0 * std::f64::consts::PI / 5.0;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+        let star = PolygonShape::new(star);
+        assert!(star.interior(0.0, 0.0).is_none());
+        assert!(star.interior(0.0, 0.8).is_some());
+        assert!(PolygonShape::new(vec![(0.0, 0.0), (1.0, 1.0)]).interior(0.5, 0.5).is_none());
+    }
+
+    #[test]
+    fn polygon_from_tilted_vertices() {
+        // L shape in the plane x + z = 0, counter-clockwise seen from +x +z
+        let flat = [(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (1.0, 1.0), (1.0, 2.0), (0.0, 2.0)];
+        let along = Vec3::new(1.0, 0.0, -1.0).normalized();
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let vertices: Vec<Vec3> = flat.iter().map(|&(a, b)| a * along + b * up).collect();
+        let polygon = Planar::polygon_from_vertices(&vertices, material());
+        let toward = Vec3::new(1.0, 0.0, 1.0);
+        let ray_at = |a: f64, b: f64| Ray::new(a * along + b * up + 5.0 * toward, -toward);
+        let hit = first_hit(&polygon, ray_at(1.5, 0.5)).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-9 && hit.front_face);
+        assert!(first_hit(&polygon, ray_at(0.5, 1.5)).is_some());
+        assert!(first_hit(&polygon, ray_at(1.5, 1.5)).is_none());
+        let bbox = polygon.bounding_box();
+        for vertex in &vertices {
+            assert!(bbox.x.expand(1e-9).contains(vertex.x) && bbox.y.expand(1e-9).contains(vertex.y) && bbox.z.expand(1e-9).contains(vertex.z));
+        }
+    }
+
+    #[test]
+    fn disk_and_triangle_constructors() {
+        let disk = Planar::new_disk(Vec3::new(0.0, 0.0, -3.0), Vec3::new(0.0, 0.0, 1.0), 2.0, material());
+        assert!(first_hit(&disk, Ray::new(Vec3::new(1.9, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_some());
+        assert!(first_hit(&disk, Ray::new(Vec3::new(1.5, 1.5, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+        let triangle = Planar::new_triangle(Vec3::new(0.0, 0.0, -3.0), Vec3::new(1.0, 0.0, -3.0), Vec3::new(0.0, 1.0, -3.0), material());
+        assert!(first_hit(&triangle, Ray::new(Vec3::new(0.4, 0.4, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_some());
+        assert!(first_hit(&triangle, Ray::new(Vec3::new(0.6, 0.6, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+    }
+}
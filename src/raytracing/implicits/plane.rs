@@ -1,26 +1,128 @@
 use crate::raytracing::hittable::{HitRecord, Hittable};
 use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
 use crate::raytracing::ray::Ray;
 use crate::raytracing::aabb::AABB;
 use crate::vector::Vec3;
+use std::sync::Arc;
 
+// Infinite plane. Its bounding box is AABB::UNIVERSE, so BVHNode keeps it
+// in its unbounded list instead of putting it in the tree.
 pub struct Plane {
     pub position: Vec3,
     pub normal: Vec3,
+    // uv axes in the plane, u = dot(p - position, tangent_u) / uv_scale.0 + uv_offset.0 (same for v)
+    // wrapped to [0, 1) so textures repeat every uv_scale units
+    tangent_u: Vec3,
+    tangent_v: Vec3,
+    uv_scale: (f64, f64),
+    uv_offset: (f64, f64),
+    mat: Arc<dyn Material>,
 }
 
 impl Plane {
-    pub fn new(position: Vec3, normal: Vec3) -> Self {
-        Self { position, normal }
+    pub fn new(position: Vec3, normal: Vec3, mat: Arc<dyn Material>) -> Self {
+        let normal = normal.normalized();
+        // Any axis that isn't too close to the normal works as a starting point
+        let helper = if normal.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let tangent_v = normal.cross(helper).normalized();
+        let tangent_u = tangent_v.cross(normal);
+        Self {
+            position,
+            normal,
+            tangent_u,
+            tangent_v,
+            uv_scale: (1.0, 1.0),
+            uv_offset: (0.0, 0.0),
+            mat,
+        }
+    }
+
+    // Points the u axis along the in-plane part of `direction`
+    pub fn with_tangent(mut self, direction: Vec3) -> Self {
+        let in_plane = direction - direction.dot(self.normal) * self.normal;
+        if in_plane.length() > 0.0 {
+            self.tangent_u = in_plane.normalized();
+            self.tangent_v = self.normal.cross(self.tangent_u);
+        }
+        self
+    }
+
+    // scale is the size of one texture tile along u and v, offset shifts the tiling (in tiles)
+    pub fn with_uv_tiling(mut self, scale: (f64, f64), offset: (f64, f64)) -> Self {
+        self.uv_scale = scale;
+        self.uv_offset = offset;
+        self
     }
 }
 
 impl Hittable for Plane {
     fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
-        false
+        let denom = self.normal.dot(ray.direction);
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+        let t = (self.position - ray.origin).dot(self.normal) / denom;
+        if !interval.contains(t) {
+            return false;
+        }
+
+        interval.upper_bound = t;
+        hit_record.t = t;
+        hit_record.point = ray.at(t);
+        let offset = hit_record.point - self.position;
+        hit_record.u = (offset.dot(self.tangent_u) / self.uv_scale.0 + self.uv_offset.0).rem_euclid(1.0);
+        hit_record.v = (offset.dot(self.tangent_v) / self.uv_scale.1 + self.uv_offset.1).rem_euclid(1.0);
+        hit_record.mat = Some(self.mat.clone());
+        hit_record.set_face_normal(ray, self.normal);
+        true
     }
-    
+
     fn bounding_box(&self) -> AABB {
         AABB::UNIVERSE
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::material::Lambertian;
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+    fn floor() -> Plane {
+        Plane::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 2.0, 0.0), Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))
+    }
+
+    #[test]
+    fn hits_from_both_sides() {
+        let hit = first_hit(&floor(), Ray::new(Vec3::new(3.0, 1.0, -7.0), Vec3::new(0.0, -1.0, 0.0))).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-12 && hit.front_face);
+        let hit = first_hit(&floor(), Ray::new(Vec3::new(3.0, -3.0, -7.0), Vec3::new(0.0, 1.0, 0.0))).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-12 && !hit.front_face);
+        assert!((hit.normal - Vec3::new(0.0, -1.0, 0.0)).length() < 1e-12);
+        assert!(first_hit(&floor(), Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0))).is_none());
+        assert!(first_hit(&floor(), Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0))).is_none());
+        assert!(floor().bounding_box().is_unbounded());
+    }
+
+    #[test]
+    fn uvs_tile() {
+        let plane = floor().with_tangent(Vec3::new(1.0, 5.0, 0.0)).with_uv_tiling((2.0, 4.0), (0.25, 0.0));
+        let uv = |x: f64, z: f64| {
+            let hit = first_hit(&plane, Ray::new(Vec3::new(x, 0.0, z), Vec3::new(0.0, -1.0, 0.0))).unwrap();
+            (hit.u, hit.v)
+        };
+        let (u, _) = uv(0.5, 0.0);
+        assert!((u - 0.5).abs() < 1e-12);
+        // One tile further along u is the same spot of the texture
+        let ((u0, v0), (u1, v1)) = (uv(0.3, 1.0), uv(2.3, 1.0));
+        assert!((u0 - u1).abs() < 1e-12 && (v0 - v1).abs() < 1e-12);
+        let (u, v) = uv(-0.7, -3.0);
+        assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::implicits::{azimuth_u, keep_closest, quartic_roots, record_local_hit, LocalFrame, LocalHit};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::solid::Solid;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Ring around `axis`: a tube of minor_radius swept along a circle of major_radius.
+// u goes around the axis, v around the tube.
+pub struct Torus {
+    frame: LocalFrame,
+    major_radius: f64,
+    minor_radius: f64,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Torus {
+    pub fn new(center: Vec3, axis: Vec3, major_radius: f64, minor_radius: f64, mat: Arc<dyn Material>) -> Self {
+        let frame = LocalFrame::new(center, axis);
+        let w = frame.w;
+        let extent = |w_i: f64| major_radius * (1.0 - w_i * w_i).max(0.0).sqrt() + minor_radius;
+        let extent = Vec3::new(extent(w.x), extent(w.y), extent(w.z));
+        let bbox = AABB::from_corners(center - extent, center + extent);
+        Self { frame, major_radius, minor_radius, mat, bbox }
+    }
+}
+
+impl Hittable for Torus {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let local = self.frame.ray_to_local(ray);
+        let direction_length = local.direction.length();
+        if direction_length == 0.0 {
+            return false;
+        }
+        let d = local.direction / direction_length;
+        // Solve from the point closest to the center, the quartic is badly conditioned far away
+        let shift = -local.origin.dot(d);
+        let o = local.origin + shift * d;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2) along p = o + s d
+        let r2 = self.major_radius * self.major_radius;
+        let alpha = o.dot(o) + r2 - self.minor_radius * self.minor_radius;
+        let n = o.dot(d);
+        let coefficients = [
+            1.0,
+            4.0 * n,
+            4.0 * n * n + 2.0 * alpha - 4.0 * r2 * (d.x * d.x + d.y * d.y),
+            4.0 * n * alpha - 8.0 * r2 * (o.x * d.x + o.y * d.y),
+            alpha * alpha - 4.0 * r2 * (o.x * o.x + o.y * o.y),
+        ];
+
+        let mut best = None;
+        for s in quartic_roots(coefficients) {
+            let t = (s + shift) / direction_length;
+            let p = local.at(t);
+            let ring_distance = (p.x * p.x + p.y * p.y).sqrt();
+            let ring_point = if ring_distance > 0.0 {
+                Vec3::new(p.x, p.y, 0.0) * (self.major_radius / ring_distance)
+            } else {
+                Vec3::new(self.major_radius, 0.0, 0.0)
+            };
+            keep_closest(&mut best, interval, LocalHit {
+                t,
+                outward_normal: (p - ring_point).normalized(),
+                u: azimuth_u(p),
+                v: (p.z.atan2(ring_distance - self.major_radius) / (2.0 * std::f64::consts::PI)).rem_euclid(1.0),
+            });
+        }
+        record_local_hit(&self.frame, ray, best, &self.mat, interval, hit_record)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+impl Solid for Torus {
+    fn is_point_inside(&self, point: Vec3) -> bool {
+        let p = self.frame.point_to_local(point);
+        let ring_distance = (p.x * p.x + p.y * p.y).sqrt() - self.major_radius;
+        ring_distance * ring_distance + p.z * p.z < self.minor_radius * self.minor_radius
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::tests::check_closed_surface;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+
+    #[test]
+    fn four_crossings_through_the_ring() {
+        let torus = Torus::new(Vec3::new(0.0, 0.0, -10.0), Vec3::new(0.0, 1.0, 0.0), 2.0, 0.5, material());
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut crossings = vec![];
+        let mut lower_bound = 0.001;
+        while let Some(hit) = {
+            let mut interval = Interval::new(lower_bound, f64::INFINITY);
+            let mut hit_record = HitRecord::new();
+            torus.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+        } {
+            crossings.push(hit.t);
+            lower_bound = hit.t + 1e-6;
+        }
+        let expected = [7.5, 8.5, 11.5, 12.5];
+        assert_eq!(crossings.len(), 4);
+        assert!(crossings.iter().zip(expected).all(|(t, expected)| (t - expected).abs() < 1e-9), "{:?}", crossings);
+        // Straight through the hole along the axis
+        assert!(first_hit(&torus, Ray::new(Vec3::new(0.0, 5.0, -10.0), Vec3::new(0.0, -1.0, 0.0))).is_none());
+    }
+
+    #[test]
+    fn closed_surface() {
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        check_closed_surface(&Torus::new(center, Vec3::new(1.0, 1.0, 0.2), 1.0, 0.4, material()), center, 5.0);
+    }
+}
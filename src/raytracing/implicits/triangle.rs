@@ -0,0 +1,224 @@
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Barycentric coordinates (for a, b, c) and distance of a ray/triangle hit
+pub struct TriangleIntersection {
+    pub t: f64,
+    pub barycentric: Vec3,
+}
+
+// Watertight ray/triangle intersection, Woop, Benthin & Wald 2013.
+// Rays through a shared edge or vertex always hit at least one of the triangles.
+pub fn intersect_triangle(ray: Ray, a: Vec3, b: Vec3, c: Vec3) -> Option<TriangleIntersection> {
+    let dir = ray.direction;
+    // permute so that z is the dominant axis of the ray direction
+    let kz = if dir.x.abs() > dir.y.abs() {
+        if dir.x.abs() > dir.z.abs() { 0 } else { 2 }
+    } else if dir.y.abs() > dir.z.abs() {
+        1
+    } else {
+        2
+    };
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if dir[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+    if dir[kz] == 0.0 {
+        return None;
+    }
+
+    // shear so that the ray points straight down z
+    let sx = dir[kx] / dir[kz];
+    let sy = dir[ky] / dir[kz];
+    let sz = 1.0 / dir[kz];
+
+    let a = a - ray.origin;
+    let b = b - ray.origin;
+    let c = c - ray.origin;
+    let ax = a[kx] - sx * a[kz];
+    let ay = a[ky] - sy * a[kz];
+    let bx = b[kx] - sx * b[kz];
+    let by = b[ky] - sy * b[kz];
+    let cx = c[kx] - sx * c[kz];
+    let cy = c[ky] - sy * c[kz];
+
+    // scaled barycentrics from 2d edge functions
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let az = sz * a[kz];
+    let bz = sz * b[kz];
+    let cz = sz * c[kz];
+    let t = (u * az + v * bz + w * cz) / det;
+    Some(TriangleIntersection {
+        t,
+        barycentric: Vec3::new(u / det, v / det, w / det),
+    })
+}
+
+pub struct Triangle {
+    // Same trick as Sphere: vertex i sits at vertices[i].at(time)
+    vertices: [Ray; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: [(f64, f64); 3],
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3, mat: Arc<dyn Material>) -> Self {
+        Self::new_moving([a, b, c], [a, b, c], mat)
+    }
+
+    pub fn new_moving(start: [Vec3; 3], end: [Vec3; 3], mat: Arc<dyn Material>) -> Self {
+        let vertices = [
+            Ray::new(start[0], end[0] - start[0]),
+            Ray::new(start[1], end[1] - start[1]),
+            Ray::new(start[2], end[2] - start[2]),
+        ];
+        let mut bbox = AABB::EMPTY;
+        for vertex in start.iter().chain(end.iter()) {
+            bbox = AABB::from_aabbs(bbox, AABB::from_corners(*vertex, *vertex));
+        }
+        Self {
+            vertices,
+            normals: None,
+            uvs: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            mat,
+            bbox,
+        }
+    }
+
+    // Per-vertex normals, interpolated for smooth shading
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [(f64, f64); 3]) -> Self {
+        self.uvs = uvs;
+        self
+    }
+}
+
+impl Hittable for Triangle {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let a = self.vertices[0].at(ray.time);
+        let b = self.vertices[1].at(ray.time);
+        let c = self.vertices[2].at(ray.time);
+        let hit = match intersect_triangle(ray, a, b, c) {
+            Some(hit) if interval.contains(hit.t) => hit,
+            _ => return false,
+        };
+        let bary = hit.barycentric;
+
+        interval.upper_bound = hit.t;
+        hit_record.t = hit.t;
+        hit_record.point = ray.at(hit.t);
+        let geometric_normal = (b - a).cross(c - a).normalized();
+        hit_record.set_face_normal(ray, geometric_normal);
+        if let Some(normals) = &self.normals {
+            let shading_normal = (bary.x * normals[0] + bary.y * normals[1] + bary.z * normals[2]).normalized();
+            hit_record.normal = if hit_record.front_face { shading_normal } else { -shading_normal };
+        }
+        hit_record.u = bary.x * self.uvs[0].0 + bary.y * self.uvs[1].0 + bary.z * self.uvs[2].0;
+        hit_record.v = bary.x * self.uvs[0].1 + bary.y * self.uvs[1].1 + bary.z * self.uvs[2].1;
+        hit_record.mat = Some(self.mat.clone());
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+    #[test]
+    fn barycentrics_and_distance() {
+        let (a, b, c) = (Vec3::new(0.0, 0.0, -2.0), Vec3::new(1.0, 0.0, -2.0), Vec3::new(0.0, 1.0, -2.0));
+        let ray = Ray::new(Vec3::new(0.25, 0.5, 0.0), Vec3::new(0.0, 0.0, -0.5));
+        let hit = intersect_triangle(ray, a, b, c).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-12);
+        assert!((hit.barycentric - Vec3::new(0.25, 0.25, 0.5)).length() < 1e-12);
+        // Both sides are hit, parallel rays and rays beside it are not
+        assert!(intersect_triangle(Ray::new(Vec3::new(0.25, 0.5, -4.0), Vec3::new(0.0, 0.0, 1.0)), a, b, c).is_some());
+        assert!(intersect_triangle(Ray::new(Vec3::new(0.0, 0.0, -2.0), Vec3::new(1.0, 1.0, 0.0)), a, b, c).is_none());
+        assert!(intersect_triangle(Ray::new(Vec3::new(0.6, 0.6, 0.0), Vec3::new(0.0, 0.0, -1.0)), a, b, c).is_none());
+    }
+
+    #[test]
+    fn shared_edges_have_no_cracks() {
+        // A fan of skewed triangles around one vertex, rays aimed exactly at its edges and center
+        let center = Vec3::new(0.1, -0.2, -3.0);
+        let rim: Vec<Vec3> = (0..7)
+            .map(|i| {
+                let angle = i as f64 * std::f64::consts::TAU / 7.0;
+                Vec3::new(angle.cos() * 1.3, angle.sin() * 0.9, -3.0 + 0.4 * angle.sin())
+            })
+            .collect();
+        let triangles: Vec<[Vec3; 3]> = (0..7).map(|i| [center, rim[i], rim[(i + 1) % 7]]).collect();
+        let origin = Vec3::new(0.3, 0.2, 1.0);
+        for (i, corner) in rim.iter().enumerate() {
+            for step in 0..=100 {
+                let f = step as f64 / 100.0 * 0.99;
+                let target = center + f * (*corner - center);
+                let ray = Ray::new(origin, target - origin);
+                let hits = triangles.iter().filter(|[a, b, c]| intersect_triangle(ray, *a, *b, *c).is_some()).count();
+                assert!(hits >= 1, "edge {} step {}", i, step);
+            }
+        }
+    }
+
+    #[test]
+    fn normals_and_uvs_are_interpolated() {
+        let triangle = Triangle::new(Vec3::new(0.0, 0.0, -2.0), Vec3::new(1.0, 0.0, -2.0), Vec3::new(0.0, 1.0, -2.0), material())
+            .with_normals([Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 1.0).normalized(), Vec3::new(0.0, 0.0, 1.0)])
+            .with_uvs([(0.0, 0.0), (2.0, 0.0), (0.0, 4.0)]);
+        let hit = first_hit(&triangle, Ray::new(Vec3::new(0.5, 0.25, 0.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.u - 1.0).abs() < 1e-12 && (hit.v - 1.0).abs() < 1e-12);
+        assert!(hit.front_face && hit.normal.x > 0.0);
+        // From behind the shading normal flips with the face
+        let hit = first_hit(&triangle, Ray::new(Vec3::new(0.5, 0.25, -4.0), Vec3::new(0.0, 0.0, 1.0))).unwrap();
+        assert!(!hit.front_face && hit.normal.z < 0.0);
+    }
+
+    #[test]
+    fn moving_triangle_follows_time() {
+        let start = [Vec3::new(0.0, 0.0, -2.0), Vec3::new(1.0, 0.0, -2.0), Vec3::new(0.0, 1.0, -2.0)];
+        let end = start.map(|v| v + Vec3::new(5.0, 0.0, 0.0));
+        let triangle = Triangle::new_moving(start, end, material());
+        let ray = |time| Ray::with_time(Vec3::new(5.2, 0.2, 0.0), Vec3::new(0.0, 0.0, -1.0), time);
+        assert!(first_hit(&triangle, ray(0.0)).is_none());
+        assert!(first_hit(&triangle, ray(1.0)).is_some());
+        assert!((triangle.bounding_box().x.upper_bound - 6.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::mesh::Mesh;
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::bvh::PARALLEL_BUILD_SIZE;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::implicits::triangle::intersect_triangle;
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::vector::Vec3;
+use rayon::prelude::*;
+use std::sync::Arc;
+
+// Leaves hold `count` triangles starting at `first`.
+// Interior nodes have count == 0 and their children at nodes[first] and nodes[first + 1].
+#[derive(Copy, Clone)]
+struct MeshNode {
+    bbox: AABB,
+    first: u32,
+    count: u32,
+}
+
+// Indexed triangles in world space with their own BVH, so a whole model
+// goes into a HittableList or BVHNode as a single object.
+pub struct TriangleMesh {
+    positions: Vec<Vec3>,
+    // Empty if the mesh had no per-vertex normals
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    triangles: Vec<[u32; 3]>,
+    // Index into materials per triangle, empty when the whole mesh uses materials[0]
+    face_materials: Vec<u32>,
+    materials: Vec<Arc<dyn Material>>,
+    nodes: Vec<MeshNode>,
+}
+
+impl TriangleMesh {
+    const MAX_LEAF_TRIANGLES: usize = 4;
+    // Median splits halve the triangles every level, so with u32 indices no path is longer than this
+    const STACK_SIZE: usize = 64;
+
+    pub fn new(mesh: &Mesh, mat: Arc<dyn Material>) -> Self {
+        Self::with_face_materials(mesh, vec![mat], vec![])
+    }
+
+    // face_materials[i] is the index into materials for triangle i of the mesh
+    pub fn with_face_materials(mesh: &Mesh, materials: Vec<Arc<dyn Material>>, face_materials: Vec<usize>) -> Self {
+        assert!(!materials.is_empty(), "a triangle mesh needs at least one material");
+        if let Some(&bad) = face_materials.iter().find(|&&material| material >= materials.len()) {
+            panic!("face material {} is out of range, the mesh has {} materials", bad, materials.len());
+        }
+        let positions: Vec<Vec3> = mesh.vertices.iter()
+            .map(|v| v.scaled_non_uniform(mesh.scale).rotated(mesh.rotation).translated(mesh.position))
+            .collect();
+        // Normals take the inverse scale so they stay perpendicular under non-uniform scaling
+        let inverse_scale = Vec3::new(1.0 / mesh.scale.x, 1.0 / mesh.scale.y, 1.0 / mesh.scale.z);
+        let normals: Vec<Vec3> = if mesh.normals.len() == mesh.vertices.len() {
+            mesh.normals.iter()
+                .map(|n| n.scaled_non_uniform(inverse_scale).rotated(mesh.rotation).normalized())
+                .collect()
+        } else {
+            vec![]
+        };
+        let uvs = if mesh.uvs.len() == mesh.vertices.len() { mesh.uvs.clone() } else { vec![] };
+
+        // Triangles that point at missing vertices are dropped
+        let vertex_count = positions.len();
+        let mut triangles = vec![];
+        let mut kept_face_materials = vec![];
+        for (face, indices) in mesh.indices.chunks_exact(3).enumerate() {
+            if indices.iter().all(|&i| i < vertex_count) {
+                triangles.push([indices[0] as u32, indices[1] as u32, indices[2] as u32]);
+                if !face_materials.is_empty() {
+                    let material = face_materials.get(face).copied().unwrap_or(0);
+                    kept_face_materials.push(material as u32);
+                }
+            }
+        }
+
+        Self::from_triangles(positions, normals, uvs, triangles, materials, kept_face_materials)
+    }
+
+    // Everything already in world space
+    pub fn from_triangles(positions: Vec<Vec3>, normals: Vec<Vec3>, uvs: Vec<(f64, f64)>, triangles: Vec<[u32; 3]>,
+                          materials: Vec<Arc<dyn Material>>, face_materials: Vec<u32>) -> Self {
+        assert!(!materials.is_empty(), "a triangle mesh needs at least one material");
+        assert!(face_materials.is_empty() || face_materials.len() == triangles.len(), "a triangle mesh needs one face material per triangle");
+        if let Some(&bad) = face_materials.iter().find(|&&material| material as usize >= materials.len()) {
+            panic!("face material {} is out of range, the mesh has {} materials", bad, materials.len());
+        }
+        let mut mesh = Self {
+            positions,
+            normals,
+            uvs,
+            triangles,
+            face_materials,
+            materials,
+            nodes: vec![],
+        };
+        mesh.build_bvh();
+        mesh
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    fn triangle_bbox(&self, triangle: usize) -> AABB {
+        let [a, b, c] = self.triangles[triangle];
+        let (a, b, c) = (self.positions[a as usize], self.positions[b as usize], self.positions[c as usize]);
+        AABB::from_aabbs(AABB::from_corners(a, b), AABB::from_corners(c, c))
+    }
+
+    fn build_bvh(&mut self) {
+        self.nodes.clear();
+        if self.triangles.is_empty() {
+            self.nodes.push(MeshNode { bbox: AABB::EMPTY, first: 0, count: 0 });
+            return;
+        }
+        // Sort a permutation, then apply it to triangles and face materials together
+        let bboxes: Vec<AABB> = (0..self.triangles.len()).into_par_iter().map(|i| self.triangle_bbox(i)).collect();
+        let centroids: Vec<Vec3> = bboxes.par_iter()
+            .map(|b| 0.5 * Vec3::new(b.x.lower_bound + b.x.upper_bound, b.y.lower_bound + b.y.upper_bound, b.z.lower_bound + b.z.upper_bound))
+            .collect();
+        let mut order: Vec<usize> = (0..self.triangles.len()).collect();
+        let mut nodes = vec![MeshNode { bbox: AABB::EMPTY, first: 0, count: 0 }];
+        Self::build_node(&mut nodes, 0, &mut order, 0, &bboxes, &centroids);
+
+        self.triangles = order.iter().map(|&i| self.triangles[i]).collect();
+        if !self.face_materials.is_empty() {
+            self.face_materials = order.iter().map(|&i| self.face_materials[i]).collect();
+        }
+        self.nodes = nodes;
+    }
+
+    fn build_node(nodes: &mut Vec<MeshNode>, node: usize, order: &mut [usize], offset: usize, bboxes: &[AABB], centroids: &[Vec3]) {
+        let mut bbox = AABB::EMPTY;
+        let mut centroid_bounds = AABB::EMPTY;
+        for &i in order.iter() {
+            bbox = AABB::from_aabbs(bbox, bboxes[i]);
+            centroid_bounds = AABB::from_aabbs(centroid_bounds, AABB::from_corners(centroids[i], centroids[i]));
+        }
+        let axis = centroid_bounds.longest_axis();
+        if order.len() <= Self::MAX_LEAF_TRIANGLES || centroid_bounds.axis_interval(axis as i32).size() <= 0.0 {
+            nodes[node] = MeshNode { bbox, first: offset as u32, count: order.len() as u32 };
+            return;
+        }
+
+        let mid = order.len() / 2;
+        order.select_nth_unstable_by(mid, |&a, &b| centroids[a][axis].total_cmp(&centroids[b][axis]));
+        let left = nodes.len();
+        nodes.push(MeshNode { bbox: AABB::EMPTY, first: 0, count: 0 });
+        nodes.push(MeshNode { bbox: AABB::EMPTY, first: 0, count: 0 });
+        nodes[node] = MeshNode { bbox, first: left as u32, count: 0 };
+        let (left_order, right_order) = order.split_at_mut(mid);
+        if right_order.len() < PARALLEL_BUILD_SIZE {
+            Self::build_node(nodes, left, left_order, offset, bboxes, centroids);
+            Self::build_node(nodes, left + 1, right_order, offset + mid, bboxes, centroids);
+            return;
+        }
+        // Big halves are built on the thread pool, each into its own array with its root first
+        let subtree = |order: &mut [usize], offset: usize| {
+            let mut subtree = vec![MeshNode { bbox: AABB::EMPTY, first: 0, count: 0 }];
+            Self::build_node(&mut subtree, 0, order, offset, bboxes, centroids);
+            subtree
+        };
+        let (left_nodes, right_nodes) = rayon::join(|| subtree(left_order, offset), || subtree(right_order, offset + mid));
+        Self::append_subtree(nodes, left, left_nodes);
+        Self::append_subtree(nodes, left + 1, right_nodes);
+    }
+
+    // Puts the subtree's root in slot and the rest at the end, moving its child indices along
+    fn append_subtree(nodes: &mut Vec<MeshNode>, slot: usize, subtree: Vec<MeshNode>) {
+        let base = nodes.len() as u32;
+        let shifted = |mut node: MeshNode| {
+            if node.count == 0 {
+                node.first = base + node.first - 1;
+            }
+            node
+        };
+        nodes[slot] = shifted(subtree[0]);
+        nodes.extend(subtree[1..].iter().map(|node| shifted(*node)));
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        if self.triangles.is_empty() {
+            return false;
+        }
+        // Find the closest triangle first, then fill in the hit record once
+        let mut closest: Option<(usize, Vec3)> = None;
+        let mut stack = [0u32; Self::STACK_SIZE];
+        let mut stack_size = 1;
+        while stack_size > 0 {
+            stack_size -= 1;
+            let node = &self.nodes[stack[stack_size] as usize];
+            if !node.bbox.hit(ray, interval) {
+                continue;
+            }
+            if node.count == 0 {
+                stack[stack_size] = node.first;
+                stack[stack_size + 1] = node.first + 1;
+                stack_size += 2;
+                continue;
+            }
+            for triangle in node.first as usize..(node.first + node.count) as usize {
+                let [a, b, c] = self.triangles[triangle];
+                let (a, b, c) = (self.positions[a as usize], self.positions[b as usize], self.positions[c as usize]);
+                if let Some(hit) = intersect_triangle(ray, a, b, c)
+                    && interval.contains(hit.t) {
+                    interval.upper_bound = hit.t;
+                    closest = Some((triangle, hit.barycentric));
+                }
+            }
+        }
+
+        let (triangle, bary) = match closest {
+            Some(closest) => closest,
+            None => return false,
+        };
+        let [ia, ib, ic] = self.triangles[triangle].map(|i| i as usize);
+        let (a, b, c) = (self.positions[ia], self.positions[ib], self.positions[ic]);
+        hit_record.t = interval.upper_bound;
+        hit_record.point = ray.at(hit_record.t);
+        hit_record.set_face_normal(ray, (b - a).cross(c - a).normalized());
+        if !self.normals.is_empty() {
+            let shading_normal = (bary.x * self.normals[ia] + bary.y * self.normals[ib] + bary.z * self.normals[ic]).normalized();
+            hit_record.normal = if hit_record.front_face { shading_normal } else { -shading_normal };
+        }
+        let (uv_a, uv_b, uv_c) = if self.uvs.is_empty() {
+            ((0.0, 0.0), (1.0, 0.0), (0.0, 1.0))
+        } else {
+            (self.uvs[ia], self.uvs[ib], self.uvs[ic])
+        };
+        hit_record.u = bary.x * uv_a.0 + bary.y * uv_b.0 + bary.z * uv_c.0;
+        hit_record.v = bary.x * uv_a.1 + bary.y * uv_b.1 + bary.z * uv_c.1;
+        let material = if self.face_materials.is_empty() { 0 } else { self.face_materials[triangle] as usize };
+        hit_record.mat = Some(self.materials[material].clone());
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.nodes[0].bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    // Two triangles side by side in the z = 0 plane
+    fn two_triangles() -> Mesh {
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0), Vec3::new(3.0, 0.0, 0.0), Vec3::new(2.0, 1.0, 0.0),
+        ];
+        Mesh::new(vertices, vec![], vec![0, 1, 2, 3, 4, 5])
+    }
+
+    fn hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mesh = Mesh::uv_sphere(2.0, 24, 12);
+        let triangles = TriangleMesh::new(&mesh, material());
+        for x in -30..=30 {
+            for y in -30..=30 {
+                let ray = Ray::new(Vec3::new(x as f64 * 0.07, y as f64 * 0.07, 5.0), Vec3::new(0.01, -0.02, -1.0));
+                // Triangles pointing past the vertices are dropped by the mesh too
+                let expected = mesh.indices.chunks_exact(3)
+                    .filter(|i| i.iter().all(|&i| i < mesh.vertices.len()))
+                    .filter_map(|i| intersect_triangle(ray, mesh.vertices[i[0]], mesh.vertices[i[1]], mesh.vertices[i[2]]))
+                    .map(|hit| hit.t)
+                    .filter(|&t| t >= 0.001)
+                    .min_by(|a, b| a.total_cmp(b));
+                assert_eq!(hit(&triangles, ray).map(|hit| hit.t), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn face_materials_follow_their_triangles() {
+        let (first, second) = (material(), material());
+        let triangles = TriangleMesh::with_face_materials(&two_triangles(), vec![first.clone(), second.clone()], vec![1, 0]);
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        let left = hit(&triangles, Ray::new(Vec3::new(0.2, 0.2, 1.0), down)).unwrap();
+        let right = hit(&triangles, Ray::new(Vec3::new(2.2, 0.2, 1.0), down)).unwrap();
+        assert!(Arc::ptr_eq(&left.mat.unwrap(), &second));
+        assert!(Arc::ptr_eq(&right.mat.unwrap(), &first));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn rejects_face_materials_out_of_range() {
+        TriangleMesh::with_face_materials(&two_triangles(), vec![material()], vec![0, 1]);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::color::Color;
+use crate::formats::FormatError;
+use crate::formats::vox::VoxelData;
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::solid::Solid;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Dense grid of axis-aligned voxels. Each cell holds a material index, 0 is empty and
+// index i uses materials[i - 1]. Rays step from cell to cell (Amanatides-Woo 3D DDA)
+// and hit where they go from an empty cell into an occupied one, or back out,
+// so a grid of glass voxels refracts like one solid.
+// uv runs across each voxel face along the next two axes, like Cuboid.
+pub struct VoxelGrid {
+    corner: Vec3,
+    voxel_size: Vec3,
+    size: [usize; 3],
+    cells: Vec<u8>,
+    materials: Vec<Arc<dyn Material>>,
+    // Occupied cells only, rays skip the empty space around them
+    bbox: AABB,
+}
+
+impl VoxelGrid {
+    // An empty grid with its minimum corner at corner, fill it with set.
+    // Fails when the cell count overflows usize.
+    pub fn new(size: [usize; 3], corner: Vec3, voxel_size: Vec3, materials: Vec<Arc<dyn Material>>) -> Result<Self, FormatError> {
+        let cell_count = size[0].checked_mul(size[1])
+            .and_then(|count| count.checked_mul(size[2]))
+            .ok_or_else(|| FormatError::parse(0, format!("voxel grid of {} x {} x {} is too big", size[0], size[1], size[2])))?;
+        Ok(Self {
+            corner,
+            voxel_size,
+            size,
+            cells: vec![0; cell_count],
+            materials,
+            bbox: AABB::EMPTY,
+        })
+    }
+
+    // materials[i - 1] for palette index i, see palette_materials for the file's own colors.
+    // Fails when the data uses an index past the end of materials, e.g. raw voxels with too few materials.
+    pub fn from_data(data: &VoxelData, corner: Vec3, voxel_size: Vec3, materials: Vec<Arc<dyn Material>>) -> Result<Self, FormatError> {
+        let highest = data.voxels.iter().copied().max().unwrap_or(0);
+        if highest as usize > materials.len() {
+            return Err(FormatError::parse(0, format!(
+                "voxels use material index {} but there are only {} materials", highest, materials.len())));
+        }
+        let mut grid = Self::new(data.size, corner, voxel_size, materials)?;
+        for z in 0..data.size[2] {
+            for y in 0..data.size[1] {
+                for x in 0..data.size[0] {
+                    let index = data.get(x, y, z);
+                    if index != 0 {
+                        grid.fill(x, y, z, index);
+                    }
+                }
+            }
+        }
+        Ok(grid)
+    }
+
+    // One material per palette color, in palette order
+    pub fn palette_materials(data: &VoxelData, material: impl Fn(Color) -> Arc<dyn Material>) -> Vec<Arc<dyn Material>> {
+        data.palette.iter().map(|color| material(*color)).collect()
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.cells[self.cell_index(x, y, z)]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, index: u8) {
+        assert!(index as usize <= self.materials.len(), "voxel material {} doesn't exist", index);
+        self.fill(x, y, z, index);
+    }
+
+    // set without the material check
+    fn fill(&mut self, x: usize, y: usize, z: usize, index: u8) {
+        let cell = self.cell_index(x, y, z);
+        self.cells[cell] = index;
+        if index != 0 {
+            let min = self.cell_min([x, y, z]);
+            self.bbox = AABB::from_aabbs(self.bbox, AABB::from_corners(min, min + self.voxel_size));
+        }
+    }
+
+    fn cell_index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.size[1] + y) * self.size[0] + x
+    }
+
+    fn cell_min(&self, cell: [usize; 3]) -> Vec3 {
+        Vec3::new(
+            self.corner.x + cell[0] as f64 * self.voxel_size.x,
+            self.corner.y + cell[1] as f64 * self.voxel_size.y,
+            self.corner.z + cell[2] as f64 * self.voxel_size.z,
+        )
+    }
+
+    // face is the axis the face is perpendicular to and the sign of its outward normal on that axis
+    fn hit_face(&self, ray: Ray, t: f64, face: (usize, f64), index: u8, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        if !interval.contains(t) {
+            return false;
+        }
+        let (axis, outward) = face;
+        let point = ray.at(t);
+        let mut outward_normal = Vec3::new(0.0, 0.0, 0.0);
+        outward_normal[axis] = outward;
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let local = |axis: usize| {
+            let cells = (point[axis] - self.corner[axis]) / self.voxel_size[axis];
+            cells - cells.floor()
+        };
+        interval.upper_bound = t;
+        hit_record.t = t;
+        hit_record.point = point;
+        hit_record.u = local(u_axis);
+        hit_record.v = local(v_axis);
+        hit_record.mat = Some(self.materials[index as usize - 1].clone());
+        hit_record.set_face_normal(ray, outward_normal);
+        true
+    }
+}
+
+impl Hittable for VoxelGrid {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let Some(range) = self.bbox.clip(ray, *interval) else {
+            return false;
+        };
+        let start = ray.at(range.lower_bound);
+        let mut cell = [0usize; 3];
+        let mut step = [0isize; 3];
+        let mut t_max = [f64::INFINITY; 3];
+        let mut t_delta = [f64::INFINITY; 3];
+        for axis in 0..3 {
+            let direction = ray.direction[axis];
+            let position = (start[axis] - self.corner[axis]) / self.voxel_size[axis];
+            // On a cell wall take the cell the ray is heading into, so a ray leaving a voxel's face
+            // starts in the empty cell outside it instead of inside the voxel
+            let on_wall = (position - position.round()).abs() <= 1e-9;
+            let index = if !on_wall {
+                position.floor()
+            } else if direction < 0.0 {
+                position.round() - 1.0
+            } else {
+                position.round()
+            };
+            // Starts on the grid's outer wall, heading out
+            if on_wall && (index < 0.0 || index >= self.size[axis] as f64) {
+                return false;
+            }
+            cell[axis] = (index.max(0.0) as usize).min(self.size[axis] - 1);
+            if direction > 0.0 {
+                step[axis] = 1;
+                t_max[axis] = (self.corner[axis] + (cell[axis] + 1) as f64 * self.voxel_size[axis] - ray.origin[axis]) / direction;
+                t_delta[axis] = self.voxel_size[axis] / direction;
+            } else if direction < 0.0 {
+                step[axis] = -1;
+                t_max[axis] = (self.corner[axis] + cell[axis] as f64 * self.voxel_size[axis] - ray.origin[axis]) / direction;
+                t_delta[axis] = -self.voxel_size[axis] / direction;
+            }
+        }
+
+        let mut current = self.get(cell[0], cell[1], cell[2]);
+        // A ray from outside the occupied box enters through one of its faces, the slab that starts last
+        if range.lower_bound > interval.lower_bound && current != 0 {
+            let entry_axis = (0..3)
+                .filter(|&axis| ray.direction[axis] != 0.0)
+                .max_by(|&a, &b| {
+                    let near = |axis: usize| {
+                        let face = if ray.direction[axis] > 0.0 { self.bbox.axis_interval(axis as i32).lower_bound } else { self.bbox.axis_interval(axis as i32).upper_bound };
+                        (face - ray.origin[axis]) / ray.direction[axis]
+                    };
+                    near(a).total_cmp(&near(b))
+                })
+                .unwrap_or(0);
+            let outward = -ray.direction[entry_axis].signum();
+            return self.hit_face(ray, range.lower_bound, (entry_axis, outward), current, interval, hit_record);
+        }
+
+        loop {
+            let axis = if t_max[0] < t_max[1] {
+                if t_max[0] < t_max[2] { 0 } else { 2 }
+            } else if t_max[1] < t_max[2] { 1 } else { 2 };
+            let t = t_max[axis];
+            if t > interval.upper_bound || (current == 0 && t > range.upper_bound) {
+                return false;
+            }
+            let next = cell[axis] as isize + step[axis];
+            // Past the grid's edge counts as empty
+            let next_value = if next < 0 || next >= self.size[axis] as isize {
+                0
+            } else {
+                cell[axis] = next as usize;
+                self.get(cell[0], cell[1], cell[2])
+            };
+            if (current == 0) != (next_value == 0) {
+                // Entering faces point back along the ray, leaving faces along it
+                let (index, outward) = if current == 0 {
+                    (next_value, -step[axis] as f64)
+                } else {
+                    (current, step[axis] as f64)
+                };
+                return self.hit_face(ray, t, (axis, outward), index, interval, hit_record);
+            }
+            if next_value == 0 && (next < 0 || next >= self.size[axis] as isize) {
+                return false;
+            }
+            current = next_value;
+            t_max[axis] += t_delta[axis];
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+impl Solid for VoxelGrid {
+    fn is_point_inside(&self, point: Vec3) -> bool {
+        let mut cell = [0usize; 3];
+        for axis in 0..3 {
+            let position = ((point[axis] - self.corner[axis]) / self.voxel_size[axis]).floor();
+            if position < 0.0 || position >= self.size[axis] as f64 {
+                return false;
+            }
+            cell[axis] = position as usize;
+        }
+        self.get(cell[0], cell[1], cell[2]) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+    #[test]
+    fn rays_stop_at_the_first_filled_cell() {
+        let mut grid = VoxelGrid::new([4, 4, 4], Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), vec![material(), material()]).unwrap();
+        grid.set(1, 2, 3, 1);
+        grid.set(1, 2, 0, 2);
+        let down_z = Ray::new(Vec3::new(1.5, 2.5, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = first_hit(&grid, down_z).unwrap();
+        assert!((hit.t - 6.0).abs() < 1e-9);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        // A diagonal ray through empty cells misses
+        assert!(first_hit(&grid, Ray::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0))).is_none());
+        assert!(grid.bounding_box().z.contains(0.5) && !grid.bounding_box().x.contains(2.5));
+    }
+
+    #[test]
+    fn sizes_and_materials_are_checked() {
+        let corner = Vec3::new(0.0, 0.0, 0.0);
+        let voxel_size = Vec3::new(1.0, 1.0, 1.0);
+        assert!(VoxelGrid::new([usize::MAX, 2, 2], corner, voxel_size, vec![material()]).is_err());
+        let data = VoxelData { size: [1, 1, 2], voxels: vec![1, 3], palette: vec![] };
+        assert!(VoxelGrid::from_data(&data, corner, voxel_size, vec![material(), material()]).is_err());
+        assert!(VoxelGrid::from_data(&data, corner, voxel_size, vec![material(), material(), material()]).is_ok());
+    }
+}
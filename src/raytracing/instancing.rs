@@ -0,0 +1,262 @@
+use crate::matrix::Mat4;
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::bvh::BVHNode;
+use crate::raytracing::hittable::{HitRecord, Hittable, HittableList, Transform};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use std::fmt;
+use std::sync::Arc;
+
+// Two-level acceleration structure for repeated geometry.
+// Every unique piece of geometry gets one bottom-level BVH (Blas), built once.
+// Instances only hold a transform and an optional material, and the top-level BVH (Tlas)
+// is built over the instances' world space boxes.
+
+pub struct Blas {
+    pub name: String,
+    root: BVHNode,
+    primitive_count: usize,
+    memory_bytes: usize,
+}
+
+impl Blas {
+    pub fn new(name: &str, list: &mut HittableList) -> Self {
+        let primitive_count = list.hittables.len();
+        // The primitives themselves plus the tree, not counting anything they share (textures, materials)
+        let primitive_bytes: usize = list.hittables.iter()
+            .map(|object| std::mem::size_of_val(&**object) + std::mem::size_of::<Arc<dyn Hittable>>())
+            .sum();
+        let node_bytes = bvh_node_count(primitive_count) * std::mem::size_of::<BVHNode>();
+        Self {
+            name: name.to_string(),
+            root: BVHNode::new(list),
+            primitive_count,
+            memory_bytes: primitive_bytes + node_bytes,
+        }
+    }
+
+    pub fn primitive_count(&self) -> usize {
+        self.primitive_count
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.memory_bytes
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        self.root.bounding_box()
+    }
+}
+
+// Nodes BVHNode::new_from_indices makes for this many objects
+fn bvh_node_count(objects: usize) -> usize {
+    if objects <= 2 {
+        1
+    } else {
+        1 + bvh_node_count(objects / 2) + bvh_node_count(objects - objects / 2)
+    }
+}
+
+// One placement of a Blas. The material, if any, replaces the geometry's own materials.
+pub struct Instance {
+    blas: Arc<Blas>,
+    matrix: Mat4,
+    inverse: Mat4,
+    material: Option<Arc<dyn Material>>,
+    bbox: AABB,
+}
+
+impl Instance {
+    // The matrix has to be invertible
+    pub fn new(blas: Arc<Blas>, matrix: Mat4, material: Option<Arc<dyn Material>>) -> Self {
+        let inverse = matrix.inverse().expect("Instance matrix is not invertible");
+        let bbox = Transform::transformed_bbox(blas.bounding_box(), &matrix);
+        Self { blas, matrix, inverse, material, bbox }
+    }
+}
+
+impl Hittable for Instance {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let object_ray = Ray::with_time(
+            self.inverse.transform_point(ray.origin),
+            self.inverse.transform_vector(ray.direction),
+            ray.time,
+        );
+        if !self.blas.root.first_hit_on_interval(object_ray, interval, hit_record) {
+            return false;
+        }
+        // The object ray only changes space, not the ray parameter, so t is the same in both
+        interval.upper_bound = hit_record.t;
+        hit_record.point = self.matrix.transform_point(hit_record.point);
+        // The normal matrix isn't stored to keep instances small
+        hit_record.normal = self.inverse.transpose().transform_vector(hit_record.normal).normalized();
+        if hit_record.tangent.length_squared() > 0.0 {
+            hit_record.tangent = self.matrix.transform_vector(hit_record.tangent).normalized();
+        }
+        if let Some(material) = &self.material {
+            hit_record.mat = Some(material.clone());
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+// Collects geometry and instances, then builds the Tlas
+pub struct InstanceScene {
+    geometries: Vec<Arc<Blas>>,
+    instances: HittableList,
+    instanced_primitives: usize,
+}
+
+impl InstanceScene {
+    pub fn new() -> Self {
+        Self {
+            geometries: vec![],
+            instances: HittableList::new(),
+            instanced_primitives: 0,
+        }
+    }
+
+    // Builds the Blas, keep the returned handle to place copies of it
+    pub fn add_geometry(&mut self, name: &str, list: &mut HittableList) -> Arc<Blas> {
+        let blas = Arc::new(Blas::new(name, list));
+        self.geometries.push(blas.clone());
+        blas
+    }
+
+    pub fn add_instance(&mut self, blas: &Arc<Blas>, matrix: Mat4, material: Option<Arc<dyn Material>>) {
+        self.instanced_primitives += blas.primitive_count;
+        self.instances.add(Arc::new(Instance::new(blas.clone(), matrix, material)));
+    }
+
+    pub fn build(mut self) -> Tlas {
+        let instance_count = self.instances.hittables.len();
+        let stats = InstancingStats {
+            geometries: self.geometries.len(),
+            instances: instance_count,
+            primitives: self.geometries.iter().map(|blas| blas.primitive_count).sum(),
+            instanced_primitives: self.instanced_primitives,
+            blas_bytes: self.geometries.iter().map(|blas| blas.memory_bytes).sum(),
+            tlas_bytes: instance_count * (std::mem::size_of::<Instance>() + std::mem::size_of::<Arc<dyn Hittable>>())
+                + bvh_node_count(instance_count) * std::mem::size_of::<BVHNode>(),
+        };
+        Tlas {
+            root: BVHNode::new(&mut self.instances),
+            geometries: self.geometries,
+            stats,
+        }
+    }
+}
+
+pub struct Tlas {
+    root: BVHNode,
+    geometries: Vec<Arc<Blas>>,
+    stats: InstancingStats,
+}
+
+impl Tlas {
+    pub fn stats(&self) -> &InstancingStats {
+        &self.stats
+    }
+
+    pub fn geometries(&self) -> &[Arc<Blas>] {
+        &self.geometries
+    }
+}
+
+impl Hittable for Tlas {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        if !self.root.first_hit_on_interval(ray, interval, hit_record) {
+            return false;
+        }
+        interval.upper_bound = hit_record.t;
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.root.bounding_box()
+    }
+}
+
+// Memory accounting, in bytes. Shared materials and textures aren't counted.
+#[derive(Copy, Clone, Debug)]
+pub struct InstancingStats {
+    pub geometries: usize,
+    pub instances: usize,
+    // Unique primitives, stored once
+    pub primitives: usize,
+    // Primitives the scene would have if every instance were a copy
+    pub instanced_primitives: usize,
+    pub blas_bytes: usize,
+    pub tlas_bytes: usize,
+}
+
+impl InstancingStats {
+    pub fn total_bytes(&self) -> usize {
+        self.blas_bytes + self.tlas_bytes
+    }
+}
+
+impl fmt::Display for InstancingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} instances of {} geometries, {} primitives stored for {} placed, {:.2} MiB (BLAS {:.2}, TLAS {:.2})",
+            self.instances,
+            self.geometries,
+            self.primitives,
+            self.instanced_primitives,
+            self.total_bytes() as f64 / (1024.0 * 1024.0),
+            self.blas_bytes as f64 / (1024.0 * 1024.0),
+            self.tlas_bytes as f64 / (1024.0 * 1024.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::flat_bvh::FlatBVH;
+    use crate::raytracing::implicits::sphere::Sphere;
+    use crate::raytracing::material::Lambertian;
+    use crate::vector::Vec3;
+
+    fn unit_sphere_scene() -> InstanceScene {
+        let mut scene = InstanceScene::new();
+        let mut list = HittableList::new();
+        list.add(Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))));
+        let blas = scene.add_geometry("sphere", &mut list);
+        for z in [-20.0, -5.0, -12.0] {
+            scene.add_instance(&blas, Mat4::translation(Vec3::new(0.0, 0.0, z)), None);
+        }
+        scene
+    }
+
+    #[test]
+    fn hits_shorten_the_interval() {
+        let tlas = unit_sphere_scene().build();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        assert!(tlas.first_hit_on_interval(ray, &mut interval, &mut hit_record));
+        assert!((hit_record.t - 4.0).abs() < 1e-9);
+        assert_eq!(interval.upper_bound, hit_record.t);
+    }
+
+    #[test]
+    fn nearest_hit_inside_a_flat_bvh() {
+        let mut list = HittableList::new();
+        list.add(Arc::new(unit_sphere_scene().build()));
+        list.add(Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -30.0), 1.0, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))));
+        let bvh = FlatBVH::new(&mut list);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        assert!(bvh.first_hit_on_interval(ray, &mut interval, &mut hit_record));
+        assert!((hit_record.t - 4.0).abs() < 1e-9);
+    }
+}
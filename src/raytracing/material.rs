@@ -5,7 +5,7 @@ use crate::raytracing::ray::*;
 use crate::raytracing::texture::*;
 use std::sync::Arc;
 
-pub trait Material {
+pub trait Material: Send + Sync {
     fn scatter(&self, ray_in: Ray, hit_record: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool;
 }
 
@@ -31,7 +31,7 @@ impl Material for Lambertian {
         //let scatter_direction = random_on_unit_sphere_above_normal(hit_record.normal);
         let scatter_direction = random_cosine_direction(hit_record.normal);
         *scattered = Ray::with_time(hit_record.point, scatter_direction, ray_in.time);
-        *attenuation = self.texture.value(hit_record.u, hit_record.v, hit_record.point);
+        *attenuation = hit_record.tinted(self.texture.value(hit_record.u, hit_record.v, hit_record.point));
         true
     }
 }
@@ -59,7 +59,7 @@ impl Material for Metal {
         *scattered = Ray::with_time(hit_record.point, reflected, ray_in.time);
         *attenuation = self.albedo;
         if scattered.direction.dot(hit_record.normal) > 0.0 {
-            *attenuation = self.albedo;
+            *attenuation = hit_record.tinted(self.albedo);
             true
         } else {
             *attenuation = Color::new(0.0, 0.0, 0.0);
@@ -130,7 +130,7 @@ impl DiffuseLight {
 
 impl Material for DiffuseLight {
     fn scatter(&self, ray_in: Ray, hit_record: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
-        *attenuation = self.tex.value(hit_record.u, hit_record.v, hit_record.point);
+        *attenuation = hit_record.tinted(self.tex.value(hit_record.u, hit_record.v, hit_record.point));
         false
     }
 }
@@ -154,7 +154,7 @@ impl Isotropic {
 impl Material for Isotropic {
     fn scatter(&self, ray_in: Ray, hit_record: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
         *scattered = Ray::with_time(hit_record.point, random_unit_vector(), ray_in.time);
-        *attenuation = self.tex.value(hit_record.u, hit_record.v, hit_record.point);
+        *attenuation = hit_record.tinted(self.tex.value(hit_record.u, hit_record.v, hit_record.point));
         true
     }
 }
\ No newline at end of file
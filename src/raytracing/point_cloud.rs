@@ -0,0 +1,285 @@
+use crate::color::{Col3u8, Color};
+use crate::mesh::Mesh;
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::implicits::sphere::Sphere;
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PointShape {
+    Sphere,
+    // Two-sided disk facing along the point's normal (splats)
+    Disk,
+}
+
+// Node of the cloud's own BVH. Inner nodes have count 0, their left child right after them
+// and their right child at first. Leaves cover points first..first + count.
+struct PointNode {
+    bbox: AABB,
+    first: u32,
+    count: u32,
+}
+
+// Millions of scanned points without a Hittable (and an Arc) per point.
+// The points are stored as separate arrays, reordered so every BVH leaf is a contiguous range.
+// Per-point colors end up in HitRecord::color, which the material multiplies in,
+// so one white material shows the scan's colors.
+pub struct PointCloud {
+    shape: PointShape,
+    positions: Vec<Vec3>,
+    radii: Vec<f32>,
+    // Empty when the points have no color
+    colors: Vec<Col3u8>,
+    // Only for disks
+    normals: Vec<Vec3>,
+    nodes: Vec<PointNode>,
+    mat: Arc<dyn Material>,
+}
+
+impl PointCloud {
+    const LEAF_SIZE: usize = 4;
+    // Every split halves the points, so with u32 indices no path is longer than this
+    const STACK_SIZE: usize = 64;
+
+    // colors is either empty or one per point
+    pub fn spheres(positions: Vec<Vec3>, radii: Vec<f64>, colors: Vec<Color>, mat: Arc<dyn Material>) -> Self {
+        Self::new(PointShape::Sphere, positions, vec![], radii, colors, mat)
+    }
+
+    pub fn disks(positions: Vec<Vec3>, normals: Vec<Vec3>, radii: Vec<f64>, colors: Vec<Color>, mat: Arc<dyn Material>) -> Self {
+        assert_eq!(normals.len(), positions.len(), "every disk needs a normal");
+        let normals = normals.iter().map(|n| n.normalized()).collect();
+        Self::new(PointShape::Disk, positions, normals, radii, colors, mat)
+    }
+
+    // The mesh's vertices (and their colors and normals) as points of the same radius,
+    // e.g. a PLY scan without faces
+    pub fn from_mesh(mesh: &Mesh, radius: f64, shape: PointShape, mat: Arc<dyn Material>) -> Self {
+        let radii = vec![radius; mesh.vertices.len()];
+        match shape {
+            PointShape::Sphere => Self::spheres(mesh.vertices.clone(), radii, mesh.colors.clone(), mat),
+            PointShape::Disk => Self::disks(mesh.vertices.clone(), mesh.normals.clone(), radii, mesh.colors.clone(), mat),
+        }
+    }
+
+    fn new(shape: PointShape, positions: Vec<Vec3>, normals: Vec<Vec3>, radii: Vec<f64>, colors: Vec<Color>, mat: Arc<dyn Material>) -> Self {
+        assert_eq!(radii.len(), positions.len(), "every point needs a radius");
+        assert!(colors.is_empty() || colors.len() == positions.len(), "colors has to be empty or one per point");
+        let mut cloud = Self {
+            shape,
+            positions,
+            radii: radii.iter().map(|r| *r as f32).collect(),
+            colors: colors.iter().map(|c| Col3u8::from(*c)).collect(),
+            normals,
+            nodes: vec![],
+            mat,
+        };
+        let mut order: Vec<usize> = (0..cloud.positions.len()).collect();
+        if !order.is_empty() {
+            cloud.build_node(&mut order, 0);
+        }
+        // Put the points in leaf order
+        cloud.positions = order.iter().map(|&i| cloud.positions[i]).collect();
+        cloud.radii = order.iter().map(|&i| cloud.radii[i]).collect();
+        if !cloud.colors.is_empty() {
+            cloud.colors = order.iter().map(|&i| cloud.colors[i]).collect();
+        }
+        if !cloud.normals.is_empty() {
+            cloud.normals = order.iter().map(|&i| cloud.normals[i]).collect();
+        }
+        cloud
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    // Points and tree, without the material
+    pub fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(&self.positions[..])
+            + std::mem::size_of_val(&self.radii[..])
+            + std::mem::size_of_val(&self.colors[..])
+            + std::mem::size_of_val(&self.normals[..])
+            + std::mem::size_of_val(&self.nodes[..])
+    }
+
+    fn point_bbox(&self, index: usize) -> AABB {
+        let r = self.radii[index] as f64;
+        let extent = Vec3::new(r, r, r);
+        AABB::from_corners(self.positions[index] - extent, self.positions[index] + extent)
+    }
+
+    // Builds the node for order[start..start + order.len()] (median split on the longest axis),
+    // returns its index
+    fn build_node(&mut self, order: &mut [usize], start: usize) -> usize {
+        let bbox = order.iter().fold(AABB::EMPTY, |bbox, &i| AABB::from_aabbs(bbox, self.point_bbox(i)));
+        let index = self.nodes.len();
+        if order.len() <= Self::LEAF_SIZE {
+            self.nodes.push(PointNode { bbox, first: start as u32, count: order.len() as u32 });
+            return index;
+        }
+        self.nodes.push(PointNode { bbox, first: 0, count: 0 });
+        let axis = bbox.longest_axis();
+        let middle = order.len() / 2;
+        order.select_nth_unstable_by(middle, |&a, &b| self.positions[a][axis].total_cmp(&self.positions[b][axis]));
+        let (left, right) = order.split_at_mut(middle);
+        self.build_node(left, start);
+        let right_index = self.build_node(right, start + middle);
+        self.nodes[index].first = right_index as u32;
+        index
+    }
+
+    // t of the ray's first hit on point index within interval
+    fn hit_point(&self, index: usize, ray: Ray, interval: &Interval) -> Option<f64> {
+        let center = self.positions[index];
+        let radius = self.radii[index] as f64;
+        match self.shape {
+            PointShape::Sphere => {
+                let oc = center - ray.origin;
+                let a = ray.direction.length_squared();
+                let h = ray.direction.dot(oc);
+                let discriminant = h * h - a * (oc.length_squared() - radius * radius);
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let root = discriminant.sqrt();
+                [(h - root) / a, (h + root) / a].into_iter().find(|t| interval.contains(*t))
+            }
+            PointShape::Disk => {
+                let normal = self.normals[index];
+                let denominator = normal.dot(ray.direction);
+                if denominator.abs() < 1e-12 {
+                    return None;
+                }
+                let t = normal.dot(center - ray.origin) / denominator;
+                if !interval.contains(t) || (ray.at(t) - center).length_squared() > radius * radius {
+                    return None;
+                }
+                Some(t)
+            }
+        }
+    }
+}
+
+impl Hittable for PointCloud {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let mut closest = None;
+        let mut stack = [0u32; Self::STACK_SIZE];
+        let mut stack_size = 1;
+        while stack_size > 0 {
+            stack_size -= 1;
+            let node_index = stack[stack_size] as usize;
+            let node: &PointNode = &self.nodes[node_index];
+            if node.bbox.clip(ray, *interval).is_none() {
+                continue;
+            }
+            if node.count == 0 {
+                stack[stack_size] = node.first;
+                stack[stack_size + 1] = node_index as u32 + 1;
+                stack_size += 2;
+                continue;
+            }
+            for index in node.first as usize..(node.first + node.count) as usize {
+                if let Some(t) = self.hit_point(index, ray, interval) {
+                    interval.upper_bound = t;
+                    closest = Some(index);
+                }
+            }
+        }
+        let Some(index) = closest else {
+            return false;
+        };
+
+        let t = interval.upper_bound;
+        let point = ray.at(t);
+        let center = self.positions[index];
+        let radius = self.radii[index] as f64;
+        let outward_normal = match self.shape {
+            PointShape::Sphere => (point - center) / radius,
+            PointShape::Disk => self.normals[index],
+        };
+        hit_record.t = t;
+        hit_record.point = point;
+        (hit_record.u, hit_record.v) = match self.shape {
+            PointShape::Sphere => Sphere::get_sphere_uv(outward_normal),
+            // v from the center (0) to the edge (1)
+            PointShape::Disk => (0.0, (point - center).length() / radius),
+        };
+        hit_record.mat = Some(self.mat.clone());
+        hit_record.set_face_normal(ray, outward_normal);
+        hit_record.color = self.colors.get(index).map(|c| Color::from(*c));
+        true
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.nodes.first().map_or(AABB::EMPTY, |root| root.bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::hittable::HittableList;
+    use crate::raytracing::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)))
+    }
+
+    fn grid() -> Vec<Vec3> {
+        (0..400).map(|i| Vec3::new((i % 20) as f64 * 0.5, (i / 20) as f64 * 0.5, -((i * 7) % 11) as f64)).collect()
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+    #[test]
+    fn spheres_match_separate_spheres() {
+        let positions = grid();
+        let radii: Vec<f64> = (0..positions.len()).map(|i| 0.1 + 0.05 * (i % 4) as f64).collect();
+        let cloud = PointCloud::spheres(positions.clone(), radii.clone(), vec![], material());
+        let mut list = HittableList::new();
+        for (position, radius) in positions.iter().zip(&radii) {
+            list.add(Arc::new(Sphere::new(*position, *radius, material())));
+        }
+        for x in 0..50 {
+            for y in 0..50 {
+                // Offset so no ray grazes a sphere, where f32 and f64 radii could disagree
+                let ray = Ray::new(Vec3::new(x as f64 * 0.2 + 0.013, y as f64 * 0.2 + 0.007, 5.0), Vec3::new(0.0, 0.0, -1.0));
+                let expected = first_hit(&list, ray).map(|hit| hit.t);
+                let t = first_hit(&cloud, ray).map(|hit| hit.t);
+                // The cloud keeps radii as f32
+                assert_eq!(t.is_some(), expected.is_some(), "ray {x} {y}");
+                if let (Some(t), Some(expected)) = (t, expected) {
+                    assert!((t - expected).abs() < 1e-6, "ray {x} {y}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn disks_carry_their_colors() {
+        let positions = vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)];
+        let normals = vec![Vec3::new(0.0, 0.0, 2.0); 2];
+        let colors = vec![Color::new(1.0, 0.0, 0.0), Color::new(0.0, 1.0, 0.0)];
+        let cloud = PointCloud::disks(positions, normals, vec![0.5, 0.5], colors, material());
+        let hit = first_hit(&cloud, Ray::new(Vec3::new(0.1, 0.1, 3.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-12);
+        assert_eq!(hit.color.map(|c| (c.x, c.y, c.z)), Some((1.0, 0.0, 0.0)));
+        assert!(first_hit(&cloud, Ray::new(Vec3::new(0.6, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+    }
+}
@@ -0,0 +1,204 @@
+pub mod primitives;
+pub mod operations;
+
+use crate::raytracing::aabb::AABB;
+use crate::raytracing::hittable::{HitRecord, Hittable};
+use crate::raytracing::interval::Interval;
+use crate::raytracing::material::Material;
+use crate::raytracing::ray::Ray;
+use crate::solid::Solid;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Signed distance to a surface: negative inside, positive outside.
+// The sphere tracer relies on the distance never being more than the true distance,
+// distorting operations (twist, bend) break that and need a smaller step scale.
+pub trait Sdf: Send + Sync {
+    fn distance(&self, point: Vec3) -> f64;
+}
+
+// Renders an Sdf by sphere tracing inside user supplied bounds.
+// The bounds are also the bounding box, so they have to contain the whole surface.
+pub struct SdfHittable {
+    sdf: Arc<dyn Sdf>,
+    bounds: AABB,
+    mat: Arc<dyn Material>,
+    max_steps: usize,
+    // A point this close to the surface counts as a hit
+    epsilon: f64,
+    // Fraction of the distance to step, 1 for exact distance functions
+    step_scale: f64,
+}
+
+impl SdfHittable {
+    pub fn new(sdf: Arc<dyn Sdf>, bounds: AABB, mat: Arc<dyn Material>) -> Self {
+        Self {
+            sdf,
+            bounds,
+            mat,
+            max_steps: 256,
+            epsilon: 1e-4,
+            step_scale: 1.0,
+        }
+    }
+
+    pub fn with_precision(mut self, epsilon: f64, max_steps: usize) -> Self {
+        self.epsilon = epsilon;
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn with_step_scale(mut self, step_scale: f64) -> Self {
+        self.step_scale = step_scale;
+        self
+    }
+
+    // Gradient by central differences, points outward
+    fn normal(&self, point: Vec3) -> Vec3 {
+        let h = self.epsilon;
+        let dx = Vec3::new(h, 0.0, 0.0);
+        let dy = Vec3::new(0.0, h, 0.0);
+        let dz = Vec3::new(0.0, 0.0, h);
+        let gradient = Vec3::new(
+            self.sdf.distance(point + dx) - self.sdf.distance(point - dx),
+            self.sdf.distance(point + dy) - self.sdf.distance(point - dy),
+            self.sdf.distance(point + dz) - self.sdf.distance(point - dz),
+        );
+        if gradient.length_squared() > 0.0 { gradient.normalized() } else { Vec3::new(0.0, 1.0, 0.0) }
+    }
+}
+
+impl Hittable for SdfHittable {
+    fn first_hit_on_interval(&self, ray: Ray, interval: &mut Interval, hit_record: &mut HitRecord) -> bool {
+        let Some(range) = self.bounds.clip(ray, *interval) else {
+            return false;
+        };
+        let direction_length = ray.direction.length();
+        if direction_length == 0.0 {
+            return false;
+        }
+        // Rays that start inside (refraction) march towards the surface from the other side
+        let mut t = range.lower_bound;
+        let start = ray.at(t);
+        let start_distance = self.sdf.distance(start);
+        let side = if start_distance.abs() < self.epsilon {
+            // Scattered rays start on the surface, the side is the one they leave towards
+            if self.normal(start).dot(ray.direction) < 0.0 { -1.0 } else { 1.0 }
+        } else if start_distance < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        // Only hits after getting clear of the surface count, otherwise every scattered ray hits where it started
+        let mut clear = side * start_distance >= self.epsilon;
+        for _ in 0..self.max_steps {
+            let distance = side * self.sdf.distance(ray.at(t));
+            if !clear {
+                clear = distance >= self.epsilon;
+                t += self.epsilon.max(distance) / direction_length;
+                if t > range.upper_bound {
+                    return false;
+                }
+                continue;
+            }
+            if distance < self.epsilon {
+                if !interval.contains(t) {
+                    return false;
+                }
+                let point = ray.at(t);
+                interval.upper_bound = t;
+                hit_record.t = t;
+                hit_record.point = point;
+                hit_record.u = 0.0;
+                hit_record.v = 0.0;
+                hit_record.mat = Some(self.mat.clone());
+                hit_record.set_face_normal(ray, self.normal(point));
+                return true;
+            }
+            t += self.step_scale * distance / direction_length;
+            if t > range.upper_bound {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bounds
+    }
+}
+
+impl Solid for SdfHittable {
+    fn is_point_inside(&self, point: Vec3) -> bool {
+        self.sdf.distance(point) < 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::implicits::sphere::Sphere;
+    use crate::raytracing::material::Lambertian;
+    use crate::raytracing::sdf::primitives::SdfSphere;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn first_hit(object: &dyn Hittable, ray: Ray) -> Option<HitRecord> {
+        let mut interval = Interval::new(0.001, f64::INFINITY);
+        let mut hit_record = HitRecord::new();
+        object.first_hit_on_interval(ray, &mut interval, &mut hit_record).then_some(hit_record)
+    }
+
+    fn unit_sphere() -> SdfHittable {
+        let bounds = AABB::from_corners(Vec3::new(-1.1, -1.1, -1.1), Vec3::new(1.1, 1.1, 1.1));
+        SdfHittable::new(Arc::new(SdfSphere { radius: 1.0 }), bounds, material())
+    }
+
+    #[test]
+    fn matches_an_analytic_sphere() {
+        let traced = unit_sphere();
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, material());
+        for x in 0..20 {
+            for y in 0..20 {
+                let origin = Vec3::new(x as f64 * 0.11 - 1.05, y as f64 * 0.11 - 1.05, 5.0);
+                let ray = Ray::new(origin, Vec3::new(0.05, -0.02, -1.0));
+                match (first_hit(&traced, ray), first_hit(&sphere, ray)) {
+                    (Some(traced_hit), Some(exact_hit)) => {
+                        // Stops within epsilon of the surface, never past it
+                        assert!(traced_hit.t <= exact_hit.t + 1e-9, "ray {} {}", x, y);
+                        assert!((ray.at(traced_hit.t).length() - 1.0).abs() < 1e-3, "ray {} {}", x, y);
+                        assert!(traced_hit.front_face);
+                        // Grazing rays get within epsilon long before they reach the surface
+                        if exact_hit.normal.dot(ray.direction.normalized()) < -0.2 {
+                            assert!((traced_hit.t - exact_hit.t).abs() < 1e-3, "ray {} {}", x, y);
+                            assert!((traced_hit.normal - exact_hit.normal).length() < 1e-3, "ray {} {}", x, y);
+                        }
+                    }
+                    (None, None) => {}
+                    // Rays grazing the edge within epsilon can go either way
+                    (traced_hit, exact_hit) => {
+                        let t = traced_hit.or(exact_hit).unwrap().t;
+                        assert!((ray.at(t).length() - 1.0).abs() < 1e-3, "ray {} {} at {}", x, y, t);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rays_leaving_the_surface_find_the_far_side() {
+        let traced = unit_sphere();
+        // Scattered into the sphere from its surface, as refraction does
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = first_hit(&traced, ray).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-3 && !hit.front_face);
+        // Scattered away from it, nothing else to hit
+        assert!(first_hit(&traced, Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.3, 0.0, 1.0))).is_none());
+        // From inside
+        let hit = first_hit(&traced, Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0))).unwrap();
+        assert!((hit.t - 0.5).abs() < 1e-3 && !hit.front_face);
+        assert!(traced.is_point_inside(Vec3::new(0.0, 0.5, 0.0)) && !traced.is_point_inside(Vec3::new(0.0, 1.5, 0.0)));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::raytracing::sdf::Sdf;
+use crate::vector::Vec3;
+use std::sync::Arc;
+
+// Combinations and domain operations. The smooth versions blend the surfaces over
+// a distance of about k, and k = 0 gives the sharp result.
+
+// Polynomial smooth minimum
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+fn smooth_max(a: f64, b: f64, k: f64) -> f64 {
+    -smooth_min(-a, -b, k)
+}
+
+pub struct SmoothUnion {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+    pub k: f64,
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, point: Vec3) -> f64 {
+        smooth_min(self.a.distance(point), self.b.distance(point), self.k)
+    }
+}
+
+pub struct SmoothIntersection {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+    pub k: f64,
+}
+
+impl Sdf for SmoothIntersection {
+    fn distance(&self, point: Vec3) -> f64 {
+        smooth_max(self.a.distance(point), self.b.distance(point), self.k)
+    }
+}
+
+// a minus b
+pub struct SmoothSubtraction {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+    pub k: f64,
+}
+
+impl Sdf for SmoothSubtraction {
+    fn distance(&self, point: Vec3) -> f64 {
+        smooth_max(self.a.distance(point), -self.b.distance(point), self.k)
+    }
+}
+
+pub struct Translate {
+    pub inner: Arc<dyn Sdf>,
+    pub offset: Vec3,
+}
+
+impl Sdf for Translate {
+    fn distance(&self, point: Vec3) -> f64 {
+        self.inner.distance(point - self.offset)
+    }
+}
+
+// Copies of inner every `period` along each axis, a period of 0 leaves that axis alone.
+// The inner shape should fit in one cell, otherwise the distance is wrong near the cell walls.
+pub struct Repeat {
+    pub inner: Arc<dyn Sdf>,
+    pub period: Vec3,
+    // Copies on each side of the origin per axis, None repeats forever
+    pub limit: Option<Vec3>,
+}
+
+impl Repeat {
+    pub fn new(inner: Arc<dyn Sdf>, period: Vec3) -> Self {
+        Self { inner, period, limit: None }
+    }
+
+    pub fn limited(inner: Arc<dyn Sdf>, period: Vec3, limit: Vec3) -> Self {
+        Self { inner, period, limit: Some(limit) }
+    }
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, point: Vec3) -> f64 {
+        let mut local = point;
+        for axis in 0..3 {
+            let period = self.period[axis];
+            if period <= 0.0 {
+                continue;
+            }
+            let mut cell = (point[axis] / period).round();
+            if let Some(limit) = self.limit {
+                cell = cell.clamp(-limit[axis], limit[axis]);
+            }
+            local[axis] = point[axis] - period * cell;
+        }
+        self.inner.distance(local)
+    }
+}
+
+// Twists around the y axis by `rate` radians per unit of height.
+// Not an exact distance anymore, trace it with a step scale below 1.
+pub struct Twist {
+    pub inner: Arc<dyn Sdf>,
+    pub rate: f64,
+}
+
+impl Sdf for Twist {
+    fn distance(&self, point: Vec3) -> f64 {
+        let (sin, cos) = (self.rate * point.y).sin_cos();
+        let local = Vec3::new(cos * point.x - sin * point.z, point.y, sin * point.x + cos * point.z);
+        self.inner.distance(local)
+    }
+}
+
+// Bends the x axis into an arc in the xy plane, `rate` radians per unit along x.
+// Not an exact distance anymore either.
+pub struct Bend {
+    pub inner: Arc<dyn Sdf>,
+    pub rate: f64,
+}
+
+impl Sdf for Bend {
+    fn distance(&self, point: Vec3) -> f64 {
+        let (sin, cos) = (self.rate * point.x).sin_cos();
+        let local = Vec3::new(cos * point.x - sin * point.y, sin * point.x + cos * point.y, point.z);
+        self.inner.distance(local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::sdf::primitives::SdfSphere;
+
+    fn sphere_at(x: f64) -> Arc<dyn Sdf> {
+        Arc::new(Translate { inner: Arc::new(SdfSphere { radius: 1.0 }), offset: Vec3::new(x, 0.0, 0.0) })
+    }
+
+    #[test]
+    fn smooth_min_blends_below_min() {
+        assert_eq!(smooth_min(0.3, 0.5, 0.0), 0.3);
+        assert!(smooth_min(0.3, 0.5, 0.5) < 0.3);
+        // Far apart compared to k, it's the plain min
+        assert_eq!(smooth_min(0.0, 5.0, 0.5), 0.0);
+        assert_eq!(smooth_max(0.3, 0.5, 0.0), 0.5);
+    }
+
+    #[test]
+    fn combinations() {
+        let (a, b) = (sphere_at(-0.5), sphere_at(0.5));
+        let union = SmoothUnion { a: a.clone(), b: b.clone(), k: 0.0 };
+        let intersection = SmoothIntersection { a: a.clone(), b: b.clone(), k: 0.0 };
+        let subtraction = SmoothSubtraction { a, b, k: 0.0 };
+        let (left, middle) = (Vec3::new(-1.2, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        assert!(union.distance(left) < 0.0 && union.distance(middle) < 0.0);
+        assert!(intersection.distance(left) > 0.0 && intersection.distance(middle) < 0.0);
+        assert!(subtraction.distance(left) < 0.0 && subtraction.distance(middle) > 0.0);
+        assert!((subtraction.distance(Vec3::new(-0.5, 0.0, 0.0)) - 0.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn repeat_copies_the_shape() {
+        let sphere: Arc<dyn Sdf> = Arc::new(SdfSphere { radius: 0.25 });
+        let forever = Repeat::new(sphere.clone(), Vec3::new(2.0, 0.0, 0.0));
+        assert!((forever.distance(Vec3::new(100.0, 0.0, 0.0)) + 0.25).abs() < 1e-12);
+        // Not repeated along y
+        assert!((forever.distance(Vec3::new(0.0, 4.0, 0.0)) - 3.75).abs() < 1e-12);
+        let limited = Repeat::limited(sphere, Vec3::new(2.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!((limited.distance(Vec3::new(2.0, 0.0, 0.0)) + 0.25).abs() < 1e-12);
+        assert!((limited.distance(Vec3::new(6.0, 0.0, 0.0)) - 3.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn twist_and_bend_leave_the_origin_alone() {
+        let sphere: Arc<dyn Sdf> = Arc::new(SdfSphere { radius: 1.0 });
+        let twist = Twist { inner: sphere.clone(), rate: 0.7 };
+        let bend = Bend { inner: sphere.clone(), rate: 0.7 };
+        // Rotations keep a centered sphere the same
+        for point in [Vec3::new(0.3, 0.5, -0.2), Vec3::new(1.5, -0.7, 0.4)] {
+            assert!((twist.distance(point) - sphere.distance(point)).abs() < 1e-12);
+            assert!((bend.distance(point) - sphere.distance(point)).abs() < 1e-12);
+        }
+    }
+}
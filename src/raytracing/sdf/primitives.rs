@@ -0,0 +1,139 @@
+use crate::raytracing::sdf::Sdf;
+use crate::vector::Vec3;
+
+// Exact distance functions, centered on the origin.
+// Place them with operations::Translate.
+
+fn abs(v: Vec3) -> Vec3 {
+    Vec3::new(v.x.abs(), v.y.abs(), v.z.abs())
+}
+
+fn positive_part(v: Vec3) -> Vec3 {
+    Vec3::new(v.x.max(0.0), v.y.max(0.0), v.z.max(0.0))
+}
+
+fn box_distance(point: Vec3, half_extents: Vec3) -> f64 {
+    let q = abs(point) - half_extents;
+    positive_part(q).length() + q.x.max(q.y.max(q.z)).min(0.0)
+}
+
+pub struct SdfSphere {
+    pub radius: f64,
+}
+
+impl Sdf for SdfSphere {
+    fn distance(&self, point: Vec3) -> f64 {
+        point.length() - self.radius
+    }
+}
+
+pub struct SdfBox {
+    pub half_extents: Vec3,
+}
+
+impl Sdf for SdfBox {
+    fn distance(&self, point: Vec3) -> f64 {
+        box_distance(point, self.half_extents)
+    }
+}
+
+// Box with its edges rounded off, the rounding stays inside half_extents
+pub struct SdfRoundBox {
+    pub half_extents: Vec3,
+    pub radius: f64,
+}
+
+impl Sdf for SdfRoundBox {
+    fn distance(&self, point: Vec3) -> f64 {
+        let inner = self.half_extents - Vec3::new(self.radius, self.radius, self.radius);
+        box_distance(point, inner) - self.radius
+    }
+}
+
+// Ring in the xz plane, around the y axis
+pub struct SdfTorus {
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+impl Sdf for SdfTorus {
+    fn distance(&self, point: Vec3) -> f64 {
+        let ring_distance = (point.x * point.x + point.z * point.z).sqrt() - self.major_radius;
+        (ring_distance * ring_distance + point.y * point.y).sqrt() - self.minor_radius
+    }
+}
+
+// Everything within radius of the segment a-b
+pub struct SdfCapsule {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f64,
+}
+
+impl Sdf for SdfCapsule {
+    fn distance(&self, point: Vec3) -> f64 {
+        let pa = point - self.a;
+        let ba = self.b - self.a;
+        let h = (pa.dot(ba) / ba.dot(ba)).clamp(0.0, 1.0);
+        (pa - h * ba).length() - self.radius
+    }
+}
+
+// Capped cylinder along the y axis, from -half_height to half_height
+pub struct SdfCylinder {
+    pub half_height: f64,
+    pub radius: f64,
+}
+
+impl Sdf for SdfCylinder {
+    fn distance(&self, point: Vec3) -> f64 {
+        let radial = (point.x * point.x + point.z * point.z).sqrt() - self.radius;
+        let axial = point.y.abs() - self.half_height;
+        let outside = (radial.max(0.0) * radial.max(0.0) + axial.max(0.0) * axial.max(0.0)).sqrt();
+        radial.max(axial).min(0.0) + outside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn primitives() -> Vec<Box<dyn Sdf>> {
+        vec![
+            Box::new(SdfSphere { radius: 1.0 }),
+            Box::new(SdfBox { half_extents: Vec3::new(1.0, 0.5, 0.25) }),
+            Box::new(SdfRoundBox { half_extents: Vec3::new(1.0, 0.5, 0.25), radius: 0.1 }),
+            Box::new(SdfTorus { major_radius: 1.0, minor_radius: 0.25 }),
+            Box::new(SdfCapsule { a: Vec3::new(-1.0, 0.0, 0.0), b: Vec3::new(0.5, 0.5, 0.0), radius: 0.3 }),
+            Box::new(SdfCylinder { half_height: 0.5, radius: 0.75 }),
+        ]
+    }
+
+    #[test]
+    fn known_distances() {
+        let sdf_box = SdfBox { half_extents: Vec3::new(1.0, 0.5, 0.25) };
+        assert!((sdf_box.distance(Vec3::new(3.0, 0.0, 0.0)) - 2.0).abs() < 1e-12);
+        assert!((sdf_box.distance(Vec3::new(0.0, 0.0, 0.0)) + 0.25).abs() < 1e-12);
+        // Past a corner it's the distance to the corner
+        assert!((sdf_box.distance(Vec3::new(2.0, 1.5, 0.25)) - 2.0_f64.sqrt()).abs() < 1e-12);
+        let torus = SdfTorus { major_radius: 1.0, minor_radius: 0.25 };
+        assert!((torus.distance(Vec3::new(0.0, 0.0, 0.0)) - 0.75).abs() < 1e-12);
+        assert!((torus.distance(Vec3::new(0.0, 0.0, 1.0)) + 0.25).abs() < 1e-12);
+        let cylinder = SdfCylinder { half_height: 0.5, radius: 0.75 };
+        assert!((cylinder.distance(Vec3::new(0.0, 2.0, 0.0)) - 1.5).abs() < 1e-12);
+        assert!((cylinder.distance(Vec3::new(0.0, 0.4, 0.0)) + 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn distances_never_overshoot() {
+        // Sphere tracing needs |d(p) - d(q)| <= |p - q|
+        for sdf in primitives() {
+            for i in 0..500 {
+                let f = i as f64;
+                let p = Vec3::new((f * 0.37).sin() * 2.0, (f * 0.71).cos() * 2.0, (f * 0.13).sin() * 2.0);
+                let q = p + Vec3::new((f * 1.3).cos(), (f * 0.9).sin(), (f * 2.1).cos()) * 0.3;
+                assert!((sdf.distance(p) - sdf.distance(q)).abs() <= (p - q).length() + 1e-12);
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 use crate::color::*;
 use crate::vector::*;
-use stb_image::stb_image::{stbi_loadf, stbi_load, stbi_image_free, stbi_set_flip_vertically_on_load};
+use stb_image::stb_image::{stbi_loadf, stbi_load, stbi_load_from_memory, stbi_image_free, stbi_set_flip_vertically_on_load};
 use std::fs;
 use std::ffi::CString;
 use std::path::Path;
@@ -10,7 +10,7 @@ use crate::image::Image;
 use crate::raytracing::interval::Interval;
 use crate::random;
 
-pub trait Texture {
+pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, p: Vec3) -> Color;
 }
 
@@ -137,6 +137,25 @@ impl RTWImage {
         }
     }
 
+    // Encoded image file contents (png, jpg, ...), e.g. embedded in a model file
+    pub fn from_memory(bytes: &[u8]) -> Option<Self> {
+        let mut image = RTWImage::new();
+        let mut width = 0;
+        let mut height = 0;
+        let mut n = image.bytes_per_pixel as i32;
+        unsafe {
+            stbi_set_flip_vertically_on_load(1);
+            image.bdata = stbi_load_from_memory(bytes.as_ptr(), bytes.len() as i32, &mut width, &mut height, &mut n, image.bytes_per_pixel as i32);
+        }
+        if image.bdata.is_null() {
+            return None;
+        }
+        image.width = width as usize;
+        image.height = height as usize;
+        image.bytes_per_scanline = image.width * image.bytes_per_pixel;
+        Some(image)
+    }
+
     pub fn pixel_data(&self, x: usize, y: usize) -> &[u8 ] {
         static MAGENTA: [u8;3] = [255, 0, 255];
         if self.bdata.is_null() {
@@ -194,6 +213,10 @@ impl RTWImage {
     }*/
 }
 
+// The pixels are only written while loading, after that threads just read them
+unsafe impl Sync for RTWImage {}
+unsafe impl Send for RTWImage {}
+
 impl Drop for RTWImage {
     fn drop(&mut self) {
         unsafe {
@@ -216,6 +239,26 @@ impl ImageTexture {
             image: RTWImage::from_path(path).unwrap(),
         }
     }
+
+    pub fn try_new<P: AsRef<Path>>(path: P) -> Option<Self> {
+        Some(Self {
+            image: RTWImage::from_path(path)?,
+        })
+    }
+
+    pub fn from_memory(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            image: RTWImage::from_memory(bytes)?,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.height()
+    }
 }
 
 impl Texture for ImageTexture {
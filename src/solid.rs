@@ -1,5 +1,11 @@
+use crate::raytracing::hittable::Hittable;
 use crate::vector::*;
-#[expect(unused)]
+
 pub trait Solid {
     fn is_point_inside(&self, point: Vec3) -> bool;
-}
\ No newline at end of file
+}
+
+// Hittables that enclose a volume, the operands of CSG
+pub trait SolidHittable: Hittable + Solid {}
+
+impl<T: Hittable + Solid> SolidHittable for T {}
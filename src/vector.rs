@@ -1,5 +1,6 @@
 use std::ops::*;
 use std::ops;
+use crate::matrix::Mat4;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Vec3 {
@@ -240,6 +241,218 @@ impl ops::Neg for Vec3 {
     }
 }
 
+// 0 = x, 1 = y, 2 = z
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+    #[inline]
+    fn index(&self, index: usize) -> &f64 {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 index out of range: {}", index),
+        }
+    }
+}
+
+impl ops::IndexMut<usize> for Vec3 {
+    #[inline]
+    fn index_mut(&mut self, index: usize) -> &mut f64 {
+        match index {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("Vec3 index out of range: {}", index),
+        }
+    }
+}
+
+// Rotation quaternion, w + xi + yj + zk. Rotations should be unit length.
+// Like Mat4, a * b rotates by b first.
+#[derive(Copy, Clone, Debug)]
+pub struct Quat {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+
+    #[inline]
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Self {
+        Self { w, x, y, z }
+    }
+
+    // Counter-clockwise around the axis, same as Mat4::rotation
+    pub fn from_axis_angle(axis: Vec3, radians: f64) -> Self {
+        let (sin, cos) = (radians / 2.0).sin_cos();
+        let axis = axis.normalized() * sin;
+        Self::new(cos, axis.x, axis.y, axis.z)
+    }
+
+    // Angle in [0, pi], the axis is x for the identity
+    pub fn to_axis_angle(self) -> (Vec3, f64) {
+        let q = if self.w < 0.0 { -self.normalized() } else { self.normalized() };
+        let sin = (1.0 - q.w * q.w).max(0.0).sqrt();
+        if sin < 1e-12 {
+            return (Vec3::new(1.0, 0.0, 0.0), 0.0);
+        }
+        (Vec3::new(q.x, q.y, q.z) / sin, 2.0 * q.w.clamp(-1.0, 1.0).acos())
+    }
+
+    // Same angles and order as Vec3::rotated: x = roll, y = pitch, z = yaw, applied roll first
+    pub fn from_euler(angles_radians: Vec3) -> Self {
+        let roll = Self::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), angles_radians.x);
+        let pitch = Self::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), angles_radians.y);
+        let yaw = Self::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), angles_radians.z);
+        yaw * pitch * roll
+    }
+
+    // Inverse of from_euler, pitch ends up in [-pi/2, pi/2]
+    pub fn to_euler(self) -> Vec3 {
+        let q = self.normalized();
+        let roll = f64::atan2(2.0 * (q.w * q.x + q.y * q.z), 1.0 - 2.0 * (q.x * q.x + q.y * q.y));
+        let pitch = (2.0 * (q.w * q.y - q.z * q.x)).clamp(-1.0, 1.0).asin();
+        let yaw = f64::atan2(2.0 * (q.w * q.z + q.x * q.y), 1.0 - 2.0 * (q.y * q.y + q.z * q.z));
+        Vec3::new(roll, pitch, yaw)
+    }
+
+    pub fn to_mat4(self) -> Mat4 {
+        let Quat { w, x, y, z } = self.normalized();
+        Mat4::from_rows([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w), 0.0],
+            [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w), 0.0],
+            [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Only looks at the upper 3x3, which should be a pure rotation (see Mat4::decompose)
+    pub fn from_mat4(matrix: &Mat4) -> Self {
+        let m = matrix.rows();
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        // Divide by the largest of w, x, y, z to stay accurate
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Self::new(s / 4.0, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Self::new((m[2][1] - m[1][2]) / s, s / 4.0, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Self::new((m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, s / 4.0, (m[1][2] + m[2][1]) / s)
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Self::new((m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, s / 4.0)
+        };
+        q.normalized()
+    }
+
+    #[inline]
+    pub fn dot(&self, other: Self) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    #[inline]
+    pub fn length(&self) -> f64 {
+        self.dot(*self).sqrt()
+    }
+
+    #[inline]
+    pub fn normalized(&self) -> Self {
+        *self * (1.0 / self.length())
+    }
+
+    // The inverse for unit quaternions
+    #[inline]
+    pub fn conjugate(&self) -> Self {
+        Self::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    pub fn inverse(&self) -> Self {
+        self.conjugate() * (1.0 / self.dot(*self))
+    }
+
+    #[inline]
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        let axis = Vec3::new(self.x, self.y, self.z);
+        let t = 2.0 * axis.cross(v);
+        v + self.w * t + axis.cross(t)
+    }
+
+    // Normalized linear blend along the shorter arc. Cheap, but the speed isn't constant.
+    pub fn nlerp(&self, other: Self, t: f64) -> Self {
+        let other = if self.dot(other) < 0.0 { -other } else { other };
+        (*self + (other - *self) * t).normalized()
+    }
+
+    // Constant speed along the shorter arc
+    pub fn slerp(&self, other: Self, t: f64) -> Self {
+        let mut cos_theta = self.dot(other);
+        let other = if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            -other
+        } else {
+            other
+        };
+        // Nearly the same rotation, sin(theta) gets too small to divide by
+        if cos_theta > 0.9995 {
+            return self.nlerp(other, t);
+        }
+        let theta = cos_theta.acos();
+        let sin_theta = theta.sin();
+        (*self * (((1.0 - t) * theta).sin() / sin_theta) + other * ((t * theta).sin() / sin_theta)).normalized()
+    }
+}
+
+impl ops::Mul<Quat> for Quat {
+    type Output = Quat;
+    #[inline]
+    fn mul(self, _rhs: Quat) -> Quat {
+        Quat {
+            w: self.w * _rhs.w - self.x * _rhs.x - self.y * _rhs.y - self.z * _rhs.z,
+            x: self.w * _rhs.x + self.x * _rhs.w + self.y * _rhs.z - self.z * _rhs.y,
+            y: self.w * _rhs.y - self.x * _rhs.z + self.y * _rhs.w + self.z * _rhs.x,
+            z: self.w * _rhs.z + self.x * _rhs.y - self.y * _rhs.x + self.z * _rhs.w,
+        }
+    }
+}
+
+// Componentwise, for blending quaternions as 4D vectors
+impl ops::Add<Quat> for Quat {
+    type Output = Quat;
+    #[inline]
+    fn add(self, _rhs: Quat) -> Quat {
+        Quat::new(self.w + _rhs.w, self.x + _rhs.x, self.y + _rhs.y, self.z + _rhs.z)
+    }
+}
+
+impl ops::Sub<Quat> for Quat {
+    type Output = Quat;
+    #[inline]
+    fn sub(self, _rhs: Quat) -> Quat {
+        Quat::new(self.w - _rhs.w, self.x - _rhs.x, self.y - _rhs.y, self.z - _rhs.z)
+    }
+}
+
+impl ops::Mul<f64> for Quat {
+    type Output = Quat;
+    #[inline]
+    fn mul(self, _rhs: f64) -> Quat {
+        Quat::new(self.w * _rhs, self.x * _rhs, self.y * _rhs, self.z * _rhs)
+    }
+}
+
+impl ops::Neg for Quat {
+    type Output = Quat;
+    #[inline]
+    fn neg(self) -> Quat {
+        Quat::new(-self.w, -self.x, -self.y, -self.z)
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Vec2i {
     pub x: i32,
@@ -272,4 +485,75 @@ impl From<Vec3> for Vec2i {
             y: vec.y as i32,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    fn same_rotation(a: Quat, b: Quat) -> bool {
+        // q and -q are the same rotation
+        (a.dot(b).abs() - 1.0).abs() < 1e-12
+    }
+
+    #[test]
+    fn axis_angle_round_trip() {
+        let q = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 2.0), FRAC_PI_2);
+        let v = q.rotate(Vec3::new(1.0, 0.0, 0.0));
+        assert!((v - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        let (axis, angle) = q.to_axis_angle();
+        assert!((axis - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!((angle - FRAC_PI_2).abs() < 1e-12);
+        // The negated quaternion gives the same axis and angle
+        let (axis, angle) = (-q).to_axis_angle();
+        assert!((axis - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12 && (angle - FRAC_PI_2).abs() < 1e-12);
+        assert_eq!(Quat::IDENTITY.to_axis_angle().1, 0.0);
+    }
+
+    #[test]
+    fn euler_matches_vec3_rotated() {
+        let angles = Vec3::new(0.3, -1.1, 2.4);
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        let q = Quat::from_euler(angles);
+        assert!((q.rotate(v) - v.rotated(angles)).length() < 1e-12);
+        assert!((q.to_euler() - angles).length() < 1e-12);
+    }
+
+    #[test]
+    fn matrix_round_trip() {
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        // Angles near pi take the branches that don't divide by the trace
+        for (axis, angle) in [(Vec3::new(1.0, 2.0, 3.0), 0.7), (Vec3::new(1.0, 0.1, 0.0), 3.1), (Vec3::new(0.1, 1.0, 0.0), 3.1), (Vec3::new(0.0, 0.1, 1.0), PI)] {
+            let q = Quat::from_axis_angle(axis, angle);
+            assert!((q.to_mat4().transform_vector(v) - q.rotate(v)).length() < 1e-12);
+            assert!(same_rotation(Quat::from_mat4(&q.to_mat4()), q));
+        }
+    }
+
+    #[test]
+    fn multiplication_applies_the_right_side_first() {
+        let a = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), FRAC_PI_2);
+        let b = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), FRAC_PI_2);
+        let v = Vec3::new(0.0, 1.0, 0.0);
+        assert!(((a * b).rotate(v) - a.rotate(b.rotate(v))).length() < 1e-12);
+        assert!(same_rotation(a * a.inverse(), Quat::IDENTITY));
+    }
+
+    #[test]
+    fn slerp_has_constant_speed() {
+        let axis = Vec3::new(1.0, 1.0, 0.0);
+        let a = Quat::from_axis_angle(axis, 0.2);
+        let b = Quat::from_axis_angle(axis, 2.2);
+        for i in 0..=10 {
+            let t = i as f64 / 10.0;
+            assert!(same_rotation(a.slerp(b, t), Quat::from_axis_angle(axis, 0.2 + 2.0 * t)));
+        }
+        // Takes the shorter arc when the second rotation is given negated
+        assert!(same_rotation(a.slerp(-b, 0.5), Quat::from_axis_angle(axis, 1.2)));
+        // Nearly equal rotations fall back to nlerp without NaNs
+        let c = Quat::from_axis_angle(axis, 0.2 + 1e-9);
+        let halfway = a.slerp(c, 0.5);
+        assert!(halfway.w.is_finite() && (halfway.length() - 1.0).abs() < 1e-12);
+    }
+}